@@ -0,0 +1,161 @@
+use crate::random::*;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Scene-wide exponential height fog.
+///
+/// The extinction coefficient is `density * exp(-falloff * y)`, so the fog is
+/// thickest near the ground and thins out with altitude. Scattering is isotropic
+/// and `albedo` is the fraction of extinguished light that is scattered rather
+/// than absorbed.
+#[derive(Clone, Copy, Debug)]
+pub struct HeightFog {
+    pub density: f64,
+    pub falloff: f64,
+    pub albedo: Vec3,
+}
+
+impl HeightFog {
+    pub fn new(density: f64, falloff: f64, albedo: Vec3) -> HeightFog {
+        HeightFog {
+            density,
+            falloff,
+            albedo,
+        }
+    }
+
+    /// Optical depth along `r` between the ray origin and `r.at(t)`.
+    pub fn optical_depth(&self, r: &Ray, t: f64) -> f64 {
+        let (k, g) = self.integrand(r);
+        if g.abs() < 1.0e-9 {
+            k * t
+        } else if t.is_infinite() {
+            if g > 0.0 {
+                k / g
+            } else {
+                f64::INFINITY
+            }
+        } else {
+            k * (1.0 - (-g * t).exp()) / g
+        }
+    }
+
+    /// Fraction of light that survives the trip from `r.at(t)` back to the ray origin.
+    pub fn transmittance(&self, r: &Ray, t: f64) -> f64 {
+        (-self.optical_depth(r, t)).exp()
+    }
+
+    /// Samples a free-flight distance along `r` proportionally to the local
+    /// extinction times transmittance. Returns the ray parameter of the
+    /// scattering event, or `None` if the ray reaches `t_max` unscattered, which
+    /// happens with probability equal to the transmittance.
+    pub fn sample_distance(&self, r: &Ray, t_max: f64) -> Option<f64> {
+        if self.density <= 0.0 {
+            return None;
+        }
+
+        let target = -(1.0 - random_f64()).ln();
+        let (k, g) = self.integrand(r);
+        let t = if g.abs() < 1.0e-9 {
+            target / k
+        } else {
+            let remaining = 1.0 - target * g / k;
+            if remaining <= 0.0 {
+                return None;
+            }
+            -remaining.ln() / g
+        };
+
+        if t < t_max {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    // The optical depth integrand along a ray is `k * exp(-g * t)`.
+    fn integrand(&self, r: &Ray) -> (f64, f64) {
+        let k = self.density * r.direction.length() * (-self.falloff * r.origin.y).exp();
+        let g = self.falloff * r.direction.y;
+        (k, g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transmittance_thins_with_height() {
+        let fog = HeightFog::new(0.2, 0.5, Vec3::ones());
+        let horizontal = |y: f64| Ray::new(Vec3::new(0.0, y, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        for y in [0.0f64, 1.0, 4.0] {
+            let expected = (-0.2 * (-0.5 * y).exp() * 3.0).exp();
+            assert!((fog.transmittance(&horizontal(y), 3.0) - expected).abs() < 1.0e-12);
+        }
+        assert!(
+            fog.transmittance(&horizontal(0.0), 3.0) < fog.transmittance(&horizontal(4.0), 3.0)
+        );
+
+        // Climbing through the fog matches integrating the density along the way.
+        let up = Ray::new(Vec3::zeros(), Vec3::new(0.0, 2.0, 0.0));
+        let n = 10000;
+        let depth: f64 = (0..n)
+            .map(|i| {
+                let t = (i as f64 + 0.5) / n as f64 * 1.5;
+                0.2 * (-0.5 * up.at(t).y).exp() * 2.0 * 1.5 / n as f64
+            })
+            .sum();
+        assert!((fog.optical_depth(&up, 1.5) - depth).abs() < 1.0e-8);
+
+        // Straight up, the total is finite and some light gets through;
+        // straight down it never does.
+        assert!(fog.transmittance(&up, f64::INFINITY) > 0.0);
+        let down = Ray::new(Vec3::zeros(), Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(0.0, fog.transmittance(&down, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_free_flight_matches_transmittance() {
+        let fog = HeightFog::new(0.3, 0.4, Vec3::ones());
+        let r = Ray::new(
+            Vec3::new(0.0, 0.5, 0.0),
+            Vec3::unit_vector(Vec3::new(1.0, 0.3, 0.0)),
+        );
+        let (t_max, half) = (4.0, 1.5);
+        // Transmittance from integrating the density along the ray.
+        let transmittance = |t: f64| {
+            let n = 10000;
+            let depth: f64 = (0..n)
+                .map(|i| {
+                    0.3 * (-0.4 * r.at((i as f64 + 0.5) / n as f64 * t).y).exp() * t / n as f64
+                })
+                .sum();
+            (-depth).exp()
+        };
+        let n = 200_000;
+        let (mut escaped, mut before_half) = (0, 0);
+        for _ in 0..n {
+            match fog.sample_distance(&r, t_max) {
+                None => escaped += 1,
+                Some(t) => {
+                    assert!((0.0..t_max).contains(&t));
+                    if t < half {
+                        before_half += 1;
+                    }
+                }
+            }
+        }
+
+        // Escaping is as likely as the transmittance, and scattering before
+        // `half` as the light lost by then.
+        let escaped = escaped as f64 / n as f64;
+        let before_half = before_half as f64 / n as f64;
+        assert!((escaped - transmittance(t_max)).abs() < 0.01);
+        assert!((before_half - (1.0 - transmittance(half))).abs() < 0.01);
+
+        assert!(HeightFog::new(0.0, 0.4, Vec3::ones())
+            .sample_distance(&r, t_max)
+            .is_none());
+    }
+}
@@ -0,0 +1,325 @@
+use std::io;
+use std::sync::Arc;
+
+use crate::json::Json;
+use crate::material::*;
+use crate::ray::Ray;
+use crate::scene_file::{field, material, number, texture};
+use crate::sphere::HitRecord;
+use crate::subsurface::HomogeneousMedium;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+/// Step in texture coordinates for the finite differences of the height.
+const BUMP_DELTA: f64 = 1.0e-4;
+
+/// Any material with its shading normal tilted as if the surface were
+/// displaced along the normal by `height` (read from the first channel) times
+/// `scale`, without changing the geometry.
+pub struct BumpMapped<M: Material> {
+    pub base: M,
+    pub height: Arc<dyn Texture>,
+    pub scale: f64,
+}
+
+impl<M: Material> BumpMapped<M> {
+    pub fn new(base: M, height: Arc<dyn Texture>, scale: f64) -> BumpMapped<M> {
+        BumpMapped {
+            base,
+            height,
+            scale,
+        }
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let n = rec.outward_normal();
+        let height = |u: f64, v: f64, p: Vec3| self.scale * self.height.value(u, v, p).x;
+        let h = height(rec.u, rec.v, rec.p);
+        let dhdu =
+            (height(rec.u + BUMP_DELTA, rec.v, rec.p + BUMP_DELTA * rec.dpdu) - h) / BUMP_DELTA;
+        let dhdv =
+            (height(rec.u, rec.v + BUMP_DELTA, rec.p + BUMP_DELTA * rec.dpdv) - h) / BUMP_DELTA;
+
+        // Tangents of the displaced surface, starting from the shading normal's
+        // tangent plane so smooth shaded meshes stay smooth.
+        let dpdu = rec.dpdu - Vec3::dot(rec.dpdu, n) * n + dhdu * n;
+        let dpdv = rec.dpdv - Vec3::dot(rec.dpdv, n) * n + dhdv * n;
+        let bumped = Vec3::cross(dpdu, dpdv);
+        if bumped.near_zero() {
+            return n;
+        }
+        let bumped = Vec3::unit_vector(bumped);
+        if Vec3::dot(bumped, n) < 0.0 {
+            -bumped
+        } else {
+            bumped
+        }
+    }
+}
+
+impl BumpMapped<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<BumpMapped<Box<dyn Material>>> {
+        Ok(BumpMapped::new(
+            material(field(json, "base")?)?,
+            texture(field(json, "height")?)?,
+            number(json, "scale")?,
+        ))
+    }
+}
+
+/// Any material with its shading normal read from a tangent space normal map,
+/// where each channel maps [0, 1] to [-1, 1] along the tangent, the bitangent
+/// and the normal.
+pub struct NormalMapped<M: Material> {
+    pub base: M,
+    pub normal_map: Arc<dyn Texture>,
+}
+
+impl<M: Material> NormalMapped<M> {
+    pub fn new(base: M, normal_map: Arc<dyn Texture>) -> NormalMapped<M> {
+        NormalMapped { base, normal_map }
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let n = rec.outward_normal();
+        let tangent = rec.dpdu - Vec3::dot(rec.dpdu, n) * n;
+        if tangent.near_zero() {
+            return n;
+        }
+        let tangent = Vec3::unit_vector(tangent);
+        // Mirrored texture coordinates flip the bitangent.
+        let bitangent = if Vec3::dot(Vec3::cross(n, tangent), rec.dpdv) < 0.0 {
+            -Vec3::cross(n, tangent)
+        } else {
+            Vec3::cross(n, tangent)
+        };
+
+        let m = 2.0 * self.normal_map.value(rec.u, rec.v, rec.p) - Vec3::ones();
+        Vec3::unit_vector(m.x * tangent + m.y * bitangent + m.z.max(1.0e-3) * n)
+    }
+}
+
+impl NormalMapped<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<NormalMapped<Box<dyn Material>>> {
+        Ok(NormalMapped::new(
+            material(field(json, "base")?)?,
+            texture(field(json, "normal_map")?)?,
+        ))
+    }
+}
+
+/// The same hit with `normal` (pointing out of the surface) as its shading normal.
+fn with_normal<'a>(rec: &HitRecord<'a>, normal: Vec3) -> HitRecord<'a> {
+    HitRecord {
+        normal: if rec.front_facing { normal } else { -normal },
+        ..*rec
+    }
+}
+
+impl<M: Material> Material for BumpMapped<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.base
+            .scatter(r_in, &with_normal(rec, self.shading_normal(rec)))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.base
+            .eval(r_in, &with_normal(rec, self.shading_normal(rec)), direction)
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+
+    fn medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.medium()
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f64 {
+        self.base.alpha(rec)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "bump_mapped".into()),
+            ("base", self.base.to_json()?),
+            ("height", self.height.to_json()?),
+            ("scale", self.scale.into()),
+        ]))
+    }
+}
+
+impl<M: Material> Material for NormalMapped<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.base
+            .scatter(r_in, &with_normal(rec, self.shading_normal(rec)))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.base
+            .eval(r_in, &with_normal(rec, self.shading_normal(rec)), direction)
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+
+    fn medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.medium()
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f64 {
+        self.base.alpha(rec)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "normal_mapped".into()),
+            ("base", self.base.to_json()?),
+            ("normal_map", self.normal_map.to_json()?),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    use crate::mesh::Mesh;
+    use crate::sphere::{Hittable, Sphere};
+    use crate::texture::{GradientTexture, SolidColor};
+
+    /// Angle between the bumped normal and the surface's own at the hit of `r`
+    /// on `object`, and how far the bumped normal leans along `dpdv`.
+    fn tilt<O: Hittable>(object: &O, bumped: &BumpMapped<Lambertian>, r: Ray) -> (f64, f64, f64) {
+        let hit = object.hit(r, 0.001, f64::INFINITY).unwrap();
+        let normal = bumped.shading_normal(&hit);
+        let angle = Vec3::dot(normal, hit.outward_normal())
+            .clamp(-1.0, 1.0)
+            .acos();
+        (
+            angle,
+            Vec3::dot(normal, Vec3::unit_vector(hit.dpdv)),
+            hit.dpdv.length(),
+        )
+    }
+
+    #[test]
+    fn test_bump_tilts_normal_by_height_slope() {
+        let flat = || {
+            BumpMapped::new(
+                Lambertian::new(Vec3::ones()),
+                Arc::new(SolidColor::scalar(0.7)),
+                0.5,
+            )
+        };
+        let ramp = || {
+            let bottom = Arc::new(SolidColor::scalar(0.0));
+            let top = Arc::new(SolidColor::scalar(1.0));
+            BumpMapped::new(
+                Lambertian::new(Vec3::ones()),
+                Arc::new(GradientTexture::new(bottom, top)),
+                0.5,
+            )
+        };
+
+        let sphere = Sphere::new(Vec3::zeros(), 1.0, Lambertian::new(Vec3::ones()));
+        let triangle = Mesh::new(
+            vec![
+                Vec3::zeros(),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+            ],
+            Vec::new(),
+            vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            vec![[0, 1, 2]],
+            Lambertian::new(Vec3::ones()),
+        );
+        let sphere_ray = Ray::new(Vec3::new(0.3, 0.2, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let mesh_ray = Ray::new(Vec3::new(0.5, 0.5, 3.0), Vec3::new(0.0, 0.0, -1.0));
+
+        // A constant height moves the whole surface, which doesn't change its normal.
+        assert!(tilt(&sphere, &flat(), sphere_ray).0 < 1.0e-6);
+        assert!(tilt(&triangle, &flat(), mesh_ray).0 < 1.0e-6);
+
+        // Height rising by 0.5 over v leans the normal back against dpdv, by
+        // the angle of that slope over the length v spans.
+        for (angle, lean, length) in [
+            tilt(&sphere, &ramp(), sphere_ray),
+            tilt(&triangle, &ramp(), mesh_ray),
+        ] {
+            assert!((angle - (0.5 / length).atan()).abs() < 1.0e-6);
+            assert!((lean + angle.sin()).abs() < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn test_normal_map_tilts_normal_in_tangent_space() {
+        let (s, c) = (30.0_f64.to_radians().sin(), 30.0_f64.to_radians().cos());
+        let mapped = |m: Vec3| {
+            NormalMapped::new(
+                Lambertian::new(Vec3::new(0.8, 0.8, 0.8)),
+                Arc::new(SolidColor::new(0.5 * m + 0.5 * Vec3::ones())),
+            )
+        };
+        // The second triangle runs v the other way, which flips the bitangent.
+        let triangle = |v: f64| {
+            Mesh::new(
+                vec![
+                    Vec3::zeros(),
+                    Vec3::new(2.0, 0.0, 0.0),
+                    Vec3::new(0.0, 2.0, 0.0),
+                ],
+                Vec::new(),
+                vec![(0.0, 0.0), (1.0, 0.0), (0.0, v)],
+                vec![[0, 1, 2]],
+                Lambertian::new(Vec3::ones()),
+            )
+        };
+        let r = Ray::new(Vec3::new(0.5, 0.5, 3.0), Vec3::new(0.0, 0.0, -1.0));
+
+        for (v, m, expected) in [
+            (1.0, Vec3::new(s, 0.0, c), Vec3::new(s, 0.0, c)),
+            (1.0, Vec3::new(0.0, s, c), Vec3::new(0.0, s, c)),
+            (-1.0, Vec3::new(0.0, s, c), Vec3::new(0.0, -s, c)),
+        ] {
+            let mesh = triangle(v);
+            let hit = mesh.hit(r, 0.001, f64::INFINITY).unwrap();
+            let normal_mapped = mapped(m);
+            assert!((normal_mapped.shading_normal(&hit) - expected).length() < 1.0e-9);
+
+            // The base lobe turns with the normal and keeps all its energy.
+            let n = 200;
+            let mut integral = Vec3::zeros();
+            for i in 0..n {
+                let theta = (i as f64 + 0.5) / n as f64 * PI;
+                for j in 0..2 * n {
+                    let phi = (j as f64 + 0.5) / (2 * n) as f64 * 2.0 * PI;
+                    let direction = Vec3::new(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    );
+                    integral += (PI / n as f64)
+                        * (PI / n as f64)
+                        * theta.sin()
+                        * normal_mapped.eval(&r, &hit, direction);
+                }
+            }
+            assert!((integral - Vec3::new(0.8, 0.8, 0.8)).length() < 1.0e-3);
+
+            let samples = 10_000;
+            let mut towards_normal = 0.0;
+            for _ in 0..samples {
+                let scatter = normal_mapped.scatter(&r, &hit).unwrap();
+                assert!((scatter.attenuation - Vec3::new(0.8, 0.8, 0.8)).length() < 1.0e-9);
+                towards_normal +=
+                    Vec3::dot(Vec3::unit_vector(scatter.scattered.direction), expected)
+                        / samples as f64;
+            }
+            // The mean cosine of a cosine-weighted lobe is 2/3.
+            assert!((towards_normal - 2.0 / 3.0).abs() < 0.02);
+        }
+    }
+}
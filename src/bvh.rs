@@ -0,0 +1,227 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Box containing nothing, to start a union from.
+    pub fn empty() -> Aabb {
+        Aabb::new(
+            f64::INFINITY * Vec3::ones(),
+            f64::NEG_INFINITY * Vec3::ones(),
+        )
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points
+            .iter()
+            .fold(Aabb::empty(), |aabb, p| aabb.union(&Aabb::new(*p, *p)))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vec3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            Vec3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    /// The box grown by `delta` on every side.
+    pub fn padded(&self, delta: f64) -> Aabb {
+        Aabb::new(
+            self.min - delta * Vec3::ones(),
+            self.max + delta * Vec3::ones(),
+        )
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// Slab test; whether `r` passes through the box between `t_min` and `t_max`.
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.clip(r, t_min, t_max).is_some()
+    }
+
+    /// The part of `t_min` to `t_max` where `r` is inside the box, if any.
+    pub fn clip(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let axes = [
+            (r.origin.x, r.direction.x, self.min.x, self.max.x),
+            (r.origin.y, r.direction.y, self.min.y, self.max.y),
+            (r.origin.z, r.direction.z, self.min.z, self.max.z),
+        ];
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for (origin, direction, min, max) in axes {
+            let inv_d = 1.0 / direction;
+            let (t0, t1) = ((min - origin) * inv_d, (max - origin) * inv_d);
+            let (t0, t1) = if inv_d < 0.0 { (t1, t0) } else { (t0, t1) };
+            // Written so a NaN from a ray lying in the slab's plane keeps the old bounds.
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+/// Primitives per leaf before a node is split.
+const MAX_LEAF_SIZE: usize = 4;
+
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+/// Bounding volume hierarchy over a set of primitives given by their boxes.
+/// It only stores indices, so any list of things with bounds can use it.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(boxes: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            indices: (0..boxes.len()).collect(),
+        };
+        if !boxes.is_empty() {
+            bvh.build(boxes, 0, boxes.len());
+        }
+        bvh
+    }
+
+    // Splits the primitives at the median along the longest axis of their centroids.
+    fn build(&mut self, boxes: &[Aabb], start: usize, end: usize) -> usize {
+        let indices = &mut self.indices[start..end];
+        let bounds = indices
+            .iter()
+            .fold(Aabb::empty(), |aabb, &i| aabb.union(&boxes[i]));
+
+        let node = self.nodes.len();
+        if indices.len() <= MAX_LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf {
+                bounds,
+                start,
+                count: end - start,
+            });
+            return node;
+        }
+
+        let centroids = indices.iter().fold(Aabb::empty(), |aabb, &i| {
+            let c = boxes[i].centroid();
+            aabb.union(&Aabb::new(c, c))
+        });
+        let extent = centroids.max - centroids.min;
+        let axis = |aabb: &Aabb| {
+            let c = aabb.centroid();
+            if extent.x >= extent.y && extent.x >= extent.z {
+                c.x
+            } else if extent.y >= extent.z {
+                c.y
+            } else {
+                c.z
+            }
+        };
+        let mid = indices.len() / 2;
+        indices.select_nth_unstable_by(mid, |&a, &b| axis(&boxes[a]).total_cmp(&axis(&boxes[b])));
+
+        // Children are filled in once they are built.
+        self.nodes.push(BvhNode::Leaf {
+            bounds,
+            start,
+            count: 0,
+        });
+        let left = self.build(boxes, start, start + mid);
+        let right = self.build(boxes, start + mid, end);
+        self.nodes[node] = BvhNode::Interior {
+            bounds,
+            left,
+            right,
+        };
+        node
+    }
+
+    /// Box around every primitive, none if there are none.
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self.nodes.first()? {
+            BvhNode::Leaf { bounds, .. } | BvhNode::Interior { bounds, .. } => Some(*bounds),
+        }
+    }
+
+    /// Calls `hit` with the index of every primitive whose box `r` passes
+    /// through before the closest hit so far. `hit` returns the distance of
+    /// its hit, if any, which then limits the rest of the search.
+    pub fn traverse(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut hit: impl FnMut(usize, f64) -> Option<f64>,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut closest = t_max;
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            match &self.nodes[node] {
+                BvhNode::Leaf {
+                    bounds,
+                    start,
+                    count,
+                } => {
+                    if !bounds.hit(r, t_min, closest) {
+                        continue;
+                    }
+                    for &i in &self.indices[*start..*start + *count] {
+                        if let Some(t) = hit(i, closest) {
+                            closest = t;
+                        }
+                    }
+                }
+                BvhNode::Interior {
+                    bounds,
+                    left,
+                    right,
+                } => {
+                    if bounds.hit(r, t_min, closest) {
+                        stack.push(*right);
+                        stack.push(*left);
+                    }
+                }
+            }
+        }
+    }
+}
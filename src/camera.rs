@@ -1,4 +1,8 @@
+use std::io;
+
+use crate::json::Json;
 use crate::ray::Ray;
+use crate::scene_file::{number, vec3};
 use crate::vec3::Vec3;
 
 #[derive(Clone, Copy, Debug)]
@@ -48,6 +52,31 @@ impl Camera {
         }
     }
 
+    /// The camera in the scene file format, as the vectors it keeps.
+    pub fn to_json(self) -> Json {
+        Json::object([
+            ("origin", self.origin.into()),
+            ("lower_left_corner", self.lower_left_corner.into()),
+            ("horizontal", self.horizontal.into()),
+            ("vertical", self.vertical.into()),
+            ("u", self.u.into()),
+            ("v", self.v.into()),
+            ("lens_radius", self.lens_radius.into()),
+        ])
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Camera> {
+        Ok(Camera {
+            origin: vec3(json, "origin")?,
+            lower_left_corner: vec3(json, "lower_left_corner")?,
+            horizontal: vec3(json, "horizontal")?,
+            vertical: vec3(json, "vertical")?,
+            u: vec3(json, "u")?,
+            v: vec3(json, "v")?,
+            lens_radius: number(json, "lens_radius")?,
+        })
+    }
+
     pub fn get_ray(self, s: f64, t: f64) -> Ray {
         let rd = self.lens_radius * Vec3::random_in_unit_disk();
         let offset = rd.x * self.u + rd.y * self.v;
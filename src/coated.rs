@@ -0,0 +1,312 @@
+use std::io;
+
+use crate::json::Json;
+use crate::material::*;
+use crate::microfacet::*;
+use crate::onb::Onb;
+use crate::random::*;
+use crate::ray::Ray;
+use crate::scene_file::{distribution, field, material, number, vec3};
+use crate::sphere::HitRecord;
+use crate::vec3::Vec3;
+
+/// Bounces between the base and the underside of the coat before a path gives up.
+const MAX_INTERNAL_BOUNCES: usize = 16;
+
+/// Any base material under a dielectric clear coat, like car paint or
+/// lacquered wood.
+///
+/// Light is split at the coat by its Fresnel reflectance. The transmitted part
+/// is absorbed on its way through the coat, scatters off the base, and may be
+/// reflected back down at the underside of the coat several times before it
+/// leaves. A rough coat only blurs the reflection off its top; light is
+/// refracted through it as if it were smooth.
+pub struct Coated<M: Material> {
+    pub base: M,
+    pub index_of_refraction: f64,
+    pub distribution: TrowbridgeReitz,
+    /// Fraction of light left after crossing one unit of coat thickness head-on.
+    pub coat_color: Vec3,
+    pub thickness: f64,
+}
+
+impl<M: Material> Coated<M> {
+    pub fn new(
+        base: M,
+        index_of_refraction: f64,
+        roughness: f64,
+        coat_color: Vec3,
+        thickness: f64,
+    ) -> Coated<M> {
+        Coated {
+            base,
+            index_of_refraction,
+            distribution: TrowbridgeReitz::from_roughness(roughness, roughness),
+            coat_color,
+            thickness,
+        }
+    }
+
+    // Transmittance through the coat along the local direction `w`.
+    fn absorption(&self, w: Vec3) -> Vec3 {
+        let distance = self.thickness / w.z.abs().max(1.0e-4);
+        Vec3::new(
+            self.coat_color.x.powf(distance),
+            self.coat_color.y.powf(distance),
+            self.coat_color.z.powf(distance),
+        )
+    }
+
+    // The same hit, seen by the base material underneath the coat.
+    fn base_record<'a>(&'a self, rec: &HitRecord) -> HitRecord<'a> {
+        HitRecord {
+            mat: &self.base,
+            front_facing: true,
+            ..*rec
+        }
+    }
+}
+
+impl Coated<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Coated<Box<dyn Material>>> {
+        Ok(Coated {
+            base: material(field(json, "base")?)?,
+            index_of_refraction: number(json, "index_of_refraction")?,
+            distribution: distribution(json)?,
+            coat_color: vec3(json, "coat_color")?,
+            thickness: number(json, "thickness")?,
+        })
+    }
+}
+
+impl<M: Material> Material for Coated<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let eta = self.index_of_refraction;
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        // Reflection off the top of the coat, picked with its Fresnel probability.
+        if random_f64() < fresnel_dielectric(wo.z, eta) {
+            if self.distribution.is_smooth() {
+                return Some(ScatterRecord {
+                    attenuation: Vec3::ones(),
+                    scattered: Ray::new(rec.p, uvw.local(-wo.x, -wo.y, wo.z)),
+                    is_specular: true,
+                });
+            }
+
+            let wm = self.distribution.sample_wm(wo);
+            let wi = Vec3::reflect(-wo, wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            // The macro surface Fresnel used to pick this lobe stands in for the microfacet one.
+            let weight = fresnel_dielectric(Vec3::dot(wo, wm), eta) / fresnel_dielectric(wo.z, eta)
+                * self.distribution.g(wo, wi)
+                / self.distribution.g1(wo);
+            return Some(ScatterRecord {
+                attenuation: weight * Vec3::ones(),
+                scattered: Ray::new(rec.p, uvw.local_vec(wi)),
+                is_specular: false,
+            });
+        }
+
+        let base_rec = self.base_record(rec);
+        let mut attenuation = Vec3::ones();
+        let mut w = refract(wo, Vec3::new(0.0, 0.0, 1.0), eta)?;
+
+        for _ in 0..MAX_INTERNAL_BOUNCES {
+            // Down through the coat, off the base, and back up to the coat.
+            attenuation = attenuation * self.absorption(w);
+            let base_scatter = self
+                .base
+                .scatter(&Ray::new(rec.p, uvw.local_vec(w)), &base_rec)?;
+            attenuation = attenuation * base_scatter.attenuation;
+            // `eval` picks up light after any bounce off the base, so only
+            // the kind of the last one matters.
+            let is_specular = base_scatter.is_specular;
+
+            w = Vec3::unit_vector(uvw.to_local(base_scatter.scattered.direction));
+            if w.z <= 0.0 {
+                // The base let the light through, so it leaves below the surface.
+                return Some(ScatterRecord {
+                    attenuation,
+                    scattered: Ray::new(rec.p, uvw.local_vec(w)),
+                    is_specular,
+                });
+            }
+            attenuation = attenuation * self.absorption(w);
+
+            if random_f64() < fresnel_dielectric(w.z, 1.0 / eta) {
+                w = Vec3::new(w.x, w.y, -w.z);
+                continue;
+            }
+
+            let wi = refract(w, Vec3::new(0.0, 0.0, 1.0), 1.0 / eta)?;
+            return Some(ScatterRecord {
+                attenuation,
+                scattered: Ray::new(rec.p, uvw.local_vec(wi)),
+                is_specular,
+            });
+        }
+
+        None
+    }
+
+    /// Covers the rough coat reflection and light that scattered off the base
+    /// any number of times. Paths inside the coat have no closed form, so one
+    /// is followed down as `scatter` would and every bounce off the base is
+    /// joined to `direction`, which is right on average.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let eta = self.index_of_refraction;
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = uvw.to_local(Vec3::unit_vector(direction));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::zeros();
+        }
+
+        let mut f_cos = Vec3::zeros();
+        if !self.distribution.is_smooth() {
+            let wm = Vec3::unit_vector(wo + wi);
+            let fresnel = fresnel_dielectric(Vec3::dot(wo, wm), eta);
+            let specular = self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * wo.z);
+            f_cos += fresnel * specular * Vec3::ones();
+        }
+
+        // Both directions refracted to just above the base.
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let (mut w, wi_inside) = match (refract(wo, n, eta), refract(wi, n, eta)) {
+            (Some(w), Some(wi_inside)) => (w, -wi_inside),
+            _ => return f_cos,
+        };
+
+        // Light leaving the base towards `wi_inside` gets out along `wi`,
+        // with the base's cosine swapped for the one outside the coat and the
+        // solid angle compression through the interface accounted for.
+        let exit = (1.0 - fresnel_dielectric(wi.z, eta)) * wi.z / (wi_inside.z * eta * eta)
+            * self.absorption(wi_inside);
+
+        let base_rec = self.base_record(rec);
+        let mut throughput = (1.0 - fresnel_dielectric(wo.z, eta)) * Vec3::ones();
+        for _ in 0..MAX_INTERNAL_BOUNCES {
+            throughput = throughput * self.absorption(w);
+            let base_r_in = Ray::new(rec.p, uvw.local_vec(w));
+            let base_f_cos = self
+                .base
+                .eval(&base_r_in, &base_rec, uvw.local_vec(wi_inside));
+            f_cos += throughput * exit * base_f_cos;
+
+            // Light that doesn't leave is reflected back down by the coat.
+            let base_scatter = match self.base.scatter(&base_r_in, &base_rec) {
+                Some(base_scatter) => base_scatter,
+                None => break,
+            };
+            w = Vec3::unit_vector(uvw.to_local(base_scatter.scattered.direction));
+            if w.z <= 0.0 {
+                break;
+            }
+            throughput = fresnel_dielectric(w.z, 1.0 / eta)
+                * throughput
+                * base_scatter.attenuation
+                * self.absorption(w);
+            w = Vec3::new(w.x, w.y, -w.z);
+        }
+
+        f_cos
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "coated".into()),
+            ("base", self.base.to_json()?),
+            ("index_of_refraction", self.index_of_refraction.into()),
+            (
+                "alpha",
+                Json::Array(vec![
+                    self.distribution.alpha_x.into(),
+                    self.distribution.alpha_y.into(),
+                ]),
+            ),
+            ("coat_color", self.coat_color.into()),
+            ("thickness", self.thickness.into()),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::sphere::{Hittable, Sphere};
+
+    #[test]
+    fn test_coated_energy_bounded() {
+        for roughness in [0.0, 0.3] {
+            let coated = Coated::new(
+                Lambertian::new(Vec3::ones()),
+                1.5,
+                roughness,
+                Vec3::ones(),
+                0.0,
+            );
+            let sphere = Sphere::new(Vec3::zeros(), 1.0, coated);
+            let normal = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+            let hit = sphere.hit(normal, 0.001, f64::INFINITY).unwrap();
+
+            for cos_theta in [1.0f64, 0.5, 0.2] {
+                let r_in = Ray::new(
+                    hit.p,
+                    Vec3::new(-(1.0 - cos_theta * cos_theta).sqrt(), 0.0, -cos_theta),
+                );
+
+                // All light a white base under a clear coat sends back, counting
+                // every bounce inside the coat.
+                let n = 20_000;
+                let scatters: Vec<_> = (0..n)
+                    .filter_map(|_| sphere.material.scatter(&r_in, &hit))
+                    .collect();
+                let total = scatters
+                    .iter()
+                    .fold(Vec3::zeros(), |sum, scatter| sum + scatter.attenuation);
+                assert!(total.y / n as f64 <= 1.0 + 2.0e-2);
+                assert!(total.y / n as f64 > 0.8);
+
+                // The part `eval` covers, by quadrature over the hemisphere.
+                let m = 200;
+                let mut integral = Vec3::zeros();
+                for i in 0..m {
+                    let theta = (i as f64 + 0.5) / m as f64 * PI / 2.0;
+                    for j in 0..m {
+                        let phi = (j as f64 + 0.5) / m as f64 * 2.0 * PI;
+                        let wi = Vec3::new(
+                            theta.sin() * phi.cos(),
+                            theta.sin() * phi.sin(),
+                            theta.cos(),
+                        );
+                        integral += theta.sin() * sphere.material.eval(&r_in, &hit, wi);
+                    }
+                }
+                integral = (PI / 2.0 / m as f64) * (2.0 * PI / m as f64) * integral;
+                assert!(integral.y <= 1.0 + 2.0e-2);
+
+                // Which is everything `scatter` doesn't mark specular, so
+                // lights are seen through every bounce inside the coat. Only a
+                // smooth coat reflects anything specularly.
+                if roughness > 0.0 {
+                    assert!(scatters.iter().all(|scatter| !scatter.is_specular));
+                }
+                let covered = scatters
+                    .iter()
+                    .filter(|scatter| !scatter.is_specular)
+                    .fold(Vec3::zeros(), |sum, scatter| sum + scatter.attenuation)
+                    / n as f64;
+                assert!((integral.y - covered.y).abs() < 0.03);
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::io;
+
+use crate::bvh::Aabb;
+use crate::json::Json;
+use crate::ray::Ray;
+use crate::scene_file::{field, hittable, invalid, string};
+use crate::sphere::*;
+
+/// How the two sides of a `Csg` are combined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOperation {
+    /// Inside either side.
+    Union,
+    /// Inside both sides.
+    Intersection,
+    /// Inside the first side but not the second.
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_a || in_b,
+            CsgOperation::Intersection => in_a && in_b,
+            CsgOperation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+/// Boolean combination of two closed objects, like a lens cut from two
+/// spheres or a die with its pips drilled out. Either side can be another
+/// `Csg`. Each part of the surface keeps the material of the object it came
+/// from.
+pub struct Csg<A: Hittable, B: Hittable> {
+    pub a: A,
+    pub b: B,
+    pub operation: CsgOperation,
+    bounds: Option<Aabb>,
+}
+
+impl<A: Hittable, B: Hittable> Csg<A, B> {
+    pub fn new(a: A, b: B, operation: CsgOperation) -> Csg<A, B> {
+        // The result lies inside the first side except for a union, and
+        // inside either side's box for an intersection.
+        let bounds = match operation {
+            CsgOperation::Union => a
+                .bounding_box()
+                .zip(b.bounding_box())
+                .map(|(a, b)| a.union(&b)),
+            CsgOperation::Intersection => a.bounding_box().or_else(|| b.bounding_box()),
+            CsgOperation::Difference => a.bounding_box(),
+        };
+        Csg {
+            a,
+            b,
+            operation,
+            bounds,
+        }
+    }
+
+    pub fn union(a: A, b: B) -> Csg<A, B> {
+        Csg::new(a, b, CsgOperation::Union)
+    }
+
+    pub fn intersection(a: A, b: B) -> Csg<A, B> {
+        Csg::new(a, b, CsgOperation::Intersection)
+    }
+
+    pub fn difference(a: A, b: B) -> Csg<A, B> {
+        Csg::new(a, b, CsgOperation::Difference)
+    }
+}
+
+impl Csg<Box<dyn Hittable>, Box<dyn Hittable>> {
+    pub fn from_json(json: &Json) -> io::Result<Csg<Box<dyn Hittable>, Box<dyn Hittable>>> {
+        let operation = match string(json, "operation")? {
+            "union" => CsgOperation::Union,
+            "intersection" => CsgOperation::Intersection,
+            "difference" => CsgOperation::Difference,
+            other => return Err(invalid(format!("unknown CSG operation \"{}\"", other))),
+        };
+        Ok(Csg::new(
+            hittable(field(json, "a")?)?,
+            hittable(field(json, "b")?)?,
+            operation,
+        ))
+    }
+}
+
+impl<A: Hittable, B: Hittable> Hittable for Csg<A, B> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        if self
+            .bounds
+            .is_some_and(|bounds| !bounds.hit(&r, t_min, t_max))
+        {
+            return None;
+        }
+        self.spans(r)
+            .into_iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|hit| hit.t >= t_min && hit.t <= t_max)
+    }
+
+    /// Sweeps along the ray through the boundaries of both sides in order,
+    /// keeping track of which sides it is in.
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        if self
+            .bounds
+            .is_some_and(|bounds| !bounds.hit(&r, f64::NEG_INFINITY, f64::INFINITY))
+        {
+            return Vec::new();
+        }
+        let mut boundaries: Vec<(HitRecord, bool, bool)> = Vec::new();
+        for (spans, is_a) in [(self.a.spans(r), true), (self.b.spans(r), false)] {
+            for span in spans {
+                boundaries.push((span.enter, is_a, true));
+                boundaries.push((span.exit, is_a, false));
+            }
+        }
+        boundaries.sort_by(|x, y| x.0.t.total_cmp(&y.0.t));
+
+        let mut spans = Vec::new();
+        let (mut in_a, mut in_b) = (false, false);
+        let mut enter: Option<HitRecord> = None;
+        for (hit, is_a, entering) in boundaries {
+            let was_inside = self.operation.inside(in_a, in_b);
+            if is_a {
+                in_a = entering;
+            } else {
+                in_b = entering;
+            }
+            let inside = self.operation.inside(in_a, in_b);
+
+            // The boundary's own facing is wrong where the ray leaves the
+            // result by entering the subtracted side, and the other way around.
+            if inside && !was_inside {
+                enter = Some(HitRecord {
+                    front_facing: true,
+                    ..hit
+                });
+            } else if was_inside && !inside {
+                if let Some(entered) = enter.take() {
+                    spans.push(Span {
+                        enter: entered,
+                        exit: HitRecord {
+                            front_facing: false,
+                            ..hit
+                        },
+                    });
+                }
+            }
+        }
+        spans
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        let operation = match self.operation {
+            CsgOperation::Union => "union",
+            CsgOperation::Intersection => "intersection",
+            CsgOperation::Difference => "difference",
+        };
+        Some(Json::object([
+            ("type", "csg".into()),
+            ("operation", operation.into()),
+            ("a", self.a.to_json()?),
+            ("b", self.b.to_json()?),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cuboid::Cuboid;
+    use crate::material::Lambertian;
+    use crate::vec3::Vec3;
+    use crate::Sphere;
+
+    fn sphere(x: f64) -> Sphere<Lambertian> {
+        Sphere::new(Vec3::new(x, 0.0, 0.0), 1.0, Lambertian::new(Vec3::ones()))
+    }
+
+    #[test]
+    fn test_spans_along_x_axis() {
+        // Unit spheres around x = 0 and x = 1 overlap between 0 and 1.
+        let r = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let bounds = |spans: Vec<Span>| -> Vec<(f64, f64)> {
+            spans
+                .iter()
+                .map(|s| (s.enter.t - 5.0, s.exit.t - 5.0))
+                .collect()
+        };
+
+        assert_eq!(
+            bounds(Csg::union(sphere(0.0), sphere(1.0)).spans(r)),
+            vec![(-1.0, 2.0)]
+        );
+        assert_eq!(
+            bounds(Csg::intersection(sphere(0.0), sphere(1.0)).spans(r)),
+            vec![(0.0, 1.0)]
+        );
+        assert_eq!(
+            bounds(Csg::difference(sphere(0.0), sphere(1.0)).spans(r)),
+            vec![(-1.0, 0.0)]
+        );
+
+        let ring = Csg::difference(
+            sphere(0.0),
+            Sphere::new(Vec3::zeros(), 0.5, Lambertian::new(Vec3::ones())),
+        );
+        assert_eq!(bounds(ring.spans(r)), vec![(-1.0, -0.5), (0.5, 1.0)]);
+
+        // From inside the hole the ray enters the ring through the hole's
+        // surface, which faces into the hole.
+        let hit = ring.hit(r, 4.6, f64::INFINITY).unwrap();
+        assert!(hit.front_facing);
+        assert_eq!(hit.normal.x, -1.0);
+    }
+
+    /// A box found through its hits alone, with the default spans.
+    struct HitsOnly(Cuboid<Lambertian>);
+
+    impl Hittable for HitsOnly {
+        fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+            self.0.hit(r, t_min, t_max)
+        }
+    }
+
+    #[test]
+    fn test_default_spans_far_from_the_origin() {
+        let corner = Vec3::new(1.0e9, -1.0, -1.0);
+        let far = HitsOnly(Cuboid::new(
+            corner,
+            corner + 2.0 * Vec3::ones(),
+            Lambertian::new(Vec3::ones()),
+        ));
+        let spans = far.spans(Ray::new(Vec3::zeros(), Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].exit.t - spans[0].enter.t - 2.0).abs() < 1.0e-6);
+
+        let missed = Csg::union(far, sphere(0.0));
+        assert!(missed
+            .hit(
+                Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+                0.0,
+                f64::INFINITY
+            )
+            .is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use std::io;
+
+use crate::bvh::Aabb;
+use crate::json::Json;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_file::{field, material, vec3};
+use crate::sphere::*;
+use crate::vec3::Vec3;
+
+/// Axis-aligned box between two corners. Each face is mapped to the whole
+/// [0, 1] texture square.
+pub struct Cuboid<M: Material> {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub material: M,
+}
+
+impl<M: Material> Cuboid<M> {
+    pub fn new(a: Vec3, b: Vec3, material: M) -> Cuboid<M> {
+        Cuboid {
+            min: Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+            material,
+        }
+    }
+
+    /// Distances along `r` where the line through it enters and leaves the
+    /// box, with the axis of the face crossed at each.
+    fn slabs(&self, r: Ray) -> Option<((f64, usize), (f64, usize))> {
+        let mut near = (f64::NEG_INFINITY, 0);
+        let mut far = (f64::INFINITY, 0);
+        for axis in 0..3 {
+            let (origin, direction) = (r.origin[axis], r.direction[axis]);
+            let (min, max) = (self.min[axis], self.max[axis]);
+            if direction == 0.0 {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+            let (t0, t1) = ((min - origin) / direction, (max - origin) / direction);
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if t0 > near.0 {
+                near = (t0, axis);
+            }
+            if t1 < far.0 {
+                far = (t1, axis);
+            }
+        }
+        if near.0 > far.0 {
+            return None;
+        }
+        Some((near, far))
+    }
+
+    fn record(&self, r: Ray, t: f64, axis: usize) -> HitRecord<'_> {
+        let p = r.at(t);
+        let center = 0.5 * (self.min + self.max);
+        let mut outward_normal = Vec3::zeros();
+        outward_normal[axis] = if p[axis] > center[axis] { 1.0 } else { -1.0 };
+
+        // The other two axes in order, scaled to the size of the face.
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let size = self.max - self.min;
+        let mut dpdu = Vec3::zeros();
+        let mut dpdv = Vec3::zeros();
+        dpdu[a] = size[a];
+        dpdv[b] = size[b];
+
+        let mut hit_record = HitRecord {
+            p,
+            normal: outward_normal,
+            mat: &self.material,
+            t,
+            u: (p[a] - self.min[a]) / size[a],
+            v: (p[b] - self.min[b]) / size[b],
+            dpdu,
+            dpdv,
+            color: Vec3::ones(),
+            front_facing: false,
+        };
+        hit_record.set_face_normal(r, outward_normal);
+        hit_record
+    }
+}
+
+impl Cuboid<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Cuboid<Box<dyn Material>>> {
+        Ok(Cuboid::new(
+            vec3(json, "min")?,
+            vec3(json, "max")?,
+            material(field(json, "material")?)?,
+        ))
+    }
+}
+
+impl<M: Material> Hittable for Cuboid<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (near, far) = self.slabs(r)?;
+        let (t, axis) = if near.0 >= t_min && near.0 <= t_max {
+            near
+        } else if far.0 >= t_min && far.0 <= t_max {
+            far
+        } else {
+            return None;
+        };
+        Some(self.record(r, t, axis))
+    }
+
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        match self.slabs(r) {
+            Some((near, far)) if near.0.is_finite() && far.0.is_finite() => vec![Span {
+                enter: self.record(r, near.0, near.1),
+                exit: self.record(r, far.0, far.1),
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "cuboid".into()),
+            ("min", self.min.into()),
+            ("max", self.max.into()),
+            ("material", self.material.to_json()?),
+        ]))
+    }
+}
@@ -0,0 +1,344 @@
+use std::io;
+
+use crate::bvh::*;
+use crate::json::Json;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::scene_file::{array, field, invalid, material, numbers, string, vec3s};
+use crate::sphere::*;
+use crate::vec3::Vec3;
+
+/// How a curve's width is shaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CurveType {
+    /// A ribbon that always faces the ray, for hair and fur, whose materials
+    /// model the round fiber themselves.
+    Flat,
+    /// A ribbon facing the ray with its normal bent around the curve as if
+    /// it were a tube, for thicker wires and strands seen up close.
+    Cylinder,
+}
+
+/// Cubic Bezier segment with a width that changes linearly along it.
+#[derive(Clone, Copy, Debug)]
+pub struct CurveSegment {
+    pub control_points: [Vec3; 4],
+    /// Width at the start and at the end.
+    pub widths: [f64; 2],
+}
+
+impl CurveSegment {
+    pub fn new(control_points: [Vec3; 4], widths: [f64; 2]) -> CurveSegment {
+        CurveSegment {
+            control_points,
+            widths,
+        }
+    }
+
+    /// The curve lies within the hull of its control points, so their box
+    /// padded by half the width bounds it.
+    pub fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(&self.control_points).padded(0.5 * self.widths[0].max(self.widths[1]))
+    }
+
+    fn width(&self, u: f64) -> f64 {
+        (1.0 - u) * self.widths[0] + u * self.widths[1]
+    }
+}
+
+/// Many curve segments sharing a material, like the strands of a head of
+/// hair or a patch of fur, with a BVH over them.
+///
+/// Hits are found as in pbrt's curves: the segment is moved into a frame
+/// looking down the ray and split in halves, discarding halves whose bounds
+/// miss the ray, until each half is close enough to a straight line.
+///
+/// `u` runs along each segment and `v` across it, from 0 on one edge
+/// through 0.5 at the middle to 1 on the other.
+pub struct Curves<M: Material> {
+    pub segments: Vec<CurveSegment>,
+    pub curve_type: CurveType,
+    pub material: M,
+    bvh: Bvh,
+}
+
+/// Deepest the segments are split before a ray is tested against them.
+const MAX_SPLIT_DEPTH: i32 = 10;
+
+impl<M: Material> Curves<M> {
+    pub fn new(segments: Vec<CurveSegment>, curve_type: CurveType, material: M) -> Curves<M> {
+        let boxes: Vec<Aabb> = segments.iter().map(|s| s.bounding_box()).collect();
+        Curves {
+            segments,
+            curve_type,
+            material,
+            bvh: Bvh::new(&boxes),
+        }
+    }
+
+    fn hit_segment(&self, index: usize, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let segment = &self.segments[index];
+        let ray_length = r.direction.length();
+        let frame = Onb::build_from_w(r.direction);
+        let control_points = segment.control_points.map(|p| frame.to_local(p - r.origin));
+
+        // Split until each piece is within a twentieth of the width of a
+        // straight line, from the curvature.
+        let mut curvature: f64 = 0.0;
+        for i in 0..2 {
+            let c = control_points[i] - 2.0 * control_points[i + 1] + control_points[i + 2];
+            curvature = curvature.max(c.x.abs()).max(c.y.abs()).max(c.z.abs());
+        }
+        let epsilon = 0.05 * segment.widths[0].max(segment.widths[1]);
+        let depth = ((2.0f64.sqrt() * 6.0 * curvature / (8.0 * epsilon)).log2() / 2.0).round();
+        let depth = if depth.is_nan() {
+            0
+        } else {
+            (depth as i32).clamp(0, MAX_SPLIT_DEPTH)
+        };
+
+        let (u, z) = self.hit_piece(
+            segment,
+            &control_points,
+            (0.0, 1.0),
+            depth,
+            (t_min * ray_length, t_max * ray_length),
+        )?;
+        let t = z / ray_length;
+
+        let p = r.at(t);
+        let dpdu = bezier_derivative(&segment.control_points, u);
+        let width = segment.width(u);
+        let tangent = if dpdu.near_zero() {
+            frame.u
+        } else {
+            Vec3::unit_vector(dpdu)
+        };
+
+        // Across the curve, and facing the ray around it.
+        let side = Vec3::cross(r.direction, tangent);
+        let side = if side.near_zero() {
+            frame.v
+        } else {
+            Vec3::unit_vector(side)
+        };
+        let facing = Vec3::cross(side, tangent);
+        let offset = Vec3::dot(p - bezier(&segment.control_points, u), side) / (0.5 * width);
+        let offset = offset.clamp(-1.0, 1.0);
+        let normal = match self.curve_type {
+            CurveType::Flat => facing,
+            CurveType::Cylinder => offset * side + (1.0 - offset * offset).sqrt() * facing,
+        };
+
+        Some(HitRecord {
+            p,
+            normal,
+            mat: &self.material,
+            t,
+            u,
+            v: 0.5 + 0.5 * offset,
+            dpdu,
+            dpdv: width * side,
+            color: Vec3::ones(),
+            front_facing: true,
+        })
+    }
+
+    /// Closest hit on the piece of `segment` between `u_range`, given by its
+    /// `control_points` in the ray's frame, as its u and distance along the ray.
+    fn hit_piece(
+        &self,
+        segment: &CurveSegment,
+        control_points: &[Vec3; 4],
+        u_range: (f64, f64),
+        depth: i32,
+        z_range: (f64, f64),
+    ) -> Option<(f64, f64)> {
+        let (u0, u1) = u_range;
+        if depth > 0 {
+            let (first, second) = split_bezier(control_points);
+            let u_mid = 0.5 * (u0 + u1);
+            let mut closest: Option<(f64, f64)> = None;
+            for (points, range) in [(first, (u0, u_mid)), (second, (u_mid, u1))] {
+                let half_width = 0.5 * segment.width(range.0).max(segment.width(range.1));
+                let bounds = Aabb::from_points(&points).padded(half_width);
+                let z_max = closest.map_or(z_range.1, |(_, z)| z);
+                if bounds.min.x > 0.0
+                    || bounds.max.x < 0.0
+                    || bounds.min.y > 0.0
+                    || bounds.max.y < 0.0
+                    || bounds.min.z > z_max
+                    || bounds.max.z < z_range.0
+                {
+                    continue;
+                }
+                if let Some(hit) =
+                    self.hit_piece(segment, &points, range, depth - 1, (z_range.0, z_max))
+                {
+                    closest = Some(hit);
+                }
+            }
+            return closest;
+        }
+
+        // The ray must pass between the lines perpendicular to the piece at
+        // its two ends.
+        let [p0, p1, p2, p3] = *control_points;
+        if (p1.y - p0.y) * -p0.y + p0.x * (p0.x - p1.x) < 0.0
+            || (p2.y - p3.y) * -p3.y + p3.x * (p3.x - p2.x) < 0.0
+        {
+            return None;
+        }
+
+        // Closest point to the ray on the line through the piece's ends.
+        let (dx, dy) = (p3.x - p0.x, p3.y - p0.y);
+        let length_squared = dx * dx + dy * dy;
+        if length_squared == 0.0 {
+            return None;
+        }
+        let w = (-p0.x * dx - p0.y * dy) / length_squared;
+        let u = ((1.0 - w) * u0 + w * u1).clamp(u0, u1);
+        let width = segment.width(u);
+        let pc = bezier(control_points, w.clamp(0.0, 1.0));
+        if pc.x * pc.x + pc.y * pc.y > 0.25 * width * width {
+            return None;
+        }
+        if pc.z < z_range.0 || pc.z > z_range.1 {
+            return None;
+        }
+        Some((u, pc.z))
+    }
+}
+
+impl Curves<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Curves<Box<dyn Material>>> {
+        let segments = array(json, "segments")?
+            .iter()
+            .map(|segment| {
+                match (
+                    &vec3s(segment, "control_points")?[..],
+                    &numbers(segment, "widths")?[..],
+                ) {
+                    (&[p0, p1, p2, p3], &[w0, w1]) => {
+                        Ok(CurveSegment::new([p0, p1, p2, p3], [w0, w1]))
+                    }
+                    _ => Err(invalid(
+                        "a curve segment isn't four control points and two widths",
+                    )),
+                }
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let curve_type = match string(json, "curve_type")? {
+            "flat" => CurveType::Flat,
+            "cylinder" => CurveType::Cylinder,
+            other => return Err(invalid(format!("unknown curve type \"{}\"", other))),
+        };
+        Ok(Curves::new(
+            segments,
+            curve_type,
+            material(field(json, "material")?)?,
+        ))
+    }
+}
+
+impl<M: Material> Hittable for Curves<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+        self.bvh.traverse(&r, t_min, t_max, |index, t_max| {
+            let hit = self.hit_segment(index, &r, t_min, t_max)?;
+            let t = hit.t;
+            closest = Some(hit);
+            Some(t)
+        });
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounds()
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        let segments = self
+            .segments
+            .iter()
+            .map(|segment| {
+                Json::object([
+                    (
+                        "control_points",
+                        Json::Array(segment.control_points.iter().map(|&p| p.into()).collect()),
+                    ),
+                    (
+                        "widths",
+                        Json::Array(segment.widths.iter().map(|&w| w.into()).collect()),
+                    ),
+                ])
+            })
+            .collect();
+        let curve_type = match self.curve_type {
+            CurveType::Flat => "flat",
+            CurveType::Cylinder => "cylinder",
+        };
+        Some(Json::object([
+            ("type", "curves".into()),
+            ("segments", Json::Array(segments)),
+            ("curve_type", curve_type.into()),
+            ("material", self.material.to_json()?),
+        ]))
+    }
+}
+
+fn bezier(p: &[Vec3; 4], u: f64) -> Vec3 {
+    let s = 1.0 - u;
+    s * s * s * p[0] + 3.0 * s * s * u * p[1] + 3.0 * s * u * u * p[2] + u * u * u * p[3]
+}
+
+fn bezier_derivative(p: &[Vec3; 4], u: f64) -> Vec3 {
+    let s = 1.0 - u;
+    3.0 * (s * s * (p[1] - p[0]) + 2.0 * s * u * (p[2] - p[1]) + u * u * (p[3] - p[2]))
+}
+
+/// De Casteljau split into the halves before and after u = 0.5.
+fn split_bezier(p: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let mid = |a: Vec3, b: Vec3| 0.5 * (a + b);
+    let (p01, p12, p23) = (mid(p[0], p[1]), mid(p[1], p[2]), mid(p[2], p[3]));
+    let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+    let center = mid(p012, p123);
+    ([p[0], p01, p012, center], [center, p123, p23, p[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_ray_hits_arched_curve_at_its_width() {
+        // An arch in the z = 0 plane peaking at (0, 0.75, 0), 0.2 wide at the top.
+        let segment = CurveSegment::new(
+            [
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            ],
+            [0.2, 0.2],
+        );
+        let curves = Curves::new(
+            vec![segment],
+            CurveType::Flat,
+            Lambertian::new(Vec3::ones()),
+        );
+        let hit_at = |x: f64, y: f64| {
+            let r = Ray::new(Vec3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -2.0));
+            curves.hit(r, 0.001, f64::INFINITY)
+        };
+
+        let hit = hit_at(0.0, 0.8).expect("ray through the top should hit");
+        assert!((hit.t - 2.5).abs() < 1.0e-6);
+        assert!((hit.u - 0.5).abs() < 0.01);
+        assert!(Vec3::dot(hit.normal, Vec3::new(0.0, 0.0, 1.0)) > 0.99);
+        assert!(hit_at(0.0, 0.9).is_none());
+        assert!(hit_at(0.0, 0.5).is_none());
+    }
+}
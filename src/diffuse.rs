@@ -0,0 +1,380 @@
+use std::f64::consts::PI;
+use std::io;
+
+use crate::json::Json;
+use crate::material::*;
+use crate::onb::Onb;
+use crate::random::*;
+use crate::ray::Ray;
+use crate::scene_file::{number, vec3};
+use crate::sphere::HitRecord;
+use crate::vec3::Vec3;
+
+/// Narrowest opposition peak, which keeps it finite straight back at the light.
+const MIN_OPPOSITION_WIDTH: f64 = 1.0e-3;
+
+/// Rough diffuse surface made of tiny Lambertian V-shaped grooves, like clay
+/// or plaster ("Generalization of Lambert's Reflectance Model", Oren and
+/// Nayar 1994, in its qualitative form). Compared to `Lambertian` it is
+/// flatter, brighter towards the light and less dark at the silhouette.
+pub struct OrenNayar {
+    pub albedo: Vec3,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    /// `sigma` is the standard deviation of the groove angles in degrees;
+    /// zero is Lambertian.
+    pub fn new(albedo: Vec3, sigma: f64) -> OrenNayar {
+        let sigma2 = sigma.to_radians().powi(2);
+        OrenNayar {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    /// Oren-Nayar material saved by `to_json`, with the two coefficients
+    /// the roughness was turned into.
+    pub fn from_json(json: &Json) -> io::Result<OrenNayar> {
+        Ok(OrenNayar {
+            albedo: vec3(json, "albedo")?,
+            a: number(json, "a")?,
+            b: number(json, "b")?,
+        })
+    }
+
+    /// Reflectance relative to Lambertian for local directions `wo` and `wi`.
+    fn factor(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let sin_theta_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        let sin_theta_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+
+        // Cosine of the azimuth between the two directions.
+        let max_cos = if sin_theta_o > 1.0e-4 && sin_theta_i > 1.0e-4 {
+            ((wo.x * wi.x + wo.y * wi.y) / (sin_theta_o * sin_theta_i)).max(0.0)
+        } else {
+            0.0
+        };
+
+        let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+            (sin_theta_o, sin_theta_i / wi.z.abs())
+        } else {
+            (sin_theta_i, sin_theta_o / wo.z.abs())
+        };
+        self.a + self.b * max_cos * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = Vec3::random_cosine_direction();
+
+        // Cosine sampling cancels the Lambertian part of the BSDF.
+        Some(ScatterRecord {
+            attenuation: self.factor(wo, wi) * self.albedo,
+            scattered: Ray::new(rec.p, uvw.local_vec(wi)),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = uvw.to_local(Vec3::unit_vector(direction));
+        if wi.z <= 0.0 {
+            return Vec3::zeros();
+        }
+        wi.z / PI * self.factor(wo, wi) * self.albedo
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "oren_nayar".into()),
+            ("albedo", self.albedo.into()),
+            ("a", self.a.into()),
+            ("b", self.b.into()),
+        ]))
+    }
+}
+
+/// Dusty, porous surface that scatters light back towards where it came from,
+/// like the moon, which looks like a flat disk at full moon and brightens
+/// sharply when the sun is right behind the viewer.
+///
+/// Uses the Lommel-Seeliger law for single scattering in a dusty layer with
+/// Hapke's shadow hiding opposition effect on top.
+pub struct RetroreflectiveDiffuse {
+    /// Fraction of light reflected at grazing incidence, without the
+    /// opposition effect. Head-on it is about a third of that.
+    pub albedo: Vec3,
+    /// Extra brightness straight back towards the light, relative to the rest.
+    pub opposition_strength: f64,
+    /// Angular width of the opposition peak; smaller is sharper. Kept above
+    /// `MIN_OPPOSITION_WIDTH`.
+    pub opposition_width: f64,
+}
+
+impl RetroreflectiveDiffuse {
+    pub fn new(
+        albedo: Vec3,
+        opposition_strength: f64,
+        opposition_width: f64,
+    ) -> RetroreflectiveDiffuse {
+        RetroreflectiveDiffuse {
+            albedo,
+            opposition_strength,
+            opposition_width: opposition_width.max(MIN_OPPOSITION_WIDTH),
+        }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<RetroreflectiveDiffuse> {
+        Ok(RetroreflectiveDiffuse::new(
+            vec3(json, "albedo")?,
+            number(json, "opposition_strength")?,
+            number(json, "opposition_width")?,
+        ))
+    }
+
+    /// BSDF times cosine, relative to `albedo`, for local directions `wo` and `wi`.
+    fn f_cos(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        // Phase angle between the directions towards the viewer and the light.
+        let phase = Vec3::dot(wo, wi).clamp(-1.0, 1.0).acos();
+        let opposition =
+            self.opposition_strength / (1.0 + (phase / 2.0).tan() / self.opposition_width);
+        (1.0 + opposition) * wi.z / (2.0 * PI * (wi.z + wo.z))
+    }
+}
+
+impl Material for RetroreflectiveDiffuse {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = Vec3::random_cosine_direction();
+        let pdf = wi.z / PI;
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord {
+            attenuation: self.f_cos(wo, wi) / pdf * self.albedo,
+            scattered: Ray::new(rec.p, uvw.local_vec(wi)),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = uvw.to_local(Vec3::unit_vector(direction));
+        self.f_cos(wo, wi) * self.albedo
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "retroreflective_diffuse".into()),
+            ("albedo", self.albedo.into()),
+            ("opposition_strength", self.opposition_strength.into()),
+            ("opposition_width", self.opposition_width.into()),
+        ]))
+    }
+}
+
+/// Thin diffuse sheet that lets some light through to its other side, like
+/// paper, leaves or a lampshade. Both sides scatter in a cosine distribution.
+pub struct DiffuseTransmission {
+    pub reflectance: Vec3,
+    pub transmittance: Vec3,
+}
+
+impl DiffuseTransmission {
+    pub fn new(reflectance: Vec3, transmittance: Vec3) -> DiffuseTransmission {
+        DiffuseTransmission {
+            reflectance,
+            transmittance,
+        }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<DiffuseTransmission> {
+        Ok(DiffuseTransmission::new(
+            vec3(json, "reflectance")?,
+            vec3(json, "transmittance")?,
+        ))
+    }
+}
+
+impl Material for DiffuseTransmission {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let average = |c: Vec3| (c.x + c.y + c.z) / 3.0;
+        let (r, t) = (average(self.reflectance), average(self.transmittance));
+        if r + t <= 0.0 {
+            return None;
+        }
+
+        // Pick a side in proportion to how much light goes there.
+        let reflect_probability = r / (r + t);
+        let (side, attenuation) = if random_f64() < reflect_probability {
+            (1.0, self.reflectance / reflect_probability)
+        } else {
+            (-1.0, self.transmittance / (1.0 - reflect_probability))
+        };
+
+        let uvw = Onb::build_from_w(side * rec.normal);
+        Some(ScatterRecord {
+            attenuation,
+            scattered: Ray::new(rec.p, uvw.local_vec(Vec3::random_cosine_direction())),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let cosine = Vec3::dot(rec.normal, Vec3::unit_vector(direction));
+        if cosine >= 0.0 {
+            cosine / PI * self.reflectance
+        } else {
+            -cosine / PI * self.transmittance
+        }
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "diffuse_transmission".into()),
+            ("reflectance", self.reflectance.into()),
+            ("transmittance", self.transmittance.into()),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::{Hittable, Sphere};
+
+    #[test]
+    fn test_oren_nayar_without_roughness_is_lambertian() {
+        let albedo = Vec3::new(0.7, 0.5, 0.2);
+        let sphere = Sphere::new(Vec3::zeros(), 1.0, OrenNayar::new(albedo, 0.0));
+        let r = Ray::new(Vec3::new(0.4, 0.3, 3.0), Vec3::new(-0.1, 0.0, -1.0));
+        let hit = sphere.hit(r, 0.001, f64::INFINITY).unwrap();
+
+        let lambertian = Lambertian::new(albedo);
+        for direction in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.5, -0.3, 0.8),
+            Vec3::new(-0.9, 0.1, 0.2),
+        ] {
+            let expected = lambertian.eval(&r, &hit, direction);
+            assert!((sphere.material.eval(&r, &hit, direction) - expected).length() < 1.0e-12);
+        }
+        for _ in 0..100 {
+            let scatter = sphere.material.scatter(&r, &hit).unwrap();
+            assert!((scatter.attenuation - albedo).length() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn test_diffuse_transmission_splits_albedo() {
+        let (reflectance, transmittance) = (Vec3::new(0.5, 0.3, 0.1), Vec3::new(0.2, 0.4, 0.6));
+        let sphere = Sphere::new(
+            Vec3::zeros(),
+            1.0,
+            DiffuseTransmission::new(reflectance, transmittance),
+        );
+        let r = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.hit(r, 0.001, f64::INFINITY).unwrap();
+
+        // Each side of the sheet gets its own share of the light, by
+        // quadrature over the sphere of directions.
+        let n = 400;
+        let (mut reflected, mut transmitted) = (Vec3::zeros(), Vec3::zeros());
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI;
+            for j in 0..2 * n {
+                let phi = (j as f64 + 0.5) / (2 * n) as f64 * 2.0 * PI;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let f_cos = theta.sin()
+                    * (PI / n as f64)
+                    * (PI / n as f64)
+                    * sphere.material.eval(&r, &hit, direction);
+                if direction.z > 0.0 {
+                    reflected += f_cos;
+                } else {
+                    transmitted += f_cos;
+                }
+            }
+        }
+        assert!((reflected - reflectance).length() < 1.0e-4);
+        assert!((transmitted - transmittance).length() < 1.0e-4);
+
+        // Sampling gives the same split.
+        let samples = 100_000;
+        let (mut reflected, mut transmitted) = (Vec3::zeros(), Vec3::zeros());
+        for _ in 0..samples {
+            let scatter = sphere.material.scatter(&r, &hit).unwrap();
+            if scatter.scattered.direction.z > 0.0 {
+                reflected += scatter.attenuation;
+            } else {
+                transmitted += scatter.attenuation;
+            }
+        }
+        assert!((reflected / samples as f64 - reflectance).length() < 0.01);
+        assert!((transmitted / samples as f64 - transmittance).length() < 0.01);
+    }
+
+    #[test]
+    fn test_retroreflective_peaks_towards_the_light() {
+        let albedo = Vec3::new(0.6, 0.5, 0.4);
+        // Hits the top of the sphere, where the normal is +z.
+        let r = Ray::new(Vec3::new(1.0, 0.0, 3.0), Vec3::new(-0.5, 0.0, -1.0));
+
+        for width in [0.0, 0.05, 0.5] {
+            let sphere = Sphere::new(
+                Vec3::zeros(),
+                1.0,
+                RetroreflectiveDiffuse::new(albedo, 2.0, width),
+            );
+            let hit = sphere.hit(r, 0.001, f64::INFINITY).unwrap();
+
+            // Brightest straight back where the light comes from, even for
+            // the sharpest peak.
+            let back = sphere.material.eval(&r, &hit, -r.direction);
+            let mirror = sphere
+                .material
+                .eval(&r, &hit, Vec3::reflect(r.direction, hit.normal));
+            assert!(back.x.is_finite() && back.y.is_finite() && back.z.is_finite());
+            assert!(back.y > mirror.y);
+
+            // Sampling agrees with `eval`, by quadrature over the hemisphere.
+            let m = 200;
+            let mut expected = Vec3::zeros();
+            for i in 0..m {
+                let theta = (i as f64 + 0.5) / m as f64 * PI / 2.0;
+                for j in 0..m {
+                    let phi = (j as f64 + 0.5) / m as f64 * 2.0 * PI;
+                    let direction = Vec3::new(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    );
+                    expected += theta.sin() * sphere.material.eval(&r, &hit, direction);
+                }
+            }
+            let expected = (PI / 2.0 / m as f64) * (2.0 * PI / m as f64) * expected;
+            let n = 100_000;
+            let estimate = (0..n)
+                .filter_map(|_| sphere.material.scatter(&r, &hit))
+                .fold(Vec3::zeros(), |sum, scatter| sum + scatter.attenuation)
+                / n as f64;
+            assert!((estimate - expected).length() < 0.02 * expected.length());
+        }
+    }
+}
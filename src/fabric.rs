@@ -0,0 +1,438 @@
+use std::f64::consts::PI;
+use std::io;
+use std::sync::Arc;
+
+use crate::json::Json;
+use crate::material::*;
+use crate::onb::Onb;
+use crate::random::*;
+use crate::ray::Ray;
+use crate::scene_file::{field, material, number, texture, vec3};
+use crate::sphere::HitRecord;
+use crate::subsurface::HomogeneousMedium;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+/// Entries in the table of how much light the sheen reflects by angle.
+const ALBEDO_TABLE_SIZE: usize = 32;
+
+/// Least probability of following the sheen rather than the base.
+const MIN_SHEEN_PROBABILITY: f64 = 0.25;
+
+/// Soft sheen of fine fibers standing up from a surface, like velvet, peach
+/// skin or dust, layered over any base material.
+///
+/// Uses the "Charlie" distribution from "Production Friendly Microfacet Sheen
+/// BRDF" (Estevez and Kulla 2017) with Neubelt and Pettineo's visibility term,
+/// as in glTF's `KHR_materials_sheen`. The base is darkened by however much
+/// light the sheen already reflected, so the layer doesn't add energy.
+pub struct Sheen<M: Material> {
+    pub base: M,
+    pub color: Vec3,
+    alpha: f64,
+    /// Directional albedo of a white sheen, by the cosine of the view angle.
+    albedo: [f64; ALBEDO_TABLE_SIZE],
+}
+
+impl<M: Material> Sheen<M> {
+    /// `roughness` in [0, 1] spreads the sheen from a thin rim at the
+    /// silhouette to a haze over the whole surface.
+    pub fn new(base: M, color: Vec3, roughness: f64) -> Sheen<M> {
+        Sheen::with_alpha(base, color, roughness.clamp(0.07, 1.0).powi(2))
+    }
+
+    fn with_alpha(base: M, color: Vec3, alpha: f64) -> Sheen<M> {
+        let mut albedo = [0.0; ALBEDO_TABLE_SIZE];
+        for (i, entry) in albedo.iter_mut().enumerate() {
+            let cos_o = (i as f64 + 0.5) / ALBEDO_TABLE_SIZE as f64;
+            *entry = directional_albedo(alpha, cos_o);
+        }
+        Sheen {
+            base,
+            color,
+            alpha,
+            albedo,
+        }
+    }
+
+    /// Fraction of light the sheen reflects towards `wo`, before it is
+    /// limited to one. The fit overshoots at grazing angles when smooth.
+    fn reflected(&self, wo: Vec3) -> f64 {
+        let x = (wo.z.clamp(0.0, 1.0) * ALBEDO_TABLE_SIZE as f64 - 0.5).max(0.0);
+        let i = (x as usize).min(ALBEDO_TABLE_SIZE - 2);
+        let t = (x - i as f64).min(1.0);
+        let albedo = (1.0 - t) * self.albedo[i] + t * self.albedo[i + 1];
+        self.color.x.max(self.color.y).max(self.color.z) * albedo
+    }
+
+    /// Sheen BSDF times cosine for local directions, scaled down where it
+    /// would reflect more light than arrives.
+    fn sheen(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        charlie(self.alpha, wo, wi) * wi.z.max(0.0) / self.reflected(wo).max(1.0) * self.color
+    }
+
+    /// `value` at the ray's wavelengths if the base works with them.
+    fn to_path_space(&self, r_in: &Ray, value: Vec3) -> Vec3 {
+        match r_in.wavelengths {
+            Some(wavelengths) if self.is_spectral() => wavelengths.upsample(value),
+            _ => value,
+        }
+    }
+}
+
+impl Sheen<Box<dyn Material>> {
+    /// Sheen saved by `to_json`, from the alpha its roughness was turned into.
+    pub fn from_json(json: &Json) -> io::Result<Sheen<Box<dyn Material>>> {
+        Ok(Sheen::with_alpha(
+            material(field(json, "base")?)?,
+            vec3(json, "color")?,
+            number(json, "alpha")?,
+        ))
+    }
+}
+
+impl<M: Material> Material for Sheen<M> {
+    /// Picks the sheen or the base at random. The sheen is picked at least
+    /// now and then even where it reflects little, since its peak is narrow
+    /// and far from the base's.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        if !rec.front_facing {
+            return self.base.scatter(r_in, rec);
+        }
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let coverage = self.reflected(wo).min(1.0);
+        let sheen_probability = if coverage > 0.0 {
+            coverage.max(MIN_SHEEN_PROBABILITY)
+        } else {
+            0.0
+        };
+        if random_f64() >= sheen_probability {
+            let scatter = self.base.scatter(r_in, rec)?;
+            return Some(ScatterRecord {
+                attenuation: (1.0 - coverage) / (1.0 - sheen_probability) * scatter.attenuation,
+                ..scatter
+            });
+        }
+
+        // Uniform over the hemisphere, which follows the sheen's grazing
+        // peak better than a cosine distribution.
+        let wi = Vec3::unit_vector(Vec3::random_in_hemisphere(&Vec3::new(0.0, 0.0, 1.0)));
+        let pdf = sheen_probability / (2.0 * PI);
+        Some(ScatterRecord {
+            attenuation: self.to_path_space(r_in, self.sheen(wo, wi) / pdf),
+            scattered: Ray::new(rec.p, uvw.local_vec(wi)),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let base = self.base.eval(r_in, rec, direction);
+        if !rec.front_facing {
+            return base;
+        }
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = uvw.to_local(Vec3::unit_vector(direction));
+        self.to_path_space(r_in, self.sheen(wo, wi)) + (1.0 - self.reflected(wo).min(1.0)) * base
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+
+    fn medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.medium()
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f64 {
+        self.base.alpha(rec)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "sheen".into()),
+            ("base", self.base.to_json()?),
+            ("color", self.color.into()),
+            ("alpha", self.alpha.into()),
+        ]))
+    }
+}
+
+/// Charlie sheen BSDF for a white sheen, without the cosine term.
+fn charlie(alpha: f64, wo: Vec3, wi: Vec3) -> f64 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+    let wh = Vec3::unit_vector(wo + wi);
+    let sin_theta_h = (1.0 - wh.z * wh.z).max(0.0).sqrt();
+    let d = (2.0 + 1.0 / alpha) * sin_theta_h.powf(1.0 / alpha) / (2.0 * PI);
+    let visibility = 1.0 / (4.0 * (wi.z + wo.z - wi.z * wo.z));
+    d * visibility
+}
+
+/// Light a white sheen reflects when seen at `cos_o`, integrated over the
+/// hemisphere with the midpoint rule.
+fn directional_albedo(alpha: f64, cos_o: f64) -> f64 {
+    const STEPS: usize = 32;
+    let wo = Vec3::new((1.0 - cos_o * cos_o).sqrt(), 0.0, cos_o);
+    let (d_theta, d_phi) = (PI / 2.0 / STEPS as f64, 2.0 * PI / STEPS as f64);
+    let mut sum = 0.0;
+    for i in 0..STEPS {
+        let theta = (i as f64 + 0.5) * d_theta;
+        for j in 0..STEPS {
+            let phi = (j as f64 + 0.5) * d_phi;
+            let wi = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            );
+            sum += charlie(alpha, wo, wi) * wi.z * theta.sin() * d_theta * d_phi;
+        }
+    }
+    sum
+}
+
+/// Woven cloth with threads running along one direction, like satin, silk or
+/// brushed fabric, whose highlights stretch across the threads.
+///
+/// The threads are treated as shiny cylinders lying in the surface, with the
+/// longitudinal Gaussian lobe of "Light Scattering from Human Hair Fibers"
+/// (Marschner et al. 2003) spread evenly around each thread, over a diffuse
+/// base. The thread direction follows the texture coordinates, so it needs
+/// surfaces with `dpdu`, such as spheres and meshes with UVs.
+pub struct Fabric {
+    pub color: Arc<dyn Texture>,
+    pub specular_color: Vec3,
+    /// Angle of the threads from the u direction, in radians.
+    pub fiber_angle: f64,
+    /// Standard deviation of the highlight around the mirror cone, in radians.
+    pub width: f64,
+}
+
+impl Fabric {
+    /// `fiber_angle` and `width` are in degrees.
+    pub fn new(
+        color: Arc<dyn Texture>,
+        specular_color: Vec3,
+        fiber_angle: f64,
+        width: f64,
+    ) -> Fabric {
+        Fabric {
+            color,
+            specular_color,
+            fiber_angle: fiber_angle.to_radians(),
+            width: width.to_radians().max(1.0e-3),
+        }
+    }
+
+    /// Fabric saved by `to_json`, with its angles in radians.
+    pub fn from_json(json: &Json) -> io::Result<Fabric> {
+        Ok(Fabric {
+            color: texture(field(json, "color")?)?,
+            specular_color: vec3(json, "specular_color")?,
+            fiber_angle: number(json, "fiber_angle")?,
+            width: number(json, "width")?,
+        })
+    }
+
+    /// Local frame with the threads along x.
+    fn frame(&self, rec: &HitRecord) -> Onb {
+        let tangent = Onb::build_from_wu(rec.normal, rec.dpdu);
+        let fiber = self.fiber_angle.cos() * tangent.u + self.fiber_angle.sin() * tangent.v;
+        Onb::build_from_wu(rec.normal, fiber)
+    }
+
+    /// Probability of sampling the thread highlight rather than the diffuse base.
+    fn specular_probability(&self, color: Vec3) -> f64 {
+        let mean = |c: Vec3| (c.x + c.y + c.z) / 3.0;
+        let specular = mean(self.specular_color);
+        if specular + mean(color) <= 0.0 {
+            return 0.0;
+        }
+        specular / (specular + mean(color))
+    }
+
+    /// Longitudinal lobe around the mirror cone, with the highlight spread
+    /// over all azimuths.
+    fn specular(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let (theta_o, theta_i) = (wo.x.clamp(-1.0, 1.0).asin(), wi.x.clamp(-1.0, 1.0).asin());
+        let cos_theta_d = ((theta_o - theta_i) / 2.0).cos();
+        gaussian((theta_o + theta_i) / 2.0, self.width) / (2.0 * PI * cos_theta_d * cos_theta_d)
+    }
+
+    /// Density of sampling `wi` from the specular lobe in `scatter`.
+    fn specular_pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let cos_theta_i = (1.0 - wi.x * wi.x).max(0.0).sqrt();
+        if wi.z <= 0.0 || cos_theta_i <= 0.0 {
+            return 0.0;
+        }
+        let theta_h = (wo.x.clamp(-1.0, 1.0).asin() + wi.x.clamp(-1.0, 1.0).asin()) / 2.0;
+        gaussian(theta_h, self.width) / (2.0 * PI * cos_theta_i)
+    }
+}
+
+impl Material for Fabric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let color = self.color.value(rec.u, rec.v, rec.p);
+        let uvw = self.frame(rec);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let specular_probability = self.specular_probability(color);
+        let wi = if random_f64() < specular_probability {
+            // Mirror the longitudinal angle about the thread's normal plane,
+            // spread by the lobe, and go around the thread on the upper half.
+            let theta_h = self.width * random_normal();
+            let theta_i = 2.0 * theta_h - wo.x.clamp(-1.0, 1.0).asin();
+            if theta_i.abs() >= PI / 2.0 {
+                return None;
+            }
+            let phi = PI * random_f64();
+            Vec3::new(
+                theta_i.sin(),
+                theta_i.cos() * phi.cos(),
+                theta_i.cos() * phi.sin(),
+            )
+        } else {
+            Vec3::random_cosine_direction()
+        };
+
+        let pdf = specular_probability * self.specular_pdf(wo, wi)
+            + (1.0 - specular_probability) * wi.z.max(0.0) / PI;
+        if wi.z <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+        let f = self.specular(wo, wi) * self.specular_color + color / PI;
+        Some(ScatterRecord {
+            attenuation: wi.z / pdf * f,
+            scattered: Ray::new(rec.p, uvw.local_vec(wi)),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let uvw = self.frame(rec);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = uvw.to_local(Vec3::unit_vector(direction));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::zeros();
+        }
+        let color = self.color.value(rec.u, rec.v, rec.p);
+        wi.z * (self.specular(wo, wi) * self.specular_color + color / PI)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "fabric".into()),
+            ("color", self.color.to_json()?),
+            ("specular_color", self.specular_color.into()),
+            ("fiber_angle", self.fiber_angle.into()),
+            ("width", self.width.into()),
+        ]))
+    }
+}
+
+fn gaussian(x: f64, sigma: f64) -> f64 {
+    (-x * x / (2.0 * sigma * sigma)).exp() / (sigma * (2.0 * PI).sqrt())
+}
+
+/// Standard normal sample by the Box-Muller transform.
+fn random_normal() -> f64 {
+    let u = 1.0 - random_f64();
+    (-2.0 * u.ln()).sqrt() * (2.0 * PI * random_f64()).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::SolidColor;
+
+    /// A hit on a flat patch facing +z, with u running along x.
+    fn hit(material: &dyn Material) -> HitRecord<'_> {
+        HitRecord {
+            p: Vec3::zeros(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            mat: material,
+            t: 1.0,
+            u: 0.5,
+            v: 0.5,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 1.0, 0.0),
+            color: Vec3::ones(),
+            front_facing: true,
+        }
+    }
+
+    #[test]
+    fn test_sheen_albedo_at_normal_incidence() {
+        // With alpha = 1 the integral works out to sqrt(2) / 10.
+        let albedo = directional_albedo(1.0, 1.0);
+        assert!((albedo - 0.1 * f64::sqrt(2.0)).abs() < 1.0e-3, "{}", albedo);
+    }
+
+    #[test]
+    fn test_fabric_highlight_follows_the_threads() {
+        let black = Arc::new(SolidColor::scalar(0.0));
+        let r_in = Ray::new(Vec3::new(-0.6, 0.0, 0.8), Vec3::new(0.6, 0.0, -0.8));
+        let mirror = Vec3::new(0.6, 0.0, 0.8);
+        let steep = Vec3::unit_vector(Vec3::new(-0.3, 0.0, 1.0));
+
+        // Threads across the plane of incidence reflect into a cone around
+        // them that holds every direction in that plane.
+        let across = Fabric::new(black.clone(), Vec3::ones(), 90.0, 5.0);
+        let rec = hit(&across);
+        let (a, b) = (
+            across.eval(&r_in, &rec, mirror) / mirror.z,
+            across.eval(&r_in, &rec, steep) / steep.z,
+        );
+        assert!((a - b).length() < 1.0e-9 * a.length());
+
+        // Threads along it only send the highlight towards the mirror direction.
+        let along = Fabric::new(black, Vec3::ones(), 0.0, 5.0);
+        let rec = hit(&along);
+        let (a, b) = (
+            along.eval(&r_in, &rec, mirror),
+            along.eval(&r_in, &rec, steep),
+        );
+        assert!(b.y < 1.0e-3 * a.y);
+    }
+
+    #[test]
+    fn test_fabric_scatter_matches_eval() {
+        let fabric = Fabric::new(
+            Arc::new(SolidColor::new(Vec3::new(0.5, 0.3, 0.2))),
+            Vec3::new(0.3, 0.3, 0.3),
+            30.0,
+            15.0,
+        );
+        let rec = hit(&fabric);
+        let r_in = Ray::new(Vec3::zeros(), Vec3::new(0.5, 0.2, -0.8));
+
+        // Light sent back under uniform illumination, by quadrature and by
+        // sampling.
+        let m = 400;
+        let mut expected = Vec3::zeros();
+        for i in 0..m {
+            let theta = (i as f64 + 0.5) / m as f64 * PI / 2.0;
+            for j in 0..m {
+                let phi = (j as f64 + 0.5) / m as f64 * 2.0 * PI;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                expected += theta.sin() * fabric.eval(&r_in, &rec, direction);
+            }
+        }
+        let expected = (PI / 2.0 / m as f64) * (2.0 * PI / m as f64) * expected;
+        let n = 200_000;
+        let estimate = (0..n)
+            .filter_map(|_| fabric.scatter(&r_in, &rec))
+            .fold(Vec3::zeros(), |sum, scatter| sum + scatter.attenuation)
+            / n as f64;
+        assert!((estimate - expected).length() < 0.02 * expected.length());
+    }
+}
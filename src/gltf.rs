@@ -0,0 +1,1067 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::bump::NormalMapped;
+use crate::bvh::Aabb;
+use crate::camera::Camera;
+use crate::image::Image;
+use crate::json::Json;
+use crate::light::*;
+use crate::material::Material;
+use crate::mesh_io::MeshData;
+use crate::mix::*;
+use crate::principled::Principled;
+use crate::scene::Scene;
+use crate::sky::Sky;
+use crate::sphere::HittableList;
+use crate::texture::*;
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+
+/// Luminous efficacy of light at 555 nm. glTF lights are given in
+/// candela and lux, and dividing by this turns them into the watts the
+/// renderer's other lights are in.
+const LUMENS_PER_WATT: f64 = 683.0;
+
+/// Vertical field of view used to frame the scene when it has no camera.
+const DEFAULT_VFOV: f64 = 40.0;
+
+impl Scene {
+    /// Scene from a glTF 2.0 file, either JSON with its buffers and images
+    /// beside it or embedded as data URIs, or binary .glb.
+    ///
+    /// Meshes use the metallic-roughness material with its textures, normal
+    /// map, alpha mode and the transmission and IOR extensions; vertex
+    /// colors tint it. Lights come from KHR_lights_punctual. The first
+    /// camera found in the scene is used, or one framing everything if there
+    /// is none. Parts that can't be rendered, like emission, are skipped
+    /// with a warning.
+    pub fn from_gltf(path: impl AsRef<Path>, aspect_ratio: f64) -> io::Result<Scene> {
+        let path = path.as_ref();
+        let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        load(
+            &fs::read(path)?,
+            &base,
+            &path.display().to_string(),
+            aspect_ratio,
+        )
+    }
+}
+
+fn invalid(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("bad glTF file: {}", message),
+    )
+}
+
+/// Scene from the bytes of a glTF or .glb file, with relative URIs looked
+/// up in `base`. `name` is used in warnings.
+fn load(bytes: &[u8], base: &Path, name: &str, aspect_ratio: f64) -> io::Result<Scene> {
+    let (json, binary) = if bytes.starts_with(b"glTF") {
+        split_glb(bytes)?
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| invalid("JSON isn't UTF-8"))?;
+        (Json::parse(text)?, None)
+    };
+    let version = json
+        .get("asset")
+        .and_then(|asset| asset.get("version"))
+        .and_then(Json::as_str);
+    if !version.is_some_and(|version| version.starts_with('2')) {
+        return Err(invalid("not glTF version 2"));
+    }
+
+    let mut gltf = Gltf {
+        buffers: Vec::new(),
+        base: base.to_path_buf(),
+        name: name.to_string(),
+        images: HashMap::new(),
+        json,
+    };
+    gltf.buffers = gltf.load_buffers(binary)?;
+    gltf.build(aspect_ratio)
+}
+
+/// The JSON and binary chunks of a .glb file.
+fn split_glb(bytes: &[u8]) -> io::Result<(Json, Option<Vec<u8>>)> {
+    let word = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| invalid("GLB is truncated"))
+    };
+    if word(4)? != 2 {
+        return Err(invalid("not GLB version 2"));
+    }
+    let length = word(8)?.min(bytes.len());
+
+    let (mut json, mut binary) = (None, None);
+    let mut position = 12;
+    while position + 8 <= length {
+        let (chunk_length, kind) = (word(position)?, word(position + 4)?);
+        let data = bytes
+            .get(position + 8..position + 8 + chunk_length)
+            .ok_or_else(|| invalid("GLB chunk runs past the end of the file"))?;
+        match kind {
+            0x4e4f534a => {
+                let text =
+                    std::str::from_utf8(data).map_err(|_| invalid("JSON chunk isn't UTF-8"))?;
+                json = Some(Json::parse(text)?);
+            }
+            0x004e4942 => binary = Some(data.to_vec()),
+            _ => {}
+        }
+        // Chunks are padded to four bytes.
+        position += 8 + chunk_length.div_ceil(4) * 4;
+    }
+    Ok((
+        json.ok_or_else(|| invalid("GLB has no JSON chunk"))?,
+        binary,
+    ))
+}
+
+/// A node's transform from its column-major `matrix`, or else its
+/// translation, rotation quaternion and scale applied in reverse order.
+fn node_transform(node: &Json) -> Transform {
+    if let Some(values) = node
+        .get("matrix")
+        .and_then(Json::as_f64s)
+        .filter(|values| values.len() == 16)
+    {
+        return Transform::from_columns(&values);
+    }
+
+    let vector =
+        |key: &str, default: Vec<f64>| node.get(key).and_then(Json::as_f64s).unwrap_or(default);
+    let t = vector("translation", vec![0.0; 3]);
+    let q = vector("rotation", vec![0.0, 0.0, 0.0, 1.0]);
+    let s = vector("scale", vec![1.0; 3]);
+    if t.len() != 3 || q.len() != 4 || s.len() != 3 {
+        return Transform::identity();
+    }
+    let (x, y, z, w) = (q[0], q[1], q[2], q[3]);
+    let rotation = [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - z * w),
+            2.0 * (x * z + y * w),
+        ],
+        [
+            2.0 * (x * y + z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - x * w),
+        ],
+        [
+            2.0 * (x * z - y * w),
+            2.0 * (y * z + x * w),
+            1.0 - 2.0 * (x * x + y * y),
+        ],
+    ];
+    let mut m = Transform::identity().0;
+    for i in 0..3 {
+        for j in 0..3 {
+            m[i][j] = rotation[i][j] * s[j];
+        }
+        m[i][3] = t[i];
+    }
+    Transform(m)
+}
+
+struct Gltf {
+    json: Json,
+    buffers: Vec<Vec<u8>>,
+    base: PathBuf,
+    name: String,
+    /// Decoded images by index, none for those that failed.
+    images: HashMap<usize, Option<Arc<Image>>>,
+}
+
+/// What the walk over the node hierarchy has gathered so far.
+struct Gathered {
+    world: HittableList,
+    lights: Vec<Box<dyn Light>>,
+    camera: Option<Camera>,
+    bounds: Aabb,
+}
+
+impl Gltf {
+    fn warn(&self, message: impl std::fmt::Display) {
+        eprintln!("{}: {}", self.name, message);
+    }
+
+    /// Entry `index` of the top level array `key`.
+    fn item(&self, key: &str, index: usize) -> io::Result<&Json> {
+        self.json
+            .get(key)
+            .and_then(|items| items.at(index))
+            .ok_or_else(|| invalid(format!("{} {} doesn't exist", key, index)))
+    }
+
+    fn load_buffers(&self, binary: Option<Vec<u8>>) -> io::Result<Vec<Vec<u8>>> {
+        let buffers = self
+            .json
+            .get("buffers")
+            .and_then(Json::as_array)
+            .unwrap_or(&[]);
+        let mut binary = binary;
+        let mut loaded = Vec::with_capacity(buffers.len());
+        for (index, buffer) in buffers.iter().enumerate() {
+            let data = match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) => self.read_uri(uri)?,
+                None => binary.take().ok_or_else(|| {
+                    invalid(format!(
+                        "buffer {} has no URI and there is no GLB chunk",
+                        index
+                    ))
+                })?,
+            };
+            let length = buffer
+                .get("byteLength")
+                .and_then(Json::as_usize)
+                .unwrap_or(data.len());
+            if data.len() < length {
+                return Err(invalid(format!(
+                    "buffer {} has {} bytes but its byteLength is {}",
+                    index,
+                    data.len(),
+                    length
+                )));
+            }
+            loaded.push(data);
+        }
+        Ok(loaded)
+    }
+
+    /// Contents of a base64 data URI, or of a file relative to the glTF
+    /// file.
+    fn read_uri(&self, uri: &str) -> io::Result<Vec<u8>> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let (_, encoded) = data
+                .split_once(";base64,")
+                .ok_or_else(|| invalid("data URIs have to be base64"))?;
+            return STANDARD
+                .decode(encoded)
+                .map_err(|error| invalid(format!("bad base64 data: {}", error)));
+        }
+        fs::read(self.base.join(percent_decode(uri)))
+            .map_err(|error| io::Error::new(error.kind(), format!("can't read {}: {}", uri, error)))
+    }
+
+    fn bytes_of_view(&self, view_index: usize) -> io::Result<(&[u8], Option<usize>)> {
+        let view = self.item("bufferViews", view_index)?;
+        let buffer_index = view.get("buffer").and_then(Json::as_usize).unwrap_or(0);
+        let buffer = self.buffers.get(buffer_index).ok_or_else(|| {
+            invalid(format!(
+                "bufferView {} refers to missing buffer {}",
+                view_index, buffer_index
+            ))
+        })?;
+        let offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let length = view.get("byteLength").and_then(Json::as_usize).unwrap_or(0);
+        let bytes = buffer
+            .get(offset..offset + length)
+            .ok_or_else(|| invalid(format!("bufferView {} reaches past its buffer", view_index)))?;
+        Ok((bytes, view.get("byteStride").and_then(Json::as_usize)))
+    }
+
+    /// The elements of an accessor, each a list of its components, with
+    /// normalized integers mapped to [0, 1] or [-1, 1].
+    fn accessor(&self, index: usize) -> io::Result<Vec<Vec<f64>>> {
+        let accessor = self.item("accessors", index)?;
+        let count = accessor.get("count").and_then(Json::as_usize).unwrap_or(0);
+        let components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(invalid(format!("accessor {} has an unknown type", index))),
+        };
+        let component_type = accessor
+            .get("componentType")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        let (size, max) = match component_type {
+            5120 => (1, 127.0),
+            5121 => (1, 255.0),
+            5122 => (2, 32767.0),
+            5123 => (2, 65535.0),
+            5125 => (4, 4294967295.0),
+            5126 => (4, 1.0),
+            _ => {
+                return Err(invalid(format!(
+                    "accessor {} has an unknown component type",
+                    index
+                )))
+            }
+        };
+        let normalized = accessor
+            .get("normalized")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+        if accessor.get("sparse").is_some() {
+            self.warn(format!("ignoring the sparse values of accessor {}", index));
+        }
+
+        let Some(view) = accessor.get("bufferView").and_then(Json::as_usize) else {
+            return Ok(vec![vec![0.0; components]; count]);
+        };
+        let (bytes, stride) = self.bytes_of_view(view)?;
+        let stride = stride.unwrap_or(size * components);
+        let offset = accessor
+            .get("byteOffset")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        // Check the last element fits before allocating for all of them.
+        let end = match count.checked_sub(1) {
+            Some(last) => last
+                .checked_mul(stride)
+                .and_then(|start| start.checked_add(offset))
+                .and_then(|start| start.checked_add(size * components)),
+            None => Some(0),
+        };
+        if end.is_none_or(|end| end > bytes.len()) {
+            return Err(invalid(format!(
+                "accessor {} reaches past its bufferView",
+                index
+            )));
+        }
+        let mut elements = Vec::with_capacity(count);
+        for element in 0..count {
+            let start = offset + element * stride;
+            let raw = &bytes[start..start + size * components];
+            let values = raw
+                .chunks_exact(size)
+                .map(|b| {
+                    let value = match component_type {
+                        5120 => b[0] as i8 as f64,
+                        5121 => b[0] as f64,
+                        5122 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                        5123 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                        5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                        _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    };
+                    if normalized && component_type != 5126 {
+                        (value / max).max(-1.0)
+                    } else {
+                        value
+                    }
+                })
+                .collect();
+            elements.push(values);
+        }
+        Ok(elements)
+    }
+
+    /// Image of the texture a texture info object refers to, or none with a
+    /// warning if it can't be read.
+    fn texture(&mut self, info: Option<&Json>) -> Option<Arc<Image>> {
+        let info = info?;
+        let index = info.get("index").and_then(Json::as_usize)?;
+        if info.get("texCoord").and_then(Json::as_usize).unwrap_or(0) != 0 {
+            self.warn(format!(
+                "texture {} uses a second set of texture coordinates, using the first",
+                index
+            ));
+        }
+        let source = self
+            .item("textures", index)
+            .ok()?
+            .get("source")
+            .and_then(Json::as_usize)?;
+        if let Some(image) = self.images.get(&source) {
+            return image.clone();
+        }
+
+        let image = self.read_image(source);
+        if let Err(error) = &image {
+            self.warn(format!("ignoring image {}: {}", source, error));
+        }
+        let image = image.ok().map(Arc::new);
+        self.images.insert(source, image.clone());
+        image
+    }
+
+    fn read_image(&self, index: usize) -> io::Result<Image> {
+        let image = self.item("images", index)?;
+        if let Some(uri) = image.get("uri").and_then(Json::as_str) {
+            return Image::decode(&self.read_uri(uri)?);
+        }
+        let view = image
+            .get("bufferView")
+            .and_then(Json::as_usize)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "image {} has neither a URI nor a bufferView",
+                    index
+                ))
+            })?;
+        Image::decode(self.bytes_of_view(view)?.0)
+    }
+
+    /// Material `index`, or the default white rough metal if there is none,
+    /// tinted by vertex colors if the primitive has them.
+    fn material(
+        &mut self,
+        index: Option<usize>,
+        vertex_colored: bool,
+    ) -> io::Result<Box<dyn Material>> {
+        let json = match index {
+            Some(index) => self.item("materials", index)?.clone(),
+            None => Json::Object(Vec::new()),
+        };
+        let pbr = json
+            .get("pbrMetallicRoughness")
+            .cloned()
+            .unwrap_or(Json::Object(Vec::new()));
+        let factor = |key: &str| pbr.get(key).and_then(Json::as_f64).unwrap_or(1.0);
+        let base_factor = pbr
+            .get("baseColorFactor")
+            .and_then(Json::as_f64s)
+            .filter(|factor| factor.len() == 4)
+            .unwrap_or(vec![1.0; 4]);
+        let base_scale = Vec3::new(base_factor[0], base_factor[1], base_factor[2]);
+
+        let base_image = self.texture(pbr.get("baseColorTexture"));
+        let base_color: Arc<dyn Texture> = match &base_image {
+            Some(image) => Arc::new(ScaledTexture::new(
+                Arc::new(ImageTexture::color(image.clone())),
+                base_scale,
+            )),
+            None => Arc::new(SolidColor::new(base_scale)),
+        };
+        let packed = self.texture(pbr.get("metallicRoughnessTexture"));
+        let parameter = |channel: usize, factor: f64| -> Arc<dyn Texture> {
+            match &packed {
+                Some(image) => Arc::new(ScaledTexture::new(
+                    Arc::new(ChannelTexture::new(
+                        Arc::new(ImageTexture::data(image.clone())),
+                        channel,
+                    )),
+                    Vec3::new(factor, factor, factor),
+                )),
+                None => Arc::new(SolidColor::scalar(factor)),
+            }
+        };
+        let mut principled = Principled::new(base_color)
+            .metallic(parameter(2, factor("metallicFactor")))
+            .roughness(parameter(1, factor("roughnessFactor")));
+
+        let extension = |name: &str, key: &str| {
+            json.get("extensions")
+                .and_then(|e| e.get(name))
+                .and_then(|e| e.get(key))
+                .and_then(Json::as_f64)
+        };
+        if let Some(transmission) = extension("KHR_materials_transmission", "transmissionFactor") {
+            principled = principled.transmission(Arc::new(SolidColor::scalar(transmission)));
+        }
+        if let Some(ior) = extension("KHR_materials_ior", "ior") {
+            principled = principled.index_of_refraction(Arc::new(SolidColor::scalar(ior)));
+        }
+        let emissive = json
+            .get("emissiveFactor")
+            .and_then(Json::as_f64s)
+            .unwrap_or_default();
+        if emissive.iter().any(|&e| e > 0.0) || json.get("emissiveTexture").is_some() {
+            self.warn(format!(
+                "emission isn't supported, ignoring it on material {}",
+                index.unwrap_or(0)
+            ));
+        }
+
+        let mut material: Box<dyn Material> = Box::new(principled);
+        if let Some(image) = self.texture(json.get("normalTexture")) {
+            material = Box::new(NormalMapped::new(
+                material,
+                Arc::new(ImageTexture::data(image)),
+            ));
+        }
+        if vertex_colored {
+            material = Box::new(VertexColored::new(material));
+        }
+        let alpha = || -> Arc<dyn Texture> {
+            let a = base_factor[3];
+            match &base_image {
+                Some(image) => Arc::new(ScaledTexture::new(
+                    Arc::new(ImageTexture::alpha(image.clone())),
+                    Vec3::new(a, a, a),
+                )),
+                None => Arc::new(SolidColor::scalar(a)),
+            }
+        };
+        match json.get("alphaMode").and_then(Json::as_str) {
+            Some("MASK") => {
+                let cutoff = json
+                    .get("alphaCutoff")
+                    .and_then(Json::as_f64)
+                    .unwrap_or(0.5);
+                material = Box::new(AlphaCutout::new(
+                    material,
+                    Arc::new(ThresholdTexture::new(alpha(), cutoff)),
+                ));
+            }
+            Some("BLEND") => material = Box::new(AlphaCutout::new(material, alpha())),
+            _ => {}
+        }
+        Ok(material)
+    }
+
+    fn add_mesh(
+        &mut self,
+        index: usize,
+        transform: Transform,
+        gathered: &mut Gathered,
+    ) -> io::Result<()> {
+        let primitives = self
+            .item("meshes", index)?
+            .get("primitives")
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+            .to_vec();
+        for (k, primitive) in primitives.iter().enumerate() {
+            let mode = primitive.get("mode").and_then(Json::as_usize).unwrap_or(4);
+            if !(4..=6).contains(&mode) {
+                self.warn(format!(
+                    "skipping primitive {} of mesh {}, which isn't made of triangles",
+                    k, index
+                ));
+                continue;
+            }
+            let attribute = |name: &str| {
+                primitive
+                    .get("attributes")
+                    .and_then(|a| a.get(name))
+                    .and_then(Json::as_usize)
+            };
+            let Some(positions) = attribute("POSITION") else {
+                self.warn(format!(
+                    "skipping primitive {} of mesh {}, which has no positions",
+                    k, index
+                ));
+                continue;
+            };
+
+            let mut mesh = MeshData::default();
+            let vector = |v: &[f64]| Vec3::new(v[0], v[1], v[2]);
+            mesh.positions = self
+                .accessor(positions)?
+                .iter()
+                .map(|p| transform.point(vector(p)))
+                .collect();
+            if let Some(normals) = attribute("NORMAL") {
+                mesh.normals = self
+                    .accessor(normals)?
+                    .iter()
+                    .map(|n| transform.normal(vector(n)))
+                    .collect();
+            }
+            if let Some(uvs) = attribute("TEXCOORD_0") {
+                // glTF's v runs down the image, and the renderer's up.
+                mesh.uvs = self
+                    .accessor(uvs)?
+                    .iter()
+                    .map(|uv| (uv[0], 1.0 - uv[1]))
+                    .collect();
+            }
+            if let Some(colors) = attribute("COLOR_0") {
+                mesh.colors = self.accessor(colors)?.iter().map(|c| vector(c)).collect();
+            }
+            let count = mesh.positions.len();
+            for (name, values) in [
+                ("NORMAL", mesh.normals.len()),
+                ("TEXCOORD_0", mesh.uvs.len()),
+                ("COLOR_0", mesh.colors.len()),
+            ] {
+                if values != 0 && values != count {
+                    return Err(invalid(format!(
+                        "primitive {} of mesh {} has {} {} values for {} positions",
+                        k, index, values, name, count
+                    )));
+                }
+            }
+            let corners: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+                Some(indices) => self
+                    .accessor(indices)?
+                    .iter()
+                    .map(|i| i[0] as usize)
+                    .collect(),
+                None => (0..count).collect(),
+            };
+            if let Some(&corner) = corners.iter().find(|&&i| i >= count) {
+                return Err(invalid(format!(
+                    "primitive {} of mesh {} refers to vertex {} of {}",
+                    k, index, corner, count
+                )));
+            }
+
+            let mut triangles: Vec<[usize; 3]> = match mode {
+                4 => corners
+                    .chunks_exact(3)
+                    .map(|c| [c[0], c[1], c[2]])
+                    .collect(),
+                5 => (0..corners.len().saturating_sub(2))
+                    .map(|i| {
+                        if i % 2 == 0 {
+                            [corners[i], corners[i + 1], corners[i + 2]]
+                        } else {
+                            [corners[i + 1], corners[i], corners[i + 2]]
+                        }
+                    })
+                    .collect(),
+                _ => (1..corners.len().saturating_sub(1))
+                    .map(|i| [corners[0], corners[i], corners[i + 1]])
+                    .collect(),
+            };
+            // A mirroring transform turns the winding inside out.
+            if transform.determinant() < 0.0 {
+                for triangle in &mut triangles {
+                    triangle.swap(1, 2);
+                }
+            }
+            mesh.triangles = triangles;
+            if mesh.triangles.is_empty() {
+                continue;
+            }
+
+            let material = self.material(
+                primitive.get("material").and_then(Json::as_usize),
+                !mesh.colors.is_empty(),
+            )?;
+            gathered.bounds = gathered.bounds.union(&Aabb::from_points(&mesh.positions));
+            gathered.world.add(Box::new(mesh.into_mesh(material)));
+        }
+        Ok(())
+    }
+
+    fn camera(&self, index: usize, transform: Transform, aspect_ratio: f64) -> io::Result<Camera> {
+        let camera = self.item("cameras", index)?;
+        let perspective = camera.get("perspective");
+        if perspective.is_none() {
+            self.warn(format!(
+                "camera {} isn't a perspective camera, rendering it as one",
+                index
+            ));
+        }
+        let vfov = perspective
+            .and_then(|p| p.get("yfov"))
+            .and_then(Json::as_f64)
+            .map_or(DEFAULT_VFOV, f64::to_degrees);
+        let origin = transform.point(Vec3::zeros());
+        let forward = transform.vector(Vec3::new(0.0, 0.0, -1.0));
+        let up = transform.vector(Vec3::new(0.0, 1.0, 0.0));
+        Ok(Camera::new(
+            origin,
+            origin + forward,
+            up,
+            vfov,
+            aspect_ratio,
+            0.0,
+            1.0,
+        ))
+    }
+
+    fn light(&self, index: usize, transform: Transform) -> io::Result<Option<Box<dyn Light>>> {
+        let light = self
+            .json
+            .get("extensions")
+            .and_then(|e| e.get("KHR_lights_punctual"))
+            .and_then(|e| e.get("lights"))
+            .and_then(|lights| lights.at(index))
+            .ok_or_else(|| invalid(format!("light {} doesn't exist", index)))?;
+        let color = light
+            .get("color")
+            .and_then(Json::as_f64s)
+            .filter(|color| color.len() == 3)
+            .map_or(Vec3::ones(), |c| Vec3::new(c[0], c[1], c[2]));
+        let intensity =
+            light.get("intensity").and_then(Json::as_f64).unwrap_or(1.0) / LUMENS_PER_WATT;
+        let position = transform.point(Vec3::zeros());
+        let direction = Vec3::unit_vector(transform.vector(Vec3::new(0.0, 0.0, -1.0)));
+        Ok(match light.get("type").and_then(Json::as_str) {
+            Some("point") => Some(Box::new(PointLight::new(position, color, intensity))),
+            Some("spot") => {
+                let angle = |key: &str, default: f64| {
+                    light
+                        .get("spot")
+                        .and_then(|s| s.get(key))
+                        .and_then(Json::as_f64)
+                        .unwrap_or(default)
+                        .to_degrees()
+                };
+                Some(Box::new(SpotLight::new(
+                    position,
+                    position + direction,
+                    color,
+                    intensity,
+                    angle("outerConeAngle", std::f64::consts::FRAC_PI_4),
+                    angle("innerConeAngle", 0.0),
+                )))
+            }
+            Some("directional") => {
+                Some(Box::new(DirectionalLight::new(direction, color, intensity)))
+            }
+            _ => {
+                self.warn(format!("skipping light {} of unknown type", index));
+                None
+            }
+        })
+    }
+
+    fn visit(
+        &mut self,
+        index: usize,
+        parent: Transform,
+        aspect_ratio: f64,
+        visited: &mut Vec<bool>,
+        gathered: &mut Gathered,
+    ) -> io::Result<()> {
+        let seen = visited
+            .get_mut(index)
+            .ok_or_else(|| invalid(format!("node {} doesn't exist", index)))?;
+        if std::mem::replace(seen, true) {
+            return Err(invalid(format!(
+                "node {} appears twice in the hierarchy",
+                index
+            )));
+        }
+        let node = self.item("nodes", index)?.clone();
+        let transform = parent.then(node_transform(&node));
+
+        if let Some(mesh) = node.get("mesh").and_then(Json::as_usize) {
+            self.add_mesh(mesh, transform, gathered)?;
+        }
+        if let Some(camera) = node.get("camera").and_then(Json::as_usize) {
+            if gathered.camera.is_none() {
+                gathered.camera = Some(self.camera(camera, transform, aspect_ratio)?);
+            }
+        }
+        let light = node
+            .get("extensions")
+            .and_then(|e| e.get("KHR_lights_punctual"))
+            .and_then(|e| e.get("light"))
+            .and_then(Json::as_usize);
+        if let Some(light) = light {
+            gathered.lights.extend(self.light(light, transform)?);
+        }
+        for child in node.get("children").and_then(Json::as_array).unwrap_or(&[]) {
+            let child = child
+                .as_usize()
+                .ok_or_else(|| invalid(format!("node {} has a bad child", index)))?;
+            self.visit(child, transform, aspect_ratio, visited, gathered)?;
+        }
+        Ok(())
+    }
+
+    fn build(&mut self, aspect_ratio: f64) -> io::Result<Scene> {
+        let node_count = self
+            .json
+            .get("nodes")
+            .and_then(Json::as_array)
+            .map_or(0, <[Json]>::len);
+        let scene = self.json.get("scene").and_then(Json::as_usize).unwrap_or(0);
+        let roots: Vec<usize> = match self.json.get("scenes").and_then(|scenes| scenes.at(scene)) {
+            Some(entry) => {
+                let nodes = entry.get("nodes").and_then(Json::as_array).unwrap_or(&[]);
+                nodes
+                    .iter()
+                    .map(|node| {
+                        node.as_usize()
+                            .ok_or_else(|| invalid(format!("scene {} has a bad node", scene)))
+                    })
+                    .collect::<io::Result<_>>()?
+            }
+            // Without scenes, every node that isn't a child is a root.
+            None => {
+                let mut is_child = vec![false; node_count];
+                for node in self
+                    .json
+                    .get("nodes")
+                    .and_then(Json::as_array)
+                    .unwrap_or(&[])
+                {
+                    for child in node
+                        .get("children")
+                        .and_then(Json::as_f64s)
+                        .unwrap_or_default()
+                    {
+                        if let Some(flag) = is_child.get_mut(child as usize) {
+                            *flag = true;
+                        }
+                    }
+                }
+                (0..node_count).filter(|&n| !is_child[n]).collect()
+            }
+        };
+
+        let mut gathered = Gathered {
+            world: HittableList::new(),
+            lights: Vec::new(),
+            camera: None,
+            bounds: Aabb::empty(),
+        };
+        let mut visited = vec![false; node_count];
+        for root in roots {
+            self.visit(
+                root,
+                Transform::identity(),
+                aspect_ratio,
+                &mut visited,
+                &mut gathered,
+            )?;
+        }
+
+        let camera = gathered
+            .camera
+            .unwrap_or_else(|| framing_camera(gathered.bounds, aspect_ratio));
+        // glTF has no environment, so a scene with its own lights is lit by
+        // those alone.
+        let sky = if gathered.lights.is_empty() {
+            Sky::Gradient
+        } else {
+            Sky::Uniform(Vec3::zeros())
+        };
+        Ok(Scene {
+            objects: gathered.world,
+            camera,
+            sky,
+            lights: gathered.lights,
+            fog: None,
+        })
+    }
+}
+
+/// Camera looking at the middle of `bounds` from the front and a little
+/// above, far enough back to see all of it.
+fn framing_camera(bounds: Aabb, aspect_ratio: f64) -> Camera {
+    let (center, radius) = if bounds.min.x <= bounds.max.x {
+        (bounds.centroid(), 0.5 * (bounds.max - bounds.min).length())
+    } else {
+        (Vec3::zeros(), 1.0)
+    };
+    let distance = radius.max(1.0e-3) / (0.5 * DEFAULT_VFOV.to_radians()).sin();
+    let lookfrom = center + distance * Vec3::unit_vector(Vec3::new(0.0, 0.4, 1.0));
+    Camera::new(
+        lookfrom,
+        center,
+        Vec3::new(0.0, 1.0, 0.0),
+        DEFAULT_VFOV,
+        aspect_ratio,
+        0.0,
+        distance,
+    )
+}
+
+/// URI with its %XX escapes turned back into bytes.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::sphere::Hittable;
+
+    /// A triangle facing +z in the xy plane, as positions then u16 indices.
+    fn triangle_buffer() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for x in [-1.0f32, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend(x.to_le_bytes());
+        }
+        for i in [0u16, 1, 2] {
+            bytes.extend(i.to_le_bytes());
+        }
+        bytes.extend([0, 0]);
+        bytes
+    }
+
+    fn gltf_json(buffer: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0, 2]}}],
+                "nodes": [
+                    {{"translation": [0, 0, -5], "children": [1]}},
+                    {{"mesh": 0, "scale": [2, 2, 2]}},
+                    {{"camera": 0, "translation": [0, 0, 1], "extensions": {{"KHR_lights_punctual": {{"light": 0}}}}}}
+                ],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}}]}}],
+                "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0}}}}],
+                "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.8, "znear": 0.1}}}}],
+                "extensions": {{"KHR_lights_punctual": {{"lights": [{{"type": "point", "intensity": 683}}]}}}},
+                "buffers": [{{{}"byteLength": 44}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+                ]
+            }}"#,
+            buffer
+        )
+    }
+
+    fn check(scene: &Scene) {
+        assert_eq!(scene.lights.len(), 1);
+        // The triangle is scaled by 2 and moved 5 back, 6 in front of the camera.
+        let hit = scene
+            .objects
+            .hit(
+                Ray::new(Vec3::new(0.5, -0.5, 1.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 6.0).abs() < 1.0e-6);
+        assert!(hit.front_facing);
+        assert!(scene
+            .objects
+            .hit(
+                Ray::new(Vec3::new(1.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f64::INFINITY
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn test_loads_embedded_gltf_and_glb() {
+        let encoded = STANDARD.encode(triangle_buffer());
+        let uri = format!(
+            r#""uri": "data:application/octet-stream;base64,{}", "#,
+            encoded
+        );
+        let scene = load(gltf_json(&uri).as_bytes(), Path::new("."), "test", 1.0).unwrap();
+        check(&scene);
+
+        // Counts that don't fit the buffer and roots that aren't node indices.
+        let huge = gltf_json(&uri).replace(
+            r#""count": 3, "type": "VEC3""#,
+            r#""count": 4611686018427387904, "type": "VEC3""#,
+        );
+        let error = load(huge.as_bytes(), Path::new("."), "test", 1.0)
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .contains("accessor 0 reaches past its bufferView"));
+        let bad_root = gltf_json(&uri).replace(r#""nodes": [0, 2]"#, r#""nodes": [0, -2]"#);
+        let error = load(bad_root.as_bytes(), Path::new("."), "test", 1.0)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("scene 0 has a bad node"));
+        let short_normals = gltf_json(&uri)
+            .replace(r#"{"POSITION": 0}"#, r#"{"POSITION": 0, "NORMAL": 2}"#)
+            .replace(
+                r#""type": "SCALAR"}"#,
+                r#""type": "SCALAR"}, {"bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3"}"#,
+            );
+        let error = load(short_normals.as_bytes(), Path::new("."), "test", 1.0)
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .contains("primitive 0 of mesh 0 has 2 NORMAL values for 3 positions"));
+
+        let mut json = gltf_json("").into_bytes();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+        let bin = triangle_buffer();
+        let mut glb = b"glTF".to_vec();
+        glb.extend(2u32.to_le_bytes());
+        glb.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(&json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(&bin);
+        let scene = load(&glb, Path::new("."), "test", 1.0).unwrap();
+        check(&scene);
+
+        glb.truncate(glb.len() - 10);
+        assert!(load(&glb, Path::new("."), "test", 1.0).is_err());
+    }
+
+    #[test]
+    fn test_saves_and_reloads_imported_gltf() {
+        // A 2 by 2 RGBA PNG used for every texture the material can have.
+        let png: &[u8] = &[
+            137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 2, 0, 0, 0, 2,
+            8, 6, 0, 0, 0, 114, 182, 13, 36, 0, 0, 0, 29, 73, 68, 65, 84, 120, 1, 1, 18, 0, 237,
+            255, 0, 255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255, 128, 73, 73,
+            9, 120, 75, 217, 206, 3, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+        ];
+        let uri = format!(
+            r#""uri": "data:application/octet-stream;base64,{}", "#,
+            STANDARD.encode(triangle_buffer())
+        );
+        let textured = format!(
+            r#""materials": [{{
+                "pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0}}, "metallicRoughnessTexture": {{"index": 0}}}},
+                "normalTexture": {{"index": 0}},
+                "alphaMode": "MASK"
+            }}],
+            "textures": [{{"source": 0}}],
+            "images": [{{"uri": "data:image/png;base64,{}"}}],"#,
+            STANDARD.encode(png)
+        );
+        let json = gltf_json(&uri).replace(
+            r#""materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0}}],"#,
+            &textured,
+        );
+        assert!(json.contains("alphaMode"));
+        let scene = load(json.as_bytes(), Path::new("."), "test", 1.0).unwrap();
+
+        let text = scene.to_json().unwrap().pretty();
+        for kind in [
+            "alpha_cutout",
+            "threshold",
+            "normal_mapped",
+            "principled",
+            "scaled",
+            "channel",
+            "image",
+        ] {
+            assert!(text.contains(&format!(r#""type": "{}""#, kind)), "{}", kind);
+        }
+        // The image every texture shares is saved once.
+        let json = Json::parse(&text).unwrap();
+        assert_eq!(json.get("images").unwrap().as_array().unwrap().len(), 1);
+        assert_eq!(text.matches("data:image/png;base64,").count(), 1);
+
+        let loaded = Scene::from_json(&json).unwrap();
+        assert_eq!(loaded.to_json().unwrap().pretty(), text);
+        check(&loaded);
+    }
+}
@@ -0,0 +1,459 @@
+use std::f64::consts::{LN_2, PI};
+use std::io;
+
+use crate::json::Json;
+use crate::material::*;
+use crate::microfacet::fresnel_dielectric;
+use crate::onb::Onb;
+use crate::random::*;
+use crate::ray::Ray;
+use crate::scene_file::{number, vec3};
+use crate::sphere::HitRecord;
+use crate::vec3::Vec3;
+
+/// Scattering orders followed on their own (R, TT, TRT); the rest are lumped
+/// into one more term.
+const P_MAX: usize = 3;
+
+/// Absorption of eumelanin, the brown to black pigment, at unit concentration.
+const EUMELANIN_SIGMA_A: [f64; 3] = [0.419, 0.697, 1.37];
+
+/// Absorption of pheomelanin, the red pigment, at unit concentration.
+const PHEOMELANIN_SIGMA_A: [f64; 3] = [0.187, 0.4, 1.05];
+
+/// Human hair and animal fur, from "A Practical and Controllable Hair and
+/// Fur Model for Production Path Tracing" (Chiang et al. 2016), following
+/// pbrt's implementation.
+///
+/// Each fiber is a rough dielectric cylinder with pigment inside. Light
+/// reflects off it, passes through it or bounces around inside it, and the
+/// tilted cuticle scales shift each of those highlights.
+///
+/// Meant for `Curves`, whose `dpdu` runs along the fiber and whose `v`
+/// gives where across the fiber it was hit.
+pub struct Hair {
+    /// Absorption per unit of fiber diameter.
+    pub sigma_a: Vec3,
+    pub index_of_refraction: f64,
+    /// Roughness along the fiber, in [0, 1].
+    pub beta_m: f64,
+    /// Roughness around the fiber, in [0, 1].
+    pub beta_n: f64,
+    /// Tilt of the cuticle scales, in degrees.
+    pub alpha: f64,
+}
+
+impl Hair {
+    /// Natural hair colored by its concentrations of eumelanin (from about
+    /// 0.3 for blonde through 1.3 for brown to 8 for black) and pheomelanin
+    /// (red hair), with typical roughness.
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64) -> Hair {
+        let [ex, ey, ez] = EUMELANIN_SIGMA_A;
+        let [px, py, pz] = PHEOMELANIN_SIGMA_A;
+        Hair::new(eumelanin * Vec3::new(ex, ey, ez) + pheomelanin * Vec3::new(px, py, pz))
+    }
+
+    /// Hair that comes out roughly `color` overall, like dyed hair or fur,
+    /// with the inversion from the paper.
+    pub fn from_color(color: Vec3) -> Hair {
+        let beta_n: f64 = 0.3;
+        let denominator = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let sigma_a = |c: f64| (c.clamp(1.0e-4, 1.0).ln() / denominator).powi(2);
+        Hair::new(Vec3::new(
+            sigma_a(color.x),
+            sigma_a(color.y),
+            sigma_a(color.z),
+        ))
+    }
+
+    pub fn new(sigma_a: Vec3) -> Hair {
+        Hair {
+            sigma_a,
+            index_of_refraction: 1.55,
+            beta_m: 0.3,
+            beta_n: 0.3,
+            alpha: 2.0,
+        }
+    }
+
+    pub fn roughness(self, beta_m: f64, beta_n: f64) -> Hair {
+        Hair {
+            beta_m,
+            beta_n,
+            ..self
+        }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Hair> {
+        Ok(Hair {
+            sigma_a: vec3(json, "sigma_a")?,
+            index_of_refraction: number(json, "index_of_refraction")?,
+            beta_m: number(json, "beta_m")?,
+            beta_n: number(json, "beta_n")?,
+            alpha: number(json, "alpha")?,
+        })
+    }
+
+    /// Frame with the fiber along x, and the lobes at the hit's offset
+    /// across the fiber.
+    fn lobes(&self, rec: &HitRecord) -> (Onb, HairLobes) {
+        let frame = Onb::build_from_wu(rec.normal, rec.dpdu);
+        (
+            frame,
+            HairLobes::new(self, (2.0 * rec.v - 1.0).clamp(-1.0, 1.0)),
+        )
+    }
+}
+
+impl Material for Hair {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (frame, lobes) = self.lobes(rec);
+        let wo = frame.to_local(-Vec3::unit_vector(r_in.direction));
+        let (wi, pdf) = lobes.sample(wo)?;
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord {
+            attenuation: lobes.f(wo, wi) / pdf,
+            scattered: Ray::new(rec.p, frame.local_vec(wi)),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let (frame, lobes) = self.lobes(rec);
+        let wo = frame.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = frame.to_local(Vec3::unit_vector(direction));
+        lobes.f(wo, wi)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "hair".into()),
+            ("sigma_a", self.sigma_a.into()),
+            ("index_of_refraction", self.index_of_refraction.into()),
+            ("beta_m", self.beta_m.into()),
+            ("beta_n", self.beta_n.into()),
+            ("alpha", self.alpha.into()),
+        ]))
+    }
+}
+
+/// The hair parameters worked out for one offset `h` across the fiber.
+/// Directions are local, with the fiber along x; θ is measured from the
+/// plane across the fiber and φ around it.
+struct HairLobes {
+    sigma_a: Vec3,
+    eta: f64,
+    h: f64,
+    gamma_o: f64,
+    /// Longitudinal variance of each lobe.
+    v: [f64; P_MAX + 1],
+    /// Logistic scale of the azimuthal lobes.
+    s: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl HairLobes {
+    fn new(hair: &Hair, h: f64) -> HairLobes {
+        let beta_m = hair.beta_m.clamp(0.01, 1.0);
+        let beta_n = hair.beta_n.clamp(0.01, 1.0);
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let s =
+            (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        // The scales tilt the R lobe by twice their angle, TT by once and
+        // TRT by four times, in the other directions.
+        let mut sin_2k_alpha = [hair.alpha.to_radians().sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [(1.0 - sin_2k_alpha[0].powi(2)).max(0.0).sqrt(), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        HairLobes {
+            sigma_a: hair.sigma_a,
+            eta: hair.index_of_refraction,
+            h,
+            gamma_o: h.asin(),
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    /// `sin θo` and `cos θo` tilted by the scales for lobe `p`.
+    fn tilt(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin_theta, cos_theta) = match p {
+            0 => (
+                sin_theta_o * self.cos_2k_alpha[1] - cos_theta_o * self.sin_2k_alpha[1],
+                cos_theta_o * self.cos_2k_alpha[1] + sin_theta_o * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_theta_o * self.cos_2k_alpha[0] + cos_theta_o * self.sin_2k_alpha[0],
+                cos_theta_o * self.cos_2k_alpha[0] - sin_theta_o * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_theta_o * self.cos_2k_alpha[2] + cos_theta_o * self.sin_2k_alpha[2],
+                cos_theta_o * self.cos_2k_alpha[2] - sin_theta_o * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta, cos_theta.abs())
+    }
+
+    /// Refracted angle around the fiber and the transmittance of one pass
+    /// through it, for light leaving at `θo`.
+    fn transmission(&self, sin_theta_o: f64, cos_theta_o: f64) -> (f64, Vec3) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+        // Modified index of refraction for the projection across the fiber.
+        let etap = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let sin_gamma_t = (self.h / etap).clamp(-1.0, 1.0);
+        let cos_gamma_t = (1.0 - sin_gamma_t * sin_gamma_t).max(0.0).sqrt();
+        let distance = 2.0 * cos_gamma_t / cos_theta_t;
+        let transmittance = Vec3::new(
+            (-self.sigma_a.x * distance).exp(),
+            (-self.sigma_a.y * distance).exp(),
+            (-self.sigma_a.z * distance).exp(),
+        );
+        (sin_gamma_t.asin(), transmittance)
+    }
+
+    /// Attenuation of each lobe from Fresnel reflection and absorption.
+    fn attenuation(&self, cos_theta_o: f64, transmittance: Vec3) -> [Vec3; P_MAX + 1] {
+        let cos_gamma_o = (1.0 - self.h * self.h).max(0.0).sqrt();
+        let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let mut ap = [Vec3::zeros(); P_MAX + 1];
+        ap[0] = f * Vec3::ones();
+        ap[1] = (1.0 - f) * (1.0 - f) * transmittance;
+        for p in 2..P_MAX {
+            ap[p] = f * transmittance * ap[p - 1];
+        }
+        // Geometric series of all the longer paths.
+        let tf = f * transmittance;
+        ap[P_MAX] = Vec3::new(
+            ap[P_MAX - 1].x * tf.x / (1.0 - tf.x),
+            ap[P_MAX - 1].y * tf.y / (1.0 - tf.y),
+            ap[P_MAX - 1].z * tf.z / (1.0 - tf.z),
+        );
+        ap
+    }
+
+    /// Probability of sampling each lobe, in proportion to its attenuation.
+    fn lobe_pdf(&self, sin_theta_o: f64, cos_theta_o: f64) -> [f64; P_MAX + 1] {
+        let (_, transmittance) = self.transmission(sin_theta_o, cos_theta_o);
+        let ap = self.attenuation(cos_theta_o, transmittance);
+        let luminance = ap.map(|a| 0.2126 * a.x + 0.7152 * a.y + 0.0722 * a.z);
+        let total: f64 = luminance.iter().sum();
+        luminance.map(|l| l / total)
+    }
+
+    /// BSDF times the cosine term.
+    fn f(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (gamma_t, transmittance) = self.transmission(sin_theta_o, cos_theta_o);
+        let ap = self.attenuation(cos_theta_o, transmittance);
+        let phi = phi_i - phi_o;
+
+        let mut f = Vec3::zeros();
+        for (p, ap) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            let m = mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            );
+            f += m * np(phi, p, self.s, self.gamma_o, gamma_t) * *ap;
+        }
+        let m = mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        );
+        f + m / (2.0 * PI) * ap[P_MAX]
+    }
+
+    /// Samples a lobe, then the angle along the fiber and the angle around
+    /// it. Returns the direction and its density, summed over the lobes.
+    fn sample(&self, wo: Vec3) -> Option<(Vec3, f64)> {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let lobe_pdf = self.lobe_pdf(sin_theta_o, cos_theta_o);
+        let mut choice = random_f64();
+        let mut p = P_MAX;
+        for (lobe, probability) in lobe_pdf.iter().enumerate() {
+            if choice < *probability {
+                p = lobe;
+                break;
+            }
+            choice -= probability;
+        }
+
+        let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let u = random_f64().max(1.0e-5);
+        let cos_theta = 1.0 + self.v[p] * (u + (1.0 - u) * (-2.0 / self.v[p]).exp()).ln();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let cos_phi = (2.0 * PI * random_f64()).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = (1.0 - sin_theta_i * sin_theta_i).max(0.0).sqrt();
+
+        let (gamma_t, _) = self.transmission(sin_theta_o, cos_theta_o);
+        let dphi = if p < P_MAX {
+            phi(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(random_f64(), self.s, -PI, PI)
+        } else {
+            2.0 * PI * random_f64()
+        };
+        let phi_i = phi_o + dphi;
+        let wi = Vec3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        );
+
+        let mut pdf = 0.0;
+        for (p, probability) in lobe_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            pdf += mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            ) * probability
+                * np(dphi, p, self.s, self.gamma_o, gamma_t);
+        }
+        pdf += mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) * lobe_pdf[P_MAX]
+            / (2.0 * PI);
+        if !pdf.is_finite() {
+            return None;
+        }
+        Some((wi, pdf))
+    }
+}
+
+/// `sin θ`, `cos θ` and `φ` of a local direction.
+fn angles(w: Vec3) -> (f64, f64, f64) {
+    let sin_theta = w.x.clamp(-1.0, 1.0);
+    (
+        sin_theta,
+        (1.0 - sin_theta * sin_theta).max(0.0).sqrt(),
+        w.z.atan2(w.y),
+    )
+}
+
+/// Longitudinal scattering, a von Mises-Fisher lobe around the cone of
+/// directions mirrored across the fiber.
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        (log_i0(a) - b - 1.0 / v + LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+/// Modified Bessel function of the first kind and order zero.
+fn i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial: f64 = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+/// Angle around the fiber that lobe `p` leaves at.
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+/// Azimuthal scattering of lobe `p`, a logistic distribution around its
+/// exit angle.
+fn np(phi_difference: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi_difference - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let e = (-x.abs() / s).exp();
+    e / (s * (1.0 + e) * (1.0 + e))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+/// Logistic distribution renormalized to [a, b].
+fn trimmed_logistic(x: f64, s: f64, a: f64, b: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64) -> f64 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Without absorption a fiber scatters all the light that reaches it. The
+    // narrow lobes of smooth hair are rarely found by uniform directions, so
+    // it takes many samples to keep the estimate well within the tolerance.
+    #[test]
+    fn test_white_furnace() {
+        const SAMPLES: usize = 400_000;
+        for beta in [0.2, 0.5, 0.8] {
+            let hair = Hair::new(Vec3::zeros()).roughness(beta, beta);
+            let mut sum = 0.0;
+            for _ in 0..SAMPLES {
+                let lobes = HairLobes::new(&hair, random_f64_range(-1.0, 1.0));
+                let wo = Vec3::unit_vector(Vec3::random_in_unit_sphere());
+                let wi = Vec3::unit_vector(Vec3::random_in_unit_sphere());
+                sum += lobes.f(wo, wi).y * 4.0 * PI;
+            }
+            let albedo = sum / SAMPLES as f64;
+            assert!((albedo - 1.0).abs() < 0.05, "{}: {}", beta, albedo);
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::bvh::Aabb;
+use crate::json::Json;
+use crate::material::Material;
+use crate::perlin::Perlin;
+use crate::ray::Ray;
+use crate::scene_file::{field, index, invalid, material, numbers, vec3};
+use crate::sphere::*;
+use crate::vec3::Vec3;
+
+/// Terrain over a rectangle of the xz plane, from a grid of heights. Each
+/// grid cell is split into two triangles, but rather than building a mesh
+/// the ray walks the cells under it in order and only tests the triangles
+/// of those it passes close enough to.
+///
+/// Normals are found from the neighbouring heights at each grid point and
+/// interpolated across the triangles. `u` goes along x and `v` along z over
+/// the whole terrain.
+pub struct Heightfield<M: Material> {
+    /// Height above `min.y` of each grid point, row by row from -z to +z.
+    heights: Vec<f64>,
+    normals: Vec<Vec3>,
+    columns: usize,
+    rows: usize,
+    pub min: Vec3,
+    pub size: Vec3,
+    pub material: M,
+}
+
+impl<M: Material> Heightfield<M> {
+    /// Terrain with its lowest corner at `min` spanning `size`, from
+    /// `heights` in [0, 1], `columns` to a row, that are scaled by `size.y`.
+    pub fn new(
+        heights: &[f64],
+        columns: usize,
+        min: Vec3,
+        size: Vec3,
+        material: M,
+    ) -> Heightfield<M> {
+        assert!(
+            columns >= 2 && heights.len() >= 2 * columns,
+            "a heightfield needs at least 2 by 2 heights"
+        );
+        let rows = heights.len() / columns;
+        let heights = heights[..rows * columns]
+            .iter()
+            .map(|h| h * size.y)
+            .collect();
+        Heightfield::from_heights(heights, columns, min, size, material)
+    }
+
+    /// Terrain from heights already scaled by `size.y`, a whole number of
+    /// rows of `columns`.
+    fn from_heights(
+        heights: Vec<f64>,
+        columns: usize,
+        min: Vec3,
+        size: Vec3,
+        material: M,
+    ) -> Heightfield<M> {
+        let rows = heights.len() / columns;
+
+        // Central differences, one-sided along the edges.
+        let (cell_x, cell_z) = (size.x / (columns - 1) as f64, size.z / (rows - 1) as f64);
+        let height = |i: usize, j: usize| heights[j * columns + i];
+        let mut normals = Vec::with_capacity(heights.len());
+        for j in 0..rows {
+            for i in 0..columns {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(columns - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(rows - 1));
+                let slope_x = (height(i1, j) - height(i0, j)) / ((i1 - i0) as f64 * cell_x);
+                let slope_z = (height(i, j1) - height(i, j0)) / ((j1 - j0) as f64 * cell_z);
+                normals.push(Vec3::unit_vector(Vec3::new(-slope_x, 1.0, -slope_z)));
+            }
+        }
+
+        Heightfield {
+            heights,
+            normals,
+            columns,
+            rows,
+            min,
+            size,
+            material,
+        }
+    }
+
+    /// Terrain from fractal Perlin noise, `octaves` layers of it each with
+    /// features half the size of the one before, starting `1 / frequency`
+    /// of the terrain apart. The heights are stretched to fill [0, 1].
+    pub fn from_noise(
+        columns: usize,
+        rows: usize,
+        frequency: f64,
+        octaves: u32,
+        min: Vec3,
+        size: Vec3,
+        material: M,
+    ) -> Heightfield<M> {
+        let noise = Perlin::new();
+        let mut heights = Vec::with_capacity(columns * rows);
+        for j in 0..rows {
+            for i in 0..columns {
+                let p = Vec3::new(
+                    frequency * i as f64 / (columns - 1) as f64,
+                    0.5,
+                    frequency * j as f64 / (rows - 1) as f64,
+                );
+                let height: f64 = (0..octaves)
+                    .map(|octave| {
+                        let scale = 2.0f64.powi(octave as i32);
+                        noise.noise(scale * p) / scale
+                    })
+                    .sum();
+                heights.push(height);
+            }
+        }
+
+        let lowest = heights.iter().cloned().fold(f64::INFINITY, f64::min);
+        let highest = heights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let range = (highest - lowest).max(1.0e-12);
+        let heights: Vec<f64> = heights.iter().map(|h| (h - lowest) / range).collect();
+        Heightfield::new(&heights, columns, min, size, material)
+    }
+
+    /// Terrain from a grayscale PGM image, white being highest. The top row
+    /// of the image is the -z edge.
+    pub fn from_pgm(
+        path: impl AsRef<Path>,
+        min: Vec3,
+        size: Vec3,
+        material: M,
+    ) -> io::Result<Heightfield<M>> {
+        let (heights, columns) = parse_pgm(&fs::read(path)?)?;
+        if columns < 2 || heights.len() < 2 * columns {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "heightmap is smaller than 2 by 2",
+            ));
+        }
+        Ok(Heightfield::new(&heights, columns, min, size, material))
+    }
+
+    fn bounds(&self) -> Aabb {
+        let highest = self.heights.iter().cloned().fold(0.0, f64::max);
+        Aabb::new(
+            self.min,
+            self.min + Vec3::new(self.size.x, highest, self.size.z),
+        )
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (
+            self.size.x / (self.columns - 1) as f64,
+            self.size.z / (self.rows - 1) as f64,
+        )
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.columns + i]
+    }
+
+    /// Closest hit on the two triangles of the cell at column `i` and row
+    /// `j`. The first triangle is below the cell's diagonal, the side of
+    /// its +x edge; the second is above it, the side of its +z edge.
+    fn hit_cell(
+        &self,
+        i: usize,
+        j: usize,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'_>> {
+        let (cell_x, cell_z) = self.cell_size();
+        let corner = Vec3::new(
+            self.min.x + i as f64 * cell_x,
+            self.min.y,
+            self.min.z + j as f64 * cell_z,
+        );
+        let (h00, h10) = (self.height(i, j), self.height(i + 1, j));
+        let (h01, h11) = (self.height(i, j + 1), self.height(i + 1, j + 1));
+        let triangles = [
+            (true, (h10 - h00) / cell_x, (h11 - h10) / cell_z),
+            (false, (h11 - h01) / cell_x, (h01 - h00) / cell_z),
+        ];
+
+        let mut closest: Option<HitRecord> = None;
+        for (lower, slope_x, slope_z) in triangles {
+            // The plane through the triangle is y = h00 + slope_x x + slope_z z
+            // from the cell's corner.
+            let o = r.origin - corner;
+            let d = r.direction;
+            let denominator = d.y - slope_x * d.x - slope_z * d.z;
+            if denominator == 0.0 {
+                continue;
+            }
+            let t = (h00 + slope_x * o.x + slope_z * o.z - o.y) / denominator;
+            let t_max = closest.as_ref().map_or(t_max, |hit| hit.t);
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let (fx, fz) = ((o.x + t * d.x) / cell_x, (o.z + t * d.z) / cell_z);
+            let inside = fx >= 0.0 && fz >= 0.0 && fx <= 1.0 && fz <= 1.0 && (fz <= fx) == lower;
+            if !inside {
+                continue;
+            }
+
+            let index = |i: usize, j: usize| j * self.columns + i;
+            let (a, b, c) = if lower {
+                (
+                    (index(i, j), 1.0 - fx),
+                    (index(i + 1, j), fx - fz),
+                    (index(i + 1, j + 1), fz),
+                )
+            } else {
+                (
+                    (index(i, j), 1.0 - fz),
+                    (index(i + 1, j + 1), fx),
+                    (index(i, j + 1), fz - fx),
+                )
+            };
+            let shading_normal = Vec3::unit_vector(
+                a.1 * self.normals[a.0] + b.1 * self.normals[b.0] + c.1 * self.normals[c.0],
+            );
+            let dpdu = self.size.x * Vec3::new(1.0, slope_x, 0.0);
+            let dpdv = self.size.z * Vec3::new(0.0, slope_z, 1.0);
+            let geometric_normal = Vec3::new(-slope_x, 1.0, -slope_z);
+
+            let front_facing = Vec3::dot(r.direction, geometric_normal) < 0.0;
+            closest = Some(HitRecord {
+                p: r.at(t),
+                normal: if front_facing {
+                    shading_normal
+                } else {
+                    -shading_normal
+                },
+                mat: &self.material,
+                t,
+                u: (i as f64 + fx) / (self.columns - 1) as f64,
+                v: (j as f64 + fz) / (self.rows - 1) as f64,
+                dpdu,
+                dpdv,
+                color: Vec3::ones(),
+                front_facing,
+            });
+        }
+        closest
+    }
+}
+
+impl Heightfield<Box<dyn Material>> {
+    /// Terrain saved by `to_json`, with its heights as they were scaled.
+    pub fn from_json(json: &Json) -> io::Result<Heightfield<Box<dyn Material>>> {
+        let heights = numbers(json, "heights")?;
+        let columns = index(json, "columns")?;
+        if columns < 2 || heights.len() < 2 * columns || heights.len() % columns != 0 {
+            return Err(invalid(format!(
+                "{} heights aren't rows of {} by at least 2 by 2",
+                heights.len(),
+                columns
+            )));
+        }
+        Ok(Heightfield::from_heights(
+            heights,
+            columns,
+            vec3(json, "min")?,
+            vec3(json, "size")?,
+            material(field(json, "material")?)?,
+        ))
+    }
+}
+
+impl<M: Material> Hittable for Heightfield<M> {
+    /// Steps through the cells under the ray as in Amanatides and Woo's grid
+    /// traversal, skipping cells whose corners are all above or all below
+    /// the ray's height across them.
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t_enter, t_exit) = self.bounds().padded(1.0e-6).clip(&r, t_min, t_max)?;
+        let (cell_x, cell_z) = self.cell_size();
+        let start = r.at(t_enter);
+        let cell = |offset: f64, size: f64, count: usize| {
+            ((offset / size).floor().max(0.0) as usize).min(count - 2)
+        };
+        let mut i = cell(start.x - self.min.x, cell_x, self.columns);
+        let mut j = cell(start.z - self.min.z, cell_z, self.rows);
+
+        // Distance along the ray to the next cell boundary on an axis, and
+        // between boundaries.
+        let axis = |origin: f64, direction: f64, min: f64, size: f64, index: usize| {
+            if direction > 0.0 {
+                (
+                    (min + (index + 1) as f64 * size - origin) / direction,
+                    size / direction,
+                    1,
+                )
+            } else if direction < 0.0 {
+                (
+                    (min + index as f64 * size - origin) / direction,
+                    -size / direction,
+                    -1,
+                )
+            } else {
+                (f64::INFINITY, f64::INFINITY, 0)
+            }
+        };
+        let (mut next_x, delta_x, step_x) = axis(r.origin.x, r.direction.x, self.min.x, cell_x, i);
+        let (mut next_z, delta_z, step_z) = axis(r.origin.z, r.direction.z, self.min.z, cell_z, j);
+
+        let mut t_cell = t_enter;
+        loop {
+            let t_next = next_x.min(next_z).min(t_exit);
+            let (y0, y1) = (r.at(t_cell).y - self.min.y, r.at(t_next).y - self.min.y);
+            let corners = [
+                self.height(i, j),
+                self.height(i + 1, j),
+                self.height(i, j + 1),
+                self.height(i + 1, j + 1),
+            ];
+            let lowest = corners.iter().cloned().fold(f64::INFINITY, f64::min);
+            let highest = corners.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            if y0.min(y1) <= highest && y0.max(y1) >= lowest {
+                if let Some(hit) = self.hit_cell(i, j, &r, t_min, t_max) {
+                    return Some(hit);
+                }
+            }
+
+            if t_next >= t_exit {
+                return None;
+            }
+            t_cell = t_next;
+            if next_x < next_z {
+                if (step_x < 0 && i == 0) || (step_x > 0 && i + 2 >= self.columns) {
+                    return None;
+                }
+                i = (i as i64 + step_x) as usize;
+                next_x += delta_x;
+            } else {
+                if (step_z < 0 && j == 0) || (step_z > 0 && j + 2 >= self.rows) {
+                    return None;
+                }
+                j = (j as i64 + step_z) as usize;
+                next_z += delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds())
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "heightfield".into()),
+            (
+                "heights",
+                Json::Array(self.heights.iter().map(|&h| h.into()).collect()),
+            ),
+            ("columns", (self.columns as f64).into()),
+            ("min", self.min.into()),
+            ("size", self.size.into()),
+            ("material", self.material.to_json()?),
+        ]))
+    }
+}
+
+/// Grayscale values in [0, 1] and the width of a binary (P5) or plain (P2)
+/// PGM image.
+fn parse_pgm(bytes: &[u8]) -> io::Result<(Vec<f64>, usize)> {
+    let invalid = |message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bad PGM heightmap: {}", message),
+        )
+    };
+
+    // The header is four whitespace separated fields with optional comments.
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while fields.len() < 4 {
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if position < bytes.len() && bytes[position] == b'#' {
+            while position < bytes.len() && bytes[position] != b'\n' {
+                position += 1;
+            }
+            continue;
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("header ends early"));
+        }
+        fields.push(String::from_utf8_lossy(&bytes[start..position]).into_owned());
+    }
+    let number = |field: &str| {
+        field
+            .parse::<usize>()
+            .map_err(|_| invalid("header field isn't a number"))
+    };
+    let (width, height, max_value) = (
+        number(&fields[1])?,
+        number(&fields[2])?,
+        number(&fields[3])?,
+    );
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("maximum value out of range"));
+    }
+    let count = width * height;
+
+    let values: Vec<usize> = match fields[0].as_str() {
+        "P2" => String::from_utf8_lossy(&bytes[position..])
+            .split_ascii_whitespace()
+            .take(count)
+            .map(number)
+            .collect::<io::Result<_>>()?,
+        "P5" => {
+            // A single whitespace byte separates the header from the pixels.
+            let data = bytes.get(position + 1..).unwrap_or(&[]);
+            if max_value < 256 {
+                data.iter().take(count).map(|&b| b as usize).collect()
+            } else {
+                data.chunks_exact(2)
+                    .take(count)
+                    .map(|b| (b[0] as usize) << 8 | b[1] as usize)
+                    .collect()
+            }
+        }
+        _ => return Err(invalid("not a P2 or P5 image")),
+    };
+    if values.len() < count {
+        return Err(invalid("fewer pixels than the header says"));
+    }
+    Ok((
+        values
+            .iter()
+            .map(|&v| v as f64 / max_value as f64)
+            .collect(),
+        width,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_parses_plain_and_binary_pgm() {
+        let (plain, width) = parse_pgm(b"P2\n# ramp\n3 2\n4\n0 1 2\n2 3 4\n").unwrap();
+        assert_eq!(width, 3);
+        assert_eq!(plain, vec![0.0, 0.25, 0.5, 0.5, 0.75, 1.0]);
+
+        let mut binary = b"P5 2 2 255\n".to_vec();
+        binary.extend([0, 51, 255, 102]);
+        let (binary, width) = parse_pgm(&binary).unwrap();
+        assert_eq!(width, 2);
+        assert_eq!(binary, vec![0.0, 0.2, 1.0, 0.4]);
+
+        assert!(parse_pgm(b"P2 3 2 4 0 1").is_err());
+    }
+
+    #[test]
+    fn test_rays_hit_a_ramp() {
+        // Rises from 0 at x = 0 to 2 at x = 4, over 4 by 4 cells.
+        let heights: Vec<f64> = (0..25).map(|k| (k % 5) as f64 / 4.0).collect();
+        let ramp = Heightfield::new(
+            &heights,
+            5,
+            Vec3::zeros(),
+            Vec3::new(4.0, 2.0, 4.0),
+            Lambertian::new(Vec3::ones()),
+        );
+
+        let down = ramp
+            .hit(
+                Ray::new(Vec3::new(3.0, 5.0, 1.3), Vec3::new(0.0, -1.0, 0.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((down.t - 3.5).abs() < 1.0e-9);
+        assert!((down.u - 0.75).abs() < 1.0e-9 && (down.v - 0.325).abs() < 1.0e-9);
+        assert!(
+            Vec3::dot(down.normal, Vec3::unit_vector(Vec3::new(-0.5, 1.0, 0.0))) > 1.0 - 1.0e-9
+        );
+
+        // Skimming along x at a height of 1 meets the slope halfway across.
+        let across = ramp
+            .hit(
+                Ray::new(Vec3::new(-1.0, 1.0, 2.7), Vec3::new(1.0, 0.0, 0.1)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((across.p.x - 2.0).abs() < 1.0e-9);
+        assert!(across.front_facing);
+    }
+}
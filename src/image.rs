@@ -0,0 +1,219 @@
+use std::io;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+/// A decoded picture, RGBA in [0, 1] as stored in the file, row by row from
+/// the top.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[f64; 4]>,
+    /// The PNG or JPEG file the pixels were decoded from.
+    pub source: Vec<u8>,
+}
+
+impl Image {
+    /// Image from the bytes of a PNG or JPEG file.
+    pub fn decode(bytes: &[u8]) -> io::Result<Image> {
+        let image = if bytes.starts_with(&PNG_SIGNATURE) {
+            decode_png(bytes)?
+        } else if bytes.starts_with(&[0xff, 0xd8]) {
+            decode_jpeg(bytes)?
+        } else {
+            return Err(invalid("not a PNG or JPEG image"));
+        };
+        Ok(Image {
+            source: bytes.to_vec(),
+            ..image
+        })
+    }
+
+    /// Image from a base64 data URI of a PNG or JPEG file.
+    pub fn from_data_uri(uri: &str) -> io::Result<Image> {
+        let encoded = uri
+            .strip_prefix("data:")
+            .and_then(|data| Some(data.split_once(";base64,")?.1))
+            .ok_or_else(|| invalid("not a base64 data URI"))?;
+        let bytes = STANDARD
+            .decode(encoded)
+            .map_err(|error| invalid(format!("bad base64 data: {}", error)))?;
+        Image::decode(&bytes)
+    }
+
+    /// The file the image was decoded from, as a base64 data URI.
+    pub fn to_data_uri(&self) -> String {
+        let mime = if self.source.starts_with(&PNG_SIGNATURE) {
+            "image/png"
+        } else {
+            "image/jpeg"
+        };
+        format!("data:{};base64,{}", mime, STANDARD.encode(&self.source))
+    }
+
+    /// Pixel at column `x` and row `y` from the top, clamped to the edges.
+    pub fn pixel(&self, x: usize, y: usize) -> [f64; 4] {
+        self.pixels[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+
+    /// Image from `channels` samples a pixel, gray, gray and alpha, RGB or
+    /// RGBA, each `max` at full intensity.
+    fn from_samples(
+        width: usize,
+        height: usize,
+        channels: usize,
+        samples: impl Iterator<Item = u16>,
+        max: f64,
+    ) -> io::Result<Image> {
+        let values: Vec<f64> = samples.map(|sample| sample as f64 / max).collect();
+        if width == 0 || height == 0 || values.len() < width * height * channels {
+            return Err(invalid("fewer pixels than the image's size"));
+        }
+        let pixels = values
+            .chunks_exact(channels)
+            .take(width * height)
+            .map(|v| match channels {
+                1 => [v[0], v[0], v[0], 1.0],
+                2 => [v[0], v[0], v[0], v[1]],
+                3 => [v[0], v[1], v[2], 1.0],
+                _ => [v[0], v[1], v[2], v[3]],
+            })
+            .collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+            source: Vec::new(),
+        })
+    }
+}
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+fn invalid(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("bad image: {}", message),
+    )
+}
+
+fn decode_png(bytes: &[u8]) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes, transparency chunks and depths under 8 bits all come out as
+    // plain 8 bit samples.
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut data).map_err(invalid)?;
+    let data = &data[..frame.buffer_size()];
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let channels = frame.color_type.samples();
+    match frame.bit_depth {
+        png::BitDepth::Sixteen => Image::from_samples(
+            width,
+            height,
+            channels,
+            data.chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]])),
+            u16::MAX as f64,
+        ),
+        _ => Image::from_samples(
+            width,
+            height,
+            channels,
+            data.iter().map(|&b| b as u16),
+            u8::MAX as f64,
+        ),
+    }
+}
+
+fn decode_jpeg(bytes: &[u8]) -> io::Result<Image> {
+    let mut decoder = jpeg_decoder::Decoder::new(bytes);
+    let data = decoder.decode().map_err(invalid)?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| invalid("JPEG has no frame header"))?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => Image::from_samples(
+            width,
+            height,
+            1,
+            data.iter().map(|&b| b as u16),
+            u8::MAX as f64,
+        ),
+        jpeg_decoder::PixelFormat::L16 => Image::from_samples(
+            width,
+            height,
+            1,
+            data.chunks_exact(2)
+                .map(|b| u16::from_ne_bytes([b[0], b[1]])),
+            u16::MAX as f64,
+        ),
+        jpeg_decoder::PixelFormat::RGB24 => Image::from_samples(
+            width,
+            height,
+            3,
+            data.iter().map(|&b| b as u16),
+            u8::MAX as f64,
+        ),
+        jpeg_decoder::PixelFormat::CMYK32 => Err(invalid("CMYK JPEGs aren't supported")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_stored_and_compressed_png() {
+        // The same 2 by 2 RGBA image, red, green / blue, half transparent
+        // white, stored and then compressed with Sub and Paeth filters.
+        let stored: &[u8] = &[
+            137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 2, 0, 0, 0, 2,
+            8, 6, 0, 0, 0, 114, 182, 13, 36, 0, 0, 0, 29, 73, 68, 65, 84, 120, 1, 1, 18, 0, 237,
+            255, 0, 255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255, 128, 73, 73,
+            9, 120, 75, 217, 206, 3, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+        ];
+        let compressed: &[u8] = &[
+            137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 2, 0, 0, 0, 2,
+            8, 6, 0, 0, 0, 114, 182, 13, 36, 0, 0, 0, 23, 73, 68, 65, 84, 120, 218, 99, 252, 207,
+            192, 240, 159, 17, 72, 176, 48, 50, 252, 7, 66, 134, 70, 0, 53, 169, 5, 132, 145, 162,
+            5, 100, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+        ];
+        for bytes in [stored, compressed] {
+            let image = Image::decode(bytes).unwrap();
+            assert_eq!((image.width, image.height), (2, 2));
+            assert_eq!(image.pixel(0, 0), [1.0, 0.0, 0.0, 1.0]);
+            assert_eq!(image.pixel(1, 0), [0.0, 1.0, 0.0, 1.0]);
+            assert_eq!(image.pixel(0, 1), [0.0, 0.0, 1.0, 1.0]);
+            assert_eq!(image.pixel(1, 1), [1.0, 1.0, 1.0, 128.0 / 255.0]);
+        }
+
+        assert!(Image::decode(&stored[..60]).is_err());
+        assert!(Image::decode(&[0xff, 0xd8, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_decodes_jpeg() {
+        // An 8 by 8 baseline JPEG of pure red: one block each of Y, Cb and Cr
+        // holding only a DC coefficient.
+        let mut jpeg = vec![255, 216, 255, 219, 0, 67, 0];
+        jpeg.extend([8; 64]);
+        jpeg.extend([
+            255, 192, 0, 17, 8, 0, 8, 0, 8, 3, 1, 17, 0, 2, 17, 0, 3, 17, 0, 255, 196, 0, 21, 0, 0,
+            2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6, 7, 255, 196, 0, 20, 16, 1, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 218, 0, 12, 3, 1, 0, 2, 0, 3, 0, 0, 63, 0, 11,
+            10, 31, 239, 255, 217,
+        ]);
+        let image = Image::decode(&jpeg).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        for pixel in &image.pixels {
+            let expected = [1.0, 0.0, 0.0, 1.0];
+            assert!(pixel
+                .iter()
+                .zip(expected)
+                .all(|(a, b)| (a - b).abs() < 2.0 / 255.0));
+        }
+    }
+}
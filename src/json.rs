@@ -0,0 +1,246 @@
+use std::fmt;
+use std::io;
+
+/// A parsed JSON value. Objects keep their members in file order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses `text` with serde_json, which also bounds how deeply arrays and
+    /// objects may nest.
+    pub fn parse(text: &str) -> io::Result<Json> {
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|error| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("bad JSON: {}", error))
+        })?;
+        Ok(Json::from(value))
+    }
+
+    /// Member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Element `index` of an array.
+    pub fn at(&self, index: usize) -> Option<&Json> {
+        self.as_array()?.get(index)
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as usize)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array, none for anything else.
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    /// An array of numbers.
+    pub fn as_f64s(&self) -> Option<Vec<f64>> {
+        self.as_array()?.iter().map(Json::as_f64).collect()
+    }
+
+    pub fn object(members: impl IntoIterator<Item = (&'static str, Json)>) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Text of the value, with arrays and objects that don't fit on a line
+    /// spread over several, one element per line. Numbers are written so
+    /// they parse back to exactly the same value.
+    pub fn pretty(&self) -> String {
+        let mut text = String::new();
+        self.write_pretty(&mut text, 0);
+        text.push('\n');
+        text
+    }
+
+    fn write_pretty(&self, text: &mut String, indent: usize) {
+        let compact = self.to_string();
+        let elements: Vec<(Option<&str>, &Json)> = match self {
+            Json::Array(elements) => elements.iter().map(|element| (None, element)).collect(),
+            Json::Object(members) => members
+                .iter()
+                .map(|(name, value)| (Some(name.as_str()), value))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if indent + compact.len() <= PRETTY_WIDTH || elements.is_empty() {
+            text.push_str(&compact);
+            return;
+        }
+
+        let (open, close) = if matches!(self, Json::Array(_)) {
+            ('[', ']')
+        } else {
+            ('{', '}')
+        };
+        text.push(open);
+        for (i, (name, value)) in elements.iter().enumerate() {
+            text.push('\n');
+            text.push_str(&" ".repeat(indent + PRETTY_INDENT));
+            if let Some(name) = name {
+                write_string(text, name);
+                text.push_str(": ");
+            }
+            value.write_pretty(text, indent + PRETTY_INDENT);
+            if i + 1 < elements.len() {
+                text.push(',');
+            }
+        }
+        text.push('\n');
+        text.push_str(&" ".repeat(indent));
+        text.push(close);
+    }
+}
+
+/// Longest line `Json::pretty` keeps an array or object on.
+const PRETTY_WIDTH: usize = 100;
+
+const PRETTY_INDENT: usize = 2;
+
+impl From<f64> for Json {
+    fn from(number: f64) -> Json {
+        Json::Number(number)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(string: &str) -> Json {
+        Json::String(string.to_string())
+    }
+}
+
+impl From<serde_json::Value> for Json {
+    fn from(value: serde_json::Value) -> Json {
+        match value {
+            serde_json::Value::Null => Json::Null,
+            serde_json::Value::Bool(value) => Json::Bool(value),
+            serde_json::Value::Number(number) => number.as_f64().map_or(Json::Null, Json::Number),
+            serde_json::Value::String(string) => Json::String(string),
+            serde_json::Value::Array(elements) => {
+                Json::Array(elements.into_iter().map(Json::from).collect())
+            }
+            serde_json::Value::Object(members) => Json::Object(
+                members
+                    .into_iter()
+                    .map(|(name, value)| (name, Json::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Compact JSON on one line. Numbers that JSON can't hold, infinities and
+/// NaN, are written as `null`.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) if number.is_finite() => write!(f, "{}", number),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(string) => {
+                let mut text = String::new();
+                write_string(&mut text, string);
+                f.write_str(&text)
+            }
+            Json::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{}", separator, element)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    let mut text = String::from(if i == 0 { "" } else { ", " });
+                    write_string(&mut text, name);
+                    write!(f, "{}: {}", text, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(text: &mut String, string: &str) {
+    text.push_str(&serde_json::Value::from(string).to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_nested_values() {
+        let text =
+            r#" {"a": [1, -2.5e1, true, null], "b": {"c": "x\"\u00e9\ud83d\ude00"}, "d": []} "#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("a").unwrap().as_f64s(), None);
+        assert_eq!(json.get("a").unwrap().at(1).unwrap().as_f64(), Some(-25.0));
+        assert_eq!(json.get("a").unwrap().at(2).unwrap().as_bool(), Some(true));
+        assert_eq!(
+            json.get("b").unwrap().get("c").unwrap().as_str(),
+            Some("x\"é😀")
+        );
+        assert_eq!(json.get("d").unwrap().as_array().unwrap().len(), 0);
+        assert!(json.get("e").is_none());
+
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("{\"a\" 1}").is_err());
+        assert!(Json::parse("[1] 2").is_err());
+        // Nesting too deep to follow is an error, not a stack overflow.
+        assert!(Json::parse(&"[".repeat(100_000)).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::io;
+
+use crate::json::Json;
+use crate::scene_file::{number, vec3};
+use crate::vec3::Vec3;
+
+/// Light reaching a shading point from one sample on a light source.
+pub struct LightSample {
+    /// Unit direction from the shading point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light along `direction`, infinite for distant lights.
+    pub distance: f64,
+    /// Incident radiance divided by the pdf of picking `direction`.
+    pub radiance: Vec3,
+}
+
+/// Light sources that are sampled explicitly with shadow rays.
+pub trait Light: Sync {
+    fn sample(&self, p: Vec3) -> Option<LightSample>;
+
+    /// The light in the scene file format, none if it can't be saved.
+    fn to_json(&self) -> Option<Json> {
+        None
+    }
+}
+
+/// Light emitted equally in all directions from a single point.
+pub struct PointLight {
+    pub position: Vec3,
+    pub intensity: Vec3,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, color: Vec3, intensity: f64) -> PointLight {
+        PointLight {
+            position,
+            intensity: intensity * color,
+        }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<PointLight> {
+        Ok(PointLight {
+            position: vec3(json, "position")?,
+            intensity: vec3(json, "intensity")?,
+        })
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.length_squared();
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / distance_squared,
+        })
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "point".into()),
+            ("position", self.position.into()),
+            ("intensity", self.intensity.into()),
+        ]))
+    }
+}
+
+/// A point light restricted to a cone around `direction`. Full intensity
+/// inside `falloff_start` degrees of the axis, smoothly fading to zero at
+/// `total_width` degrees.
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    cos_total_width: f64,
+    cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Vec3,
+        target: Vec3,
+        color: Vec3,
+        intensity: f64,
+        total_width: f64,
+        falloff_start: f64,
+    ) -> SpotLight {
+        SpotLight {
+            position,
+            direction: Vec3::unit_vector(target - position),
+            intensity: intensity * color,
+            cos_total_width: total_width.to_radians().cos(),
+            cos_falloff_start: falloff_start.min(total_width).to_radians().cos(),
+        }
+    }
+
+    /// Spot light saved by `to_json`, with the cosines of its angles as
+    /// they were rather than recomputed from degrees.
+    pub fn from_json(json: &Json) -> io::Result<SpotLight> {
+        Ok(SpotLight {
+            position: vec3(json, "position")?,
+            direction: vec3(json, "direction")?,
+            intensity: vec3(json, "intensity")?,
+            cos_total_width: number(json, "cos_total_width")?,
+            cos_falloff_start: number(json, "cos_falloff_start")?,
+        })
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta < self.cos_total_width {
+            0.0
+        } else if cos_theta >= self.cos_falloff_start {
+            1.0
+        } else {
+            let delta = (cos_theta - self.cos_total_width)
+                / (self.cos_falloff_start - self.cos_total_width);
+            delta * delta * (3.0 - 2.0 * delta)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.length_squared();
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+
+        let falloff = self.falloff(Vec3::dot(-direction, self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: falloff * self.intensity / distance_squared,
+        })
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "spot".into()),
+            ("position", self.position.into()),
+            ("direction", self.direction.into()),
+            ("intensity", self.intensity.into()),
+            ("cos_total_width", self.cos_total_width.into()),
+            ("cos_falloff_start", self.cos_falloff_start.into()),
+        ]))
+    }
+}
+
+/// Light arriving from infinitely far away along a single direction, like a
+/// sun without a visible disk.
+pub struct DirectionalLight {
+    /// Unit direction the light travels in.
+    pub direction: Vec3,
+    pub irradiance: Vec3,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, color: Vec3, intensity: f64) -> DirectionalLight {
+        DirectionalLight {
+            direction: Vec3::unit_vector(direction),
+            irradiance: intensity * color,
+        }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<DirectionalLight> {
+        Ok(DirectionalLight {
+            direction: vec3(json, "direction")?,
+            irradiance: vec3(json, "irradiance")?,
+        })
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: Vec3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+        })
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "directional".into()),
+            ("direction", self.direction.into()),
+            ("irradiance", self.irradiance.into()),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_light_inverse_square() {
+        let light = PointLight::new(Vec3::new(0.0, 4.0, 0.0), Vec3::new(1.0, 0.5, 0.25), 8.0);
+        let near = light.sample(Vec3::new(0.0, 2.0, 0.0)).unwrap();
+        let far = light.sample(Vec3::new(0.0, -4.0, 0.0)).unwrap();
+        assert!((near.distance - 2.0).abs() < 1.0e-12);
+        assert!((near.direction - Vec3::new(0.0, 1.0, 0.0)).length() < 1.0e-12);
+        assert!((near.radiance - Vec3::new(2.0, 1.0, 0.5)).length() < 1.0e-12);
+        // Four times as far, sixteen times as dim.
+        assert!((16.0 * far.radiance - near.radiance).length() < 1.0e-12);
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = SpotLight::new(
+            Vec3::zeros(),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::ones(),
+            1.0,
+            30.0,
+            20.0,
+        );
+        let at_angle = |degrees: f64| {
+            let p = Vec3::new(degrees.to_radians().sin(), -degrees.to_radians().cos(), 0.0);
+            light.sample(p).map_or(0.0, |sample| sample.radiance.x)
+        };
+
+        for degrees in [0.0, 10.0, 19.9] {
+            assert!((at_angle(degrees) - 1.0).abs() < 1.0e-12);
+        }
+        for degrees in [30.1, 60.0, 180.0] {
+            assert_eq!(0.0, at_angle(degrees));
+        }
+        assert!(light.sample(Vec3::new(0.0, 1.0, 0.0)).is_none());
+
+        // In between it fades without jumps, never getting brighter.
+        let mut previous = 1.0;
+        for k in 0..=1000 {
+            let falloff = at_angle(20.0 + 10.0 * k as f64 / 1000.0);
+            assert!(falloff <= previous && previous - falloff < 2.0e-3);
+            previous = falloff;
+        }
+    }
+
+    #[test]
+    fn test_directional_light_constant_irradiance() {
+        let light = DirectionalLight::new(Vec3::new(1.0, -2.0, 0.5), Vec3::new(0.2, 0.4, 0.6), 5.0);
+        for p in [
+            Vec3::zeros(),
+            Vec3::new(100.0, -3.0, 7.0),
+            Vec3::new(-1.0e4, 1.0e4, 0.0),
+        ] {
+            let sample = light.sample(p).unwrap();
+            assert!(sample.distance.is_infinite());
+            assert!((sample.radiance - Vec3::new(1.0, 2.0, 3.0)).length() < 1.0e-12);
+            assert!(
+                (sample.direction + Vec3::unit_vector(Vec3::new(1.0, -2.0, 0.5))).length()
+                    < 1.0e-12
+            );
+        }
+    }
+}
@@ -1,18 +1,16 @@
 use std::{
+    env,
+    f64::consts::PI,
     fs::File,
     io::{LineWriter, Write},
     sync::mpsc::channel,
     thread,
     time::Instant,
-    env,
 };
 
 use rayon::prelude::*;
 use triple_buffer::TripleBuffer;
 
-extern crate nalgebra as na;
-use na::Vector3;
-
 mod vec3;
 use vec3::Vec3;
 
@@ -32,9 +30,78 @@ use random::*;
 
 mod material;
 
+mod coated;
+
+mod microfacet;
+
+mod principled;
+
+mod texture;
+
 mod scene;
 use scene::Scene;
 
+mod scene_file;
+
+mod atmosphere;
+
+mod onb;
+
+mod sky;
+
+mod light;
+use light::Light;
+
+mod spectrum;
+use spectrum::Wavelengths;
+
+mod thin_film;
+
+mod subsurface;
+use subsurface::subsurface_walk;
+
+mod bump;
+
+mod bvh;
+
+mod mesh;
+
+mod mesh_io;
+
+mod json;
+
+mod image;
+
+mod gltf;
+
+mod pbrt;
+
+mod transform;
+
+mod perlin;
+
+mod mix;
+
+mod diffuse;
+
+mod fabric;
+
+mod curve;
+
+mod hair;
+
+mod cuboid;
+
+mod csg;
+
+mod sdf;
+
+mod quadric;
+
+mod heightfield;
+
+mod metaball;
+
 mod render;
 use render::*;
 
@@ -45,20 +112,28 @@ enum RunningMode {
 }
 
 fn main() {
-    rayon::ThreadPoolBuilder::new().num_threads(12).build_global().unwrap();
-    let args:Vec<String> = env::args().collect();
-    let mode = 
-        if args.len() < 2 {
-            RunningMode::Render
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(12)
+        .build_global()
+        .unwrap();
+    let args: Vec<String> = env::args().collect();
+    let mode = if args.len() < 2 {
+        RunningMode::Render
+    } else {
+        let flag = &args[1];
+        match flag.as_str() {
+            "-File" => RunningMode::File,
+            "-Render" => RunningMode::Render,
+            _ => RunningMode::Render,
         }
-        else {
-            let flag = &args[1];
-            match flag.as_str() {
-                "-File" => RunningMode::File,
-                "-Render" => RunningMode::Render,
-                _ => RunningMode::Render
-            }
-        };
+    };
+    let scene_name = args.get(2).cloned().unwrap_or_default();
+    let spectral = args.iter().any(|arg| arg == "-Spectral");
+    let save_path = args
+        .iter()
+        .position(|arg| arg == "-Save")
+        .and_then(|i| args.get(i + 1))
+        .cloned();
     // Image
     const ASPECT_RATIO: f64 = 16.0 / 9.0;
     const WIDTH: u32 = 1200;
@@ -76,9 +151,13 @@ fn main() {
     let render = Render::new(buffer_output, receiver);
 
     thread::spawn(move || {
-        let scene = Scene::one_weekend_scene(ASPECT_RATIO);
-        // World
-        let world = scene.objects;
+        let scene = Scene::from_name(&scene_name, ASPECT_RATIO);
+        if let Some(path) = save_path {
+            match scene.save(&path) {
+                Ok(()) => eprintln!("Saved the scene to {}", path),
+                Err(error) => eprintln!("Failed to save the scene to {}: {}", path, error),
+            }
+        }
         let camera = scene.camera;
 
         let file = File::create("image.ppm").expect("Failed to create file");
@@ -95,8 +174,12 @@ fn main() {
                 for _ in 0..SAMPLES_PER_PIXEL {
                     let u = (i as f64 + random_f64()) / ((WIDTH - 1) as f64);
                     let v = (j as f64 + random_f64()) / ((HEIGHT - 1) as f64);
-                    let ray = camera.get_ray(u, v);
-                    pixel_color += ray_color(ray, &world, MAX_DEPTH);
+                    let mut ray = camera.get_ray(u, v);
+                    if spectral {
+                        ray.wavelengths = Some(Wavelengths::sample());
+                    }
+                    let radiance = ray_color(ray, &scene, MAX_DEPTH, false);
+                    pixel_color += ray.wavelengths.map_or(radiance, |w| w.to_rgb(radiance));
                 }
                 *r = set_color(pixel_color, SAMPLES_PER_PIXEL);
             });
@@ -150,20 +233,129 @@ fn set_color(color: Vec3, samples_per_pixel: i64) -> u32 {
     (255 << 24) + (ur << 16) + (ug << 8) + ub
 }
 
-fn ray_color(r: Ray, world: &HittableList, depth: i64) -> Vec3 {
+fn ray_color(r: Ray, scene: &Scene, depth: i64, lights_sampled: bool) -> Vec3 {
     if depth <= 0 {
-        Vec3::zeros()
-    } else if let Some(hit) = world.hit(r, 0.001, f64::INFINITY) {
-        if let Some((attenuation, scattered)) = hit.mat.scatter(&r, &hit) {
-            attenuation * ray_color(scattered, world, depth - 1)
+        return Vec3::zeros();
+    }
+
+    // Colors in the scene are RGB; in spectral mode they are taken at the ray's wavelengths.
+    let wavelengths = r.wavelengths;
+    let spectrum = |rgb: Vec3| wavelengths.map_or(rgb, |w| w.upsample(rgb));
+
+    let hit = scene.objects.hit(r, 0.001, f64::INFINITY);
+
+    // The fog may scatter the ray before it reaches the next surface (or the sky).
+    if let Some(fog) = &scene.fog {
+        let t_max = hit.as_ref().map_or(f64::INFINITY, |h| h.t);
+        if let Some(t) = fog.sample_distance(&r, t_max) {
+            let p = r.at(t);
+            let direct = sample_lights(scene, p, |_| Vec3::ones() / (4.0 * PI), spectrum);
+            let mut scattered = Ray::new(p, Vec3::random_unit_vector());
+            scattered.wavelengths = wavelengths;
+            return spectrum(fog.albedo) * (direct + ray_color(scattered, scene, depth - 1, true));
+        }
+    }
+
+    // Where a surface is cut out the ray carries on as if it weren't there,
+    // which still counts against the depth so stacked cutouts end.
+    if let Some(h) = &hit {
+        if random_f64() >= h.mat.alpha(h) {
+            let mut through = Ray::new(h.p, r.direction);
+            through.wavelengths = wavelengths;
+            return ray_color(through, scene, depth - 1, lights_sampled);
+        }
+    }
+
+    // A ray that hits the inside of an object filled with a medium has been
+    // travelling through it, and wanders around until it gets back to the surface.
+    let (r, hit, throughput) = match subsurface_walk(r, hit, scene, spectrum) {
+        Some(walk) => walk,
+        None => return Vec3::zeros(),
+    };
+
+    let radiance = if let Some(hit) = hit {
+        let material_spectrum = |value: Vec3| {
+            if hit.mat.is_spectral() {
+                value
+            } else {
+                spectrum(value)
+            }
+        };
+        let direct = sample_lights(
+            scene,
+            hit.p,
+            |direction| material_spectrum(hit.mat.eval(&r, &hit, direction)),
+            spectrum,
+        );
+        if let Some(scatter) = hit.mat.scatter(&r, &hit) {
+            let mut scattered = scatter.scattered;
+            scattered.wavelengths = scattered.wavelengths.or(wavelengths);
+            direct
+                + material_spectrum(scatter.attenuation)
+                    * ray_color(scattered, scene, depth - 1, !scatter.is_specular)
         } else {
-            Vec3::zeros()
+            direct
         }
     } else {
-        let unit_direction = Vec3::unit_vector(r.direction);
-        let t = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - t) * Vec3::ones() + t * Vec3::new(0.5, 0.7, 1.0)
+        spectrum(scene.sky.radiance(r.direction, !lights_sampled))
+    };
+    throughput * radiance
+}
+
+/// Light arriving directly from the sun and the scene's lights at `p`, weighted
+/// by `f`, the BSDF (or phase function) times cosine towards a given direction.
+/// Light colors go through `spectrum` to match the path.
+fn sample_lights(
+    scene: &Scene,
+    p: Vec3,
+    f: impl Fn(Vec3) -> Vec3,
+    spectrum: impl Fn(Vec3) -> Vec3,
+) -> Vec3 {
+    let sun = scene.sky.sun().map(|sun| sun as &dyn Light);
+    let lights = scene.lights.iter().map(|light| light.as_ref());
+
+    let mut direct = Vec3::zeros();
+    for light in sun.into_iter().chain(lights) {
+        let sample = match light.sample(p) {
+            Some(sample) => sample,
+            None => continue,
+        };
+
+        let f = f(sample.direction);
+        if f.near_zero() {
+            continue;
+        }
+
+        let shadow_ray = Ray::new(p, sample.direction);
+        let visibility = visibility(scene, shadow_ray, sample.distance);
+        if visibility == 0.0 {
+            continue;
+        }
+
+        let transmittance = scene
+            .fog
+            .map_or(1.0, |fog| fog.transmittance(&shadow_ray, sample.distance));
+        direct += visibility * transmittance * f * spectrum(sample.radiance);
+    }
+
+    direct
+}
+
+/// Fraction of the light let through along `r` up to `distance` by the
+/// surfaces in between, which block it except where they are cut out.
+fn visibility(scene: &Scene, r: Ray, distance: f64) -> f64 {
+    let mut visibility = 1.0;
+    let mut r = r;
+    let mut distance = distance;
+    while let Some(hit) = scene.objects.hit(r, 0.001, distance) {
+        visibility *= 1.0 - hit.mat.alpha(&hit);
+        if visibility == 0.0 {
+            break;
+        }
+        r = Ray::new(hit.p, r.direction);
+        distance -= hit.t;
     }
+    visibility
 }
 
 // fn test_scene() -> HittableList {
@@ -0,0 +1,281 @@
+use std::f64::consts::PI;
+use std::io;
+
+use crate::bvh::*;
+use crate::json::Json;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::scene_file::{array, field, invalid, material, vec3s};
+use crate::sphere::*;
+use crate::vec3::Vec3;
+
+/// Triangle mesh with optional per-vertex normals and texture coordinates.
+pub struct Mesh<M: Material> {
+    pub positions: Vec<Vec3>,
+    /// Interpolated across each triangle for smooth shading. Flat shaded if empty.
+    pub normals: Vec<Vec3>,
+    /// Texture coordinates. Every triangle spans (0, 0), (1, 0), (1, 1) if empty.
+    pub uvs: Vec<(f64, f64)>,
+    /// Interpolated across each triangle into `HitRecord::color`. White if empty.
+    pub colors: Vec<Vec3>,
+    pub triangles: Vec<[usize; 3]>,
+    pub material: M,
+    bvh: Bvh,
+}
+
+impl<M: Material> Mesh<M> {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        triangles: Vec<[usize; 3]>,
+        material: M,
+    ) -> Mesh<M> {
+        let boxes: Vec<Aabb> = triangles
+            .iter()
+            .map(|t| Aabb::from_points(&[positions[t[0]], positions[t[1]], positions[t[2]]]))
+            .collect();
+        Mesh {
+            positions,
+            normals,
+            uvs,
+            colors: Vec::new(),
+            triangles,
+            material,
+            bvh: Bvh::new(&boxes),
+        }
+    }
+
+    /// Sphere made of `segments` quads around and `rings` from pole to pole,
+    /// with smooth normals and the same texture coordinates as `Sphere`.
+    pub fn uv_sphere(
+        center: Vec3,
+        radius: f64,
+        segments: usize,
+        rings: usize,
+        material: M,
+    ) -> Mesh<M> {
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        for ring in 0..=rings {
+            let v = ring as f64 / rings as f64;
+            let theta = PI * v;
+            for segment in 0..=segments {
+                let u = segment as f64 / segments as f64;
+                let phi = 2.0 * PI * u;
+                let n = Vec3::new(
+                    -theta.sin() * phi.cos(),
+                    -theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                positions.push(center + radius * n);
+                normals.push(n);
+                uvs.push((u, v));
+            }
+        }
+
+        let mut triangles = Vec::new();
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let i0 = ring * (segments + 1) + segment;
+                let (i1, i2) = (i0 + 1, i0 + segments + 1);
+                let i3 = i2 + 1;
+                // The triangles touching a pole would have no area.
+                if ring > 0 {
+                    triangles.push([i0, i3, i1]);
+                }
+                if ring < rings - 1 {
+                    triangles.push([i0, i2, i3]);
+                }
+            }
+        }
+
+        Mesh::new(positions, normals, uvs, triangles, material)
+    }
+
+    /// The same mesh with a color at each vertex, or white if `colors` is empty.
+    pub fn with_colors(self, colors: Vec<Vec3>) -> Mesh<M> {
+        assert!(
+            colors.is_empty() || colors.len() == self.positions.len(),
+            "{} colors for {} vertices",
+            colors.len(),
+            self.positions.len()
+        );
+        Mesh { colors, ..self }
+    }
+
+    // Möller-Trumbore ray-triangle intersection.
+    fn hit_triangle(&self, index: usize, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let [i0, i1, i2] = self.triangles[index];
+        let (p0, p1, p2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+
+        let pvec = Vec3::cross(r.direction, e2);
+        let det = Vec3::dot(e1, pvec);
+        if det.abs() < 1.0e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = r.origin - p0;
+        let b1 = Vec3::dot(tvec, pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = Vec3::cross(tvec, e1);
+        let b2 = Vec3::dot(r.direction, qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = Vec3::dot(e2, qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        // The vertex normals decide which side is outside.
+        let mut geometric_normal = Vec3::unit_vector(Vec3::cross(e1, e2));
+        let shading_normal = if self.normals.is_empty() {
+            geometric_normal
+        } else {
+            let n = b0 * self.normals[i0] + b1 * self.normals[i1] + b2 * self.normals[i2];
+            let n = Vec3::unit_vector(n);
+            if Vec3::dot(n, geometric_normal) < 0.0 {
+                geometric_normal = -geometric_normal;
+            }
+            n
+        };
+
+        let (uv0, uv1, uv2) = if self.uvs.is_empty() {
+            ((0.0, 0.0), (1.0, 0.0), (1.0, 1.0))
+        } else {
+            (self.uvs[i0], self.uvs[i1], self.uvs[i2])
+        };
+        let u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+        let v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+        let color = if self.colors.is_empty() {
+            Vec3::ones()
+        } else {
+            b0 * self.colors[i0] + b1 * self.colors[i1] + b2 * self.colors[i2]
+        };
+
+        // Solve for the tangents from the texture coordinates at the corners.
+        let (du02, dv02) = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+        let (du12, dv12) = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+        let uv_det = du02 * dv12 - dv02 * du12;
+        let (dpdu, dpdv) = if uv_det.abs() < 1.0e-12 {
+            let uvw = Onb::build_from_w(geometric_normal);
+            (uvw.u, uvw.v)
+        } else {
+            (
+                (dv12 * dp02 - dv02 * dp12) / uv_det,
+                (du02 * dp12 - du12 * dp02) / uv_det,
+            )
+        };
+
+        let front_facing = Vec3::dot(r.direction, geometric_normal) < 0.0;
+        Some(HitRecord {
+            p: r.at(t),
+            normal: if front_facing {
+                shading_normal
+            } else {
+                -shading_normal
+            },
+            mat: &self.material,
+            t,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            color,
+            front_facing,
+        })
+    }
+}
+
+impl Mesh<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Mesh<Box<dyn Material>>> {
+        let positions = vec3s(json, "positions")?;
+        let normals = vec3s(json, "normals")?;
+        let colors = vec3s(json, "colors")?;
+        let uvs = array(json, "uvs")?
+            .iter()
+            .map(|uv| match uv.as_f64s().as_deref() {
+                Some(&[u, v]) => Ok((u, v)),
+                _ => Err(invalid("\"uvs\" has an element that isn't two numbers")),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let triangles = array(json, "triangles")?
+            .iter()
+            .map(|triangle| {
+                let corners: Option<Vec<usize>> =
+                    triangle.as_array()?.iter().map(Json::as_usize).collect();
+                match corners?[..] {
+                    [a, b, c] if a.max(b).max(c) < positions.len() => Some([a, b, c]),
+                    _ => None,
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("a triangle isn't three indices of vertices"))?;
+        for (name, count) in [
+            ("normals", normals.len()),
+            ("uvs", uvs.len()),
+            ("colors", colors.len()),
+        ] {
+            if count != 0 && count != positions.len() {
+                return Err(invalid(format!(
+                    "the mesh has {} {} for {} vertices",
+                    count,
+                    name,
+                    positions.len()
+                )));
+            }
+        }
+        let material = material(field(json, "material")?)?;
+        Ok(Mesh::new(positions, normals, uvs, triangles, material).with_colors(colors))
+    }
+}
+
+impl<M: Material> Hittable for Mesh<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+        self.bvh.traverse(&r, t_min, t_max, |index, t_max| {
+            let hit = self.hit_triangle(index, &r, t_min, t_max)?;
+            let t = hit.t;
+            closest = Some(hit);
+            Some(t)
+        });
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounds()
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        let vectors = |vectors: &[Vec3]| Json::Array(vectors.iter().map(|&v| v.into()).collect());
+        let uvs = self
+            .uvs
+            .iter()
+            .map(|&(u, v)| Json::Array(vec![u.into(), v.into()]))
+            .collect();
+        let triangles = self
+            .triangles
+            .iter()
+            .map(|triangle| Json::Array(triangle.iter().map(|&i| (i as f64).into()).collect()))
+            .collect();
+        Some(Json::object([
+            ("type", "mesh".into()),
+            ("positions", vectors(&self.positions)),
+            ("normals", vectors(&self.normals)),
+            ("uvs", Json::Array(uvs)),
+            ("colors", vectors(&self.colors)),
+            ("triangles", Json::Array(triangles)),
+            ("material", self.material.to_json()?),
+        ]))
+    }
+}
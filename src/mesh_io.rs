@@ -0,0 +1,561 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::SplitAsciiWhitespace;
+
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::vec3::Vec3;
+
+/// Vertices and triangles read from a file, before they become a `Mesh`.
+/// Normals, texture coordinates and colors are empty when the file has
+/// none.
+#[derive(Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Vec3>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    pub fn into_mesh<M: Material>(self, material: M) -> Mesh<M> {
+        Mesh::new(
+            self.positions,
+            self.normals,
+            self.uvs,
+            self.triangles,
+            material,
+        )
+        .with_colors(self.colors)
+    }
+}
+
+impl<M: Material> Mesh<M> {
+    /// Mesh from a PLY file, ASCII or binary of either byte order, with the
+    /// normals, texture coordinates and vertex colors it has.
+    pub fn from_ply(path: impl AsRef<Path>, material: M) -> io::Result<Mesh<M>> {
+        Ok(parse_ply(&fs::read(path)?)?.into_mesh(material))
+    }
+
+    /// Flat shaded mesh from an ASCII or binary STL file.
+    pub fn from_stl(path: impl AsRef<Path>, material: M) -> io::Result<Mesh<M>> {
+        Ok(parse_stl(&fs::read(path)?)?.into_mesh(material))
+    }
+}
+
+fn invalid(format: &str, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("bad {} file: {}", format, message),
+    )
+}
+
+/// Type of a PLY property, or of the count and the items of a list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::Int8,
+            "uchar" | "uint8" => Scalar::Uint8,
+            "short" | "int16" => Scalar::Int16,
+            "ushort" | "uint16" => Scalar::Uint16,
+            "int" | "int32" => Scalar::Int32,
+            "uint" | "uint32" => Scalar::Uint32,
+            "float" | "float32" => Scalar::Float32,
+            "double" | "float64" => Scalar::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::Int8 | Scalar::Uint8 => 1,
+            Scalar::Int16 | Scalar::Uint16 => 2,
+            Scalar::Int32 | Scalar::Uint32 | Scalar::Float32 => 4,
+            Scalar::Float64 => 8,
+        }
+    }
+
+    /// Largest value of an integer type, which colors stored in it are
+    /// scaled by.
+    fn max_value(self) -> f64 {
+        match self {
+            Scalar::Int8 => 127.0,
+            Scalar::Uint8 => 255.0,
+            Scalar::Int16 => 32767.0,
+            Scalar::Uint16 => 65535.0,
+            Scalar::Int32 => 2147483647.0,
+            Scalar::Uint32 => 4294967295.0,
+            Scalar::Float32 | Scalar::Float64 => 1.0,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    scalar: Scalar,
+    /// Type of the item count, if this is a list.
+    count: Option<Scalar>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// The values after a PLY header, read one at a time.
+enum PlyBody<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary {
+        bytes: &'a [u8],
+        position: usize,
+        big_endian: bool,
+    },
+}
+
+impl PlyBody<'_> {
+    /// The next value, or `None` at the end of the file.
+    fn read(&mut self, scalar: Scalar) -> Option<f64> {
+        match self {
+            PlyBody::Ascii(tokens) => tokens.next()?.parse().ok(),
+            PlyBody::Binary {
+                bytes,
+                position,
+                big_endian,
+            } => {
+                let raw = bytes.get(*position..*position + scalar.size())?;
+                *position += scalar.size();
+                let mut buffer = [0u8; 8];
+                buffer[..raw.len()].copy_from_slice(raw);
+                if *big_endian {
+                    buffer[..raw.len()].reverse();
+                }
+                Some(match scalar {
+                    Scalar::Int8 => buffer[0] as i8 as f64,
+                    Scalar::Uint8 => buffer[0] as f64,
+                    Scalar::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Scalar::Uint16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Scalar::Int32 => {
+                        i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::Uint32 => {
+                        u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::Float32 => {
+                        f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::Float64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+}
+
+/// Mesh from the bytes of a PLY file. Vertices are read from `x`, `y`, `z`,
+/// `nx`, `ny`, `nz`, `u` and `v` (or `s` and `t`) and `red`, `green` and
+/// `blue`, and faces from `vertex_indices` (or `vertex_index`), split into
+/// fans of triangles. Other elements and properties are skipped.
+///
+/// Colors are taken to be gamma encoded like the rendered image, and are
+/// squared back to linear.
+pub fn parse_ply(bytes: &[u8]) -> io::Result<MeshData> {
+    let end = b"end_header";
+    let header_end = bytes
+        .windows(end.len())
+        .position(|window| window == end)
+        .ok_or_else(|| invalid("PLY", "no end_header"))?;
+    let header = String::from_utf8_lossy(&bytes[..header_end]);
+    let mut body_start = header_end + end.len();
+    if bytes.get(body_start) == Some(&b'\r') {
+        body_start += 1;
+    }
+    body_start += 1;
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(invalid("PLY", "doesn't start with \"ply\""));
+    }
+    let scalar = |name: &str| {
+        Scalar::parse(name).ok_or_else(|| invalid("PLY", format!("unknown type {}", name)))
+    };
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", name, _] => format = Some(name.to_string()),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid("PLY", format!("bad count for {}", name)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("PLY", "property before any element"))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    scalar: scalar(item)?,
+                    count: Some(scalar(count)?),
+                });
+            }
+            ["property", kind, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("PLY", "property before any element"))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    scalar: scalar(kind)?,
+                    count: None,
+                });
+            }
+            _ => {
+                return Err(invalid(
+                    "PLY",
+                    format!("unexpected header line \"{}\"", line),
+                ))
+            }
+        }
+    }
+
+    let body_bytes = bytes.get(body_start..).unwrap_or(&[]);
+    let mut body = match format.as_deref() {
+        Some("ascii") => PlyBody::Ascii(
+            std::str::from_utf8(body_bytes)
+                .map_err(|_| invalid("PLY", "ASCII data isn't text"))?
+                .split_ascii_whitespace(),
+        ),
+        Some("binary_little_endian") => PlyBody::Binary {
+            bytes: body_bytes,
+            position: 0,
+            big_endian: false,
+        },
+        Some("binary_big_endian") => PlyBody::Binary {
+            bytes: body_bytes,
+            position: 0,
+            big_endian: true,
+        },
+        Some(other) => return Err(invalid("PLY", format!("unknown format {}", other))),
+        None => return Err(invalid("PLY", "no format line")),
+    };
+
+    let mut mesh = MeshData::default();
+    for element in &elements {
+        let find = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|p| names.contains(&p.name.as_str()))
+        };
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let uv = [
+            find(&["u", "s", "texture_u", "texture_s"]),
+            find(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let color = [
+            find(&["red", "r"]),
+            find(&["green", "g"]),
+            find(&["blue", "b"]),
+        ];
+        let indices = find(&["vertex_indices", "vertex_index"]);
+
+        let mut values = vec![0.0; element.properties.len()];
+        let mut list = Vec::new();
+        for index in 0..element.count {
+            let truncated = || {
+                invalid(
+                    "PLY",
+                    format!(
+                        "truncated in {} {} of {}",
+                        element.name, index, element.count
+                    ),
+                )
+            };
+            for (k, property) in element.properties.iter().enumerate() {
+                match property.count {
+                    None => values[k] = body.read(property.scalar).ok_or_else(truncated)?,
+                    Some(count) => {
+                        let count = body.read(count).ok_or_else(truncated)?;
+                        let items = (0..count as usize)
+                            .map(|_| body.read(property.scalar))
+                            .collect::<Option<Vec<f64>>>()
+                            .ok_or_else(truncated)?;
+                        if Some(k) == indices {
+                            list = items;
+                        }
+                    }
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let vector = |fields: [Option<usize>; 3]| {
+                        Vec3::new(
+                            fields[0].map_or(0.0, |k| values[k]),
+                            fields[1].map_or(0.0, |k| values[k]),
+                            fields[2].map_or(0.0, |k| values[k]),
+                        )
+                    };
+                    mesh.positions.push(vector(position));
+                    if normal.iter().all(Option::is_some) {
+                        mesh.normals.push(vector(normal));
+                    }
+                    if let [Some(u), Some(v)] = uv {
+                        mesh.uvs.push((values[u], values[v]));
+                    }
+                    if let [Some(r), Some(g), Some(b)] = color {
+                        let channel = |k: usize| {
+                            (values[k] / element.properties[k].scalar.max_value()).powi(2)
+                        };
+                        mesh.colors
+                            .push(Vec3::new(channel(r), channel(g), channel(b)));
+                    }
+                }
+                "face" => {
+                    if indices.is_none() {
+                        return Err(invalid("PLY", "faces without vertex_indices"));
+                    }
+                    let corners = list
+                        .iter()
+                        .map(|&i| {
+                            if i >= 0.0 && i.fract() == 0.0 {
+                                Ok(i as usize)
+                            } else {
+                                Err(invalid("PLY", format!("vertex index {}", i)))
+                            }
+                        })
+                        .collect::<io::Result<Vec<usize>>>()?;
+                    for k in 1..corners.len().saturating_sub(1) {
+                        mesh.triangles
+                            .push([corners[0], corners[k], corners[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    check_indices("PLY", &mesh)?;
+    Ok(mesh)
+}
+
+/// Mesh from the bytes of an STL file. Each facet gets its own three
+/// vertices, and the facet normals in the file are ignored in favor of the
+/// winding of the vertices.
+pub fn parse_stl(bytes: &[u8]) -> io::Result<MeshData> {
+    // Binary files can start with "solid" too, so go by whether the size
+    // matches the triangle count first, and then by whether it is text.
+    let binary_count = bytes
+        .get(80..84)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let text = std::str::from_utf8(bytes)
+        .ok()
+        .filter(|text| text.trim_start().starts_with("solid"));
+    let text = match (binary_count, text) {
+        (Some(count), _) if bytes.len() == 84 + 50 * count => None,
+        (_, text) => text,
+    };
+
+    let mut mesh = MeshData::default();
+    let Some(text) = text else {
+        let count = binary_count.ok_or_else(|| invalid("STL", "truncated in the header"))?;
+        for index in 0..count {
+            let facet = bytes
+                .get(84 + 50 * index..84 + 50 * (index + 1))
+                .ok_or_else(|| {
+                    invalid("STL", format!("truncated in facet {} of {}", index, count))
+                })?;
+            let float = |offset: usize| {
+                f32::from_le_bytes([
+                    facet[offset],
+                    facet[offset + 1],
+                    facet[offset + 2],
+                    facet[offset + 3],
+                ]) as f64
+            };
+            for corner in 0..3 {
+                let offset = 12 + 12 * corner;
+                mesh.positions.push(Vec3::new(
+                    float(offset),
+                    float(offset + 4),
+                    float(offset + 8),
+                ));
+            }
+            mesh.triangles
+                .push([3 * index, 3 * index + 1, 3 * index + 2]);
+        }
+        return Ok(mesh);
+    };
+
+    let mut tokens = text.split_ascii_whitespace();
+    let mut corners = 0;
+    loop {
+        let facet = mesh.triangles.len();
+        let truncated = || invalid("STL", format!("truncated in facet {}", facet));
+        match tokens.next() {
+            Some("vertex") => {
+                let mut coordinate = || -> io::Result<f64> {
+                    let token = tokens.next().ok_or_else(truncated)?;
+                    token.parse().map_err(|_| {
+                        invalid(
+                            "STL",
+                            format!("bad coordinate \"{}\" in facet {}", token, facet),
+                        )
+                    })
+                };
+                let p = Vec3::new(coordinate()?, coordinate()?, coordinate()?);
+                mesh.positions.push(p);
+                corners += 1;
+            }
+            Some("endfacet") => {
+                if corners != 3 {
+                    return Err(invalid(
+                        "STL",
+                        format!("facet {} has {} vertices", facet, corners),
+                    ));
+                }
+                let first = mesh.positions.len() - 3;
+                mesh.triangles.push([first, first + 1, first + 2]);
+                corners = 0;
+            }
+            Some("endsolid") => break,
+            Some(_) => {}
+            None => {
+                return Err(invalid(
+                    "STL",
+                    format!("truncated after {} facets, with no endsolid", facet),
+                ))
+            }
+        }
+    }
+    Ok(mesh)
+}
+
+fn check_indices(format: &str, mesh: &MeshData) -> io::Result<()> {
+    let count = mesh.positions.len();
+    if let Some(triangle) = mesh
+        .triangles
+        .iter()
+        .find(|t| t.iter().any(|&i| i >= count))
+    {
+        return Err(invalid(
+            format,
+            format!("triangle {:?} refers past the {} vertices", triangle, count),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLY_HEADER: &str = "element vertex 3\n\
+        property float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    #[test]
+    fn test_reads_ply_in_every_format() {
+        let ascii = format!("ply\nformat ascii 1.0\ncomment a triangle\n{}0 0 0 255 0 0\n1 0 0 0 255 0\n0 1 0 0 0 255\n3 0 1 2\n", PLY_HEADER);
+        let binary = |big_endian: bool| {
+            let order = if big_endian { "big" } else { "little" };
+            let mut bytes =
+                format!("ply\nformat binary_{}_endian 1.0\n{}", order, PLY_HEADER).into_bytes();
+            for (position, color) in [
+                ([0.0f32, 0.0, 0.0], [255, 0, 0]),
+                ([1.0, 0.0, 0.0], [0, 255, 0]),
+                ([0.0, 1.0, 0.0], [0, 0, 255]),
+            ] {
+                for x in position {
+                    bytes.extend(if big_endian {
+                        x.to_be_bytes()
+                    } else {
+                        x.to_le_bytes()
+                    });
+                }
+                bytes.extend(color);
+            }
+            bytes.push(3);
+            for i in [0i32, 1, 2] {
+                bytes.extend(if big_endian {
+                    i.to_be_bytes()
+                } else {
+                    i.to_le_bytes()
+                });
+            }
+            bytes
+        };
+
+        for bytes in [ascii.into_bytes(), binary(false), binary(true)] {
+            let mesh = parse_ply(&bytes).unwrap();
+            assert_eq!(mesh.positions.len(), 3);
+            assert_eq!(mesh.positions[1].x, 1.0);
+            assert_eq!(mesh.positions[2].y, 1.0);
+            assert_eq!(mesh.colors[1].y, 1.0);
+            assert_eq!(mesh.colors[1].x, 0.0);
+            assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        }
+
+        // Cut off halfway through the face.
+        let mut truncated = binary(false);
+        truncated.truncate(truncated.len() - 5);
+        let error = parse_ply(&truncated).unwrap_err();
+        assert!(error.to_string().contains("truncated in face 0 of 1"));
+
+        for corners in ["3 0 1 -2", "3 0 1 1.5"] {
+            let bad = format!(
+                "ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n1 0 0 0 255 0\n0 1 0 0 0 255\n{}\n",
+                PLY_HEADER, corners
+            );
+            let error = parse_ply(bad.as_bytes()).unwrap_err();
+            assert!(error.to_string().contains("vertex index"));
+        }
+    }
+
+    #[test]
+    fn test_reads_stl_in_both_formats() {
+        let ascii = "solid tri\nfacet normal 0 0 1\nouter loop\n\
+            vertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid tri\n";
+        let mesh = parse_stl(ascii.as_bytes()).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert_eq!(mesh.positions[1].x, 1.0);
+        assert!(parse_stl(&ascii.as_bytes()[..60]).is_err());
+
+        // Headers of binary files often start with "solid" as well.
+        let mut binary = b"solid exported by a CAD package".to_vec();
+        binary.resize(80, 0);
+        binary.extend(1u32.to_le_bytes());
+        for x in [
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            binary.extend(x.to_le_bytes());
+        }
+        binary.extend([0, 0]);
+        let mesh = parse_stl(&binary).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert_eq!(mesh.positions[2].y, 1.0);
+
+        binary.pop();
+        let error = parse_stl(&binary).unwrap_err();
+        assert!(error.to_string().contains("truncated in facet 0 of 1"));
+    }
+}
@@ -0,0 +1,304 @@
+use std::io;
+
+use crate::bvh::Aabb;
+use crate::json::Json;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::quadric::solve_quadratic;
+use crate::ray::Ray;
+use crate::scene_file::{array, field, material, number, vec3};
+use crate::sphere::*;
+use crate::vec3::Vec3;
+
+/// Steepest the falloff (1 - r²/R²)³ gets, times `radius`, reached at
+/// r = R / √5.
+const MAX_SLOPE: f64 = 96.0 / (25.0 * 2.236_067_977_499_79);
+
+/// Shortest step taken along a ray, so grazing rays still make progress.
+const MIN_STEP: f64 = 1.0e-4;
+
+/// Bisections of a bracketed crossing, each halving it.
+const BISECTIONS: usize = 40;
+
+/// A center of the field, adding `weight` (1 - r²/R²)³ at distance r within
+/// its `radius` R and nothing beyond. A negative weight carves into the
+/// others.
+#[derive(Clone, Copy, Debug)]
+pub struct Metaball {
+    pub center: Vec3,
+    pub radius: f64,
+    pub weight: f64,
+}
+
+impl Metaball {
+    pub fn new(center: Vec3, radius: f64, weight: f64) -> Metaball {
+        Metaball {
+            center,
+            radius,
+            weight,
+        }
+    }
+
+    fn field(&self, p: Vec3) -> f64 {
+        let s = (p - self.center).length_squared() / (self.radius * self.radius);
+        if s >= 1.0 {
+            0.0
+        } else {
+            self.weight * (1.0 - s).powi(3)
+        }
+    }
+
+    fn gradient(&self, p: Vec3) -> Vec3 {
+        let offset = p - self.center;
+        let s = offset.length_squared() / (self.radius * self.radius);
+        if s >= 1.0 {
+            Vec3::zeros()
+        } else {
+            (-6.0 * self.weight * (1.0 - s).powi(2) / (self.radius * self.radius)) * offset
+        }
+    }
+}
+
+/// Blobby surface where the summed field of the balls equals `threshold`,
+/// the balls melting into each other as they come close ("Soft Objects",
+/// Wyvill et al. 1986). The inside is where the field is above it.
+///
+/// Each ball only reaches so far, so the ray is split where it enters and
+/// leaves them into stretches with a fixed set of balls. Over each stretch
+/// the field can't change faster than the sum of their steepest slopes, so
+/// the ray steps by how far the field is from the threshold over that,
+/// which can't skip a crossing, until one step brackets it. The crossing is
+/// then found by bisection. Normals come from the field's exact gradient.
+pub struct Metaballs<M: Material> {
+    pub balls: Vec<Metaball>,
+    pub threshold: f64,
+    pub material: M,
+    bounds: Aabb,
+}
+
+impl<M: Material> Metaballs<M> {
+    pub fn new(balls: Vec<Metaball>, threshold: f64, material: M) -> Metaballs<M> {
+        let bounds = balls.iter().fold(Aabb::empty(), |bounds, ball| {
+            bounds.union(&Aabb::from_points(&[ball.center]).padded(ball.radius))
+        });
+        Metaballs {
+            balls,
+            threshold,
+            material,
+            bounds,
+        }
+    }
+
+    /// How far the field at `p` is above the threshold, from the balls at
+    /// `active` in `balls`.
+    fn excess(&self, active: &[usize], p: Vec3) -> f64 {
+        active.iter().map(|&k| self.balls[k].field(p)).sum::<f64>() - self.threshold
+    }
+
+    /// First crossing of the surface along `r` from `start` to `end`, using
+    /// only the balls at `active`.
+    fn march(&self, active: &[usize], r: &Ray, start: f64, end: f64) -> Option<f64> {
+        let length = r.direction.length();
+        let slope: f64 = active
+            .iter()
+            .map(|&k| MAX_SLOPE * self.balls[k].weight.abs() / self.balls[k].radius)
+            .sum::<f64>()
+            * length;
+
+        let mut t = start;
+        let mut excess = self.excess(active, r.at(t));
+        while t < end {
+            let next = (t + (excess.abs() / slope).max(MIN_STEP / length)).min(end);
+            let next_excess = self.excess(active, r.at(next));
+            if (next_excess > 0.0) != (excess > 0.0) {
+                let (mut low, mut high) = (t, next);
+                for _ in 0..BISECTIONS {
+                    let middle = 0.5 * (low + high);
+                    if (self.excess(active, r.at(middle)) > 0.0) == (excess > 0.0) {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                return Some(0.5 * (low + high));
+            }
+            t = next;
+            excess = next_excess;
+        }
+        None
+    }
+}
+
+impl Metaballs<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Metaballs<Box<dyn Material>>> {
+        let balls = array(json, "balls")?
+            .iter()
+            .map(|ball| {
+                Ok(Metaball::new(
+                    vec3(ball, "center")?,
+                    number(ball, "radius")?,
+                    number(ball, "weight")?,
+                ))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Metaballs::new(
+            balls,
+            number(json, "threshold")?,
+            material(field(json, "material")?)?,
+        ))
+    }
+}
+
+impl<M: Material> Hittable for Metaballs<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (start, end) = self.bounds.clip(&r, t_min, t_max)?;
+
+        // Where the ray enters and leaves each ball's reach, within the box.
+        let mut events = vec![start, end];
+        let mut reaches = Vec::new();
+        for (k, ball) in self.balls.iter().enumerate() {
+            let oc = r.origin - ball.center;
+            let roots = solve_quadratic(
+                r.direction.length_squared(),
+                2.0 * Vec3::dot(oc, r.direction),
+                oc.length_squared() - ball.radius * ball.radius,
+            );
+            if let [enter, exit] = roots[..] {
+                if exit > start && enter < end {
+                    events.extend([enter.max(start), exit.min(end)]);
+                    reaches.push((k, enter, exit));
+                }
+            }
+        }
+        events.sort_by(f64::total_cmp);
+
+        let t = events.windows(2).find_map(|stretch| {
+            let (from, to) = (stretch[0], stretch[1]);
+            let middle = 0.5 * (from + to);
+            let active: Vec<usize> = reaches
+                .iter()
+                .filter(|&&(_, enter, exit)| enter <= middle && middle <= exit)
+                .map(|&(k, _, _)| k)
+                .collect();
+            if to <= from || active.is_empty() {
+                return None;
+            }
+            self.march(&active, &r, from, to)
+        })?;
+
+        let p = r.at(t);
+        let gradient = self
+            .balls
+            .iter()
+            .fold(Vec3::zeros(), |sum, ball| sum + ball.gradient(p));
+        let outward_normal = if gradient.near_zero() {
+            -Vec3::unit_vector(r.direction)
+        } else {
+            -Vec3::unit_vector(gradient)
+        };
+        let tangents = Onb::build_from_w(outward_normal);
+        let mut hit_record = HitRecord {
+            p,
+            normal: outward_normal,
+            mat: &self.material,
+            t,
+            u: 0.0,
+            v: 0.0,
+            dpdu: tangents.u,
+            dpdv: tangents.v,
+            color: Vec3::ones(),
+            front_facing: false,
+        };
+        hit_record.set_face_normal(r, outward_normal);
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        let balls = self
+            .balls
+            .iter()
+            .map(|ball| {
+                Json::object([
+                    ("center", ball.center.into()),
+                    ("radius", ball.radius.into()),
+                    ("weight", ball.weight.into()),
+                ])
+            })
+            .collect();
+        Some(Json::object([
+            ("type", "metaballs".into()),
+            ("balls", Json::Array(balls)),
+            ("threshold", self.threshold.into()),
+            ("material", self.material.to_json()?),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_single_ball_is_a_sphere() {
+        // (1 - r²/4)³ = 1/8 at r = √2.
+        let ball = Metaballs::new(
+            vec![Metaball::new(Vec3::zeros(), 2.0, 1.0)],
+            0.125,
+            Lambertian::new(Vec3::ones()),
+        );
+        let hit = ball
+            .hit(
+                Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.p.z - 2.0f64.sqrt()).abs() < 1.0e-9);
+        assert!(Vec3::dot(hit.normal, Vec3::new(0.0, 0.0, 1.0)) > 1.0 - 1.0e-9);
+
+        // From the center, the ray finds the surface facing back at it.
+        let hit = ball
+            .hit(
+                Ray::new(Vec3::zeros(), Vec3::new(1.0, 0.0, 0.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 2.0f64.sqrt()).abs() < 1.0e-9);
+        assert!(!hit.front_facing);
+    }
+
+    #[test]
+    fn test_close_balls_merge() {
+        // Alone, each ball would reach √2 from its center and leave a gap
+        // between them, but together they are joined through the middle.
+        let balls = vec![
+            Metaball::new(Vec3::new(-1.5, 0.0, 0.0), 2.0, 1.0),
+            Metaball::new(Vec3::new(1.5, 0.0, 0.0), 2.0, 1.0),
+        ];
+        let blob = Metaballs::new(balls, 0.125, Lambertian::new(Vec3::ones()));
+        let hit = blob
+            .hit(
+                Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!(hit.p.y > 0.0 && hit.p.y < 2.0);
+        assert!(Vec3::dot(hit.normal, Vec3::new(0.0, 1.0, 0.0)) > 1.0 - 1.0e-9);
+
+        // A ray that passes beside both misses.
+        assert!(blob
+            .hit(
+                Ray::new(Vec3::new(0.0, 5.0, 2.0), Vec3::new(0.0, -1.0, 0.0)),
+                0.001,
+                f64::INFINITY
+            )
+            .is_none());
+    }
+}
@@ -0,0 +1,283 @@
+use std::f64::consts::PI;
+use std::ops;
+
+use crate::random::*;
+use crate::vec3::Vec3;
+
+// Directions passed to the functions below live in the local shading frame,
+// where the surface normal is +z.
+
+/// Trowbridge-Reitz (GGX) microfacet distribution, with separate roughness
+/// along the two tangent directions for anisotropic surfaces.
+#[derive(Clone, Copy, Debug)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    /// Below this alpha the surface is treated as perfectly smooth.
+    const SMOOTH_ALPHA: f64 = 1.0e-3;
+
+    /// Maps perceptual roughness in [0, 1] to the distribution's alpha (`roughness^2`).
+    pub fn from_roughness(roughness_x: f64, roughness_y: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz {
+            alpha_x: roughness_x.clamp(0.0, 1.0).powi(2),
+            alpha_y: roughness_y.clamp(0.0, 1.0).powi(2),
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < Self::SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals `wm`.
+    pub fn d(&self, wm: Vec3) -> f64 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (wm.x / self.alpha_x).powi(2) + (wm.y / self.alpha_y).powi(2) + wm.z * wm.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// Smith's auxiliary function for the masking of direction `w`.
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+        let alpha2_tan2 =
+            ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / (w.z * w.z);
+        ((1.0 + alpha2_tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated masking-shadowing for the pair of directions.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `wo`, the pdf of `sample_wm`.
+    pub fn visible_d(&self, wo: Vec3, wm: Vec3) -> f64 {
+        let cos_o = wo.z.abs();
+        if cos_o == 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * Vec3::dot(wo, wm).abs() * self.d(wm) / cos_o
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible
+    /// from `wo` ("Sampling the GGX Distribution of Visible Normals", Heitz 2018).
+    pub fn sample_wm(&self, wo: Vec3) -> Vec3 {
+        let wo = if wo.z < 0.0 { -wo } else { wo };
+
+        // Stretch the view direction so the distribution becomes a hemisphere.
+        let vh = Vec3::unit_vector(Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z));
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(vh, t1);
+
+        // Uniform point on the projected disk, warped towards the visible half.
+        let r = random_f64().sqrt();
+        let phi = 2.0 * PI * random_f64();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        Vec3::unit_vector(Vec3::new(
+            self.alpha_x * nh.x,
+            self.alpha_y * nh.y,
+            nh.z.max(1.0e-6),
+        ))
+    }
+}
+
+/// Unpolarized Fresnel reflectance at a smooth boundary between dielectrics,
+/// where `eta` is the ratio of the index of refraction on the far side of the
+/// boundary to the one on the side `cos_theta_i` is measured from.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_theta_i = cos_theta_i.min(1.0);
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Refracts `wo` (pointing away from the surface, on the side `n` faces)
+/// through a boundary with relative index of refraction `eta`. Returns `None`
+/// on total internal reflection.
+pub fn refract(wo: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = Vec3::dot(wo, n);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-wo / eta + (cos_theta_i / eta - cos_theta_t) * n)
+}
+
+/// Unpolarized Fresnel reflectance of a conductor with complex index of
+/// refraction `eta + i k`, for light arriving at `cos_theta_i` to the normal.
+pub fn fresnel_complex(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta_i = Complex::new(cos_theta_i.clamp(0.0, 1.0), 0.0);
+    let eta = Complex::new(eta, k);
+
+    let sin2_theta_i = Complex::new(1.0, 0.0) - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    let cos_theta_t = (Complex::new(1.0, 0.0) - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel.norm() + r_perpendicular.norm()) / 2.0
+}
+
+pub fn fresnel_complex_rgb(cos_theta_i: f64, eta: Vec3, k: Vec3) -> Vec3 {
+    Vec3::new(
+        fresnel_complex(cos_theta_i, eta.x, k.x),
+        fresnel_complex(cos_theta_i, eta.y, k.y),
+        fresnel_complex(cos_theta_i, eta.z, k.z),
+    )
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    /// Squared magnitude.
+    fn norm(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn sqrt(self) -> Complex {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl ops::Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let scale = 1.0 / other.norm();
+        Complex::new(
+            scale * (self.re * other.re + self.im * other.im),
+            scale * (self.im * other.re - self.re * other.im),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_normalized() {
+        // The projected area of the microfacets must equal the macro surface.
+        let distribution = TrowbridgeReitz::from_roughness(0.5, 0.8);
+        let n = 400;
+        let mut integral = 0.0;
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI / 2.0;
+            for j in 0..n {
+                let phi = (j as f64 + 0.5) / n as f64 * 2.0 * PI;
+                let wm = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                integral += distribution.d(wm) * wm.z * theta.sin();
+            }
+        }
+        integral *= (PI / 2.0 / n as f64) * (2.0 * PI / n as f64);
+
+        assert!((integral - 1.0).abs() < 1.0e-2);
+    }
+
+    #[test]
+    fn test_fresnel_complex_normal_incidence() {
+        let (eta, k) = (0.2, 3.9);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+
+        assert!((fresnel_complex(1.0, eta, k) - expected).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn test_fresnel_dielectric_matches_complex() {
+        for cos_theta in [0.1, 0.5, 0.9, 1.0] {
+            let dielectric = fresnel_dielectric(cos_theta, 1.5);
+            let complex = fresnel_complex(cos_theta, 1.5, 0.0);
+            assert!((dielectric - complex).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn test_fresnel_dielectric_total_internal_reflection() {
+        assert_eq!(1.0, fresnel_dielectric(0.3, 1.0 / 1.5));
+    }
+
+    #[test]
+    fn test_fresnel_complex_grazing() {
+        assert!((fresnel_complex(0.0, 1.5, 0.0) - 1.0).abs() < 1.0e-12);
+    }
+}
@@ -0,0 +1,257 @@
+use std::io;
+use std::sync::Arc;
+
+use crate::json::Json;
+use crate::material::*;
+use crate::random::*;
+use crate::ray::Ray;
+use crate::scene_file::{field, material, texture};
+use crate::sphere::HitRecord;
+use crate::subsurface::HomogeneousMedium;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+/// Blend of two materials, `b` weighted by `weight` (read from the first
+/// channel) and `a` by the rest, like paint partly worn off metal.
+pub struct Mix<A: Material, B: Material> {
+    pub a: A,
+    pub b: B,
+    pub weight: Arc<dyn Texture>,
+}
+
+impl<A: Material, B: Material> Mix<A, B> {
+    pub fn new(a: A, b: B, weight: Arc<dyn Texture>) -> Mix<A, B> {
+        Mix { a, b, weight }
+    }
+
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        self.weight.value(rec.u, rec.v, rec.p).x.clamp(0.0, 1.0)
+    }
+}
+
+impl Mix<Box<dyn Material>, Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Mix<Box<dyn Material>, Box<dyn Material>>> {
+        Ok(Mix::new(
+            material(field(json, "a")?)?,
+            material(field(json, "b")?)?,
+            texture(field(json, "weight")?)?,
+        ))
+    }
+}
+
+/// `value` from `material` at the ray's wavelengths when the other side of
+/// a mix is spectral.
+fn to_path_space(material: &dyn Material, r_in: &Ray, value: Vec3) -> Vec3 {
+    match r_in.wavelengths {
+        Some(wavelengths) if !material.is_spectral() => wavelengths.upsample(value),
+        _ => value,
+    }
+}
+
+impl<A: Material, B: Material> Material for Mix<A, B> {
+    /// Scatters off one of the materials, picked with the probability of its
+    /// weight, so the picked material's own weight applies unchanged.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (material, scatter): (&dyn Material, _) = if random_f64() < self.weight(rec) {
+            (&self.b, self.b.scatter(r_in, rec)?)
+        } else {
+            (&self.a, self.a.scatter(r_in, rec)?)
+        };
+        if !self.is_spectral() {
+            return Some(scatter);
+        }
+        Some(ScatterRecord {
+            attenuation: to_path_space(material, r_in, scatter.attenuation),
+            ..scatter
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let weight = self.weight(rec);
+        let mut a = self.a.eval(r_in, rec, direction);
+        let mut b = self.b.eval(r_in, rec, direction);
+        if self.is_spectral() {
+            a = to_path_space(&self.a, r_in, a);
+            b = to_path_space(&self.b, r_in, b);
+        }
+        (1.0 - weight) * a + weight * b
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.a.is_spectral() || self.b.is_spectral()
+    }
+
+    /// What fills an object can't vary over its surface, so it is `a`'s
+    /// medium, or `b`'s if `a` has none.
+    fn medium(&self) -> Option<&HomogeneousMedium> {
+        self.a.medium().or_else(|| self.b.medium())
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f64 {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.a.alpha(rec) + weight * self.b.alpha(rec)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "mix".into()),
+            ("a", self.a.to_json()?),
+            ("b", self.b.to_json()?),
+            ("weight", self.weight.to_json()?),
+        ]))
+    }
+}
+
+/// Any material with parts cut out of it by `alpha` (read from the first
+/// channel), like the outline of a leaf on a single quad. Rays pass through
+/// where it is zero as if the surface weren't there.
+pub struct AlphaCutout<M: Material> {
+    pub base: M,
+    pub alpha: Arc<dyn Texture>,
+}
+
+impl<M: Material> AlphaCutout<M> {
+    pub fn new(base: M, alpha: Arc<dyn Texture>) -> AlphaCutout<M> {
+        AlphaCutout { base, alpha }
+    }
+}
+
+impl AlphaCutout<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<AlphaCutout<Box<dyn Material>>> {
+        Ok(AlphaCutout::new(
+            material(field(json, "base")?)?,
+            texture(field(json, "alpha")?)?,
+        ))
+    }
+}
+
+impl<M: Material> Material for AlphaCutout<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.base.scatter(r_in, rec)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.base.eval(r_in, rec, direction)
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+
+    fn medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.medium()
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f64 {
+        self.alpha.value(rec.u, rec.v, rec.p).x.clamp(0.0, 1.0) * self.base.alpha(rec)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "alpha_cutout".into()),
+            ("base", self.base.to_json()?),
+            ("alpha", self.alpha.to_json()?),
+        ]))
+    }
+}
+
+/// Any material with what it reflects and transmits multiplied by the color
+/// the hit carries from the vertices of a mesh, like a scan with its colors
+/// baked into the vertices. Surfaces without vertex colors are unchanged.
+pub struct VertexColored<M: Material> {
+    pub base: M,
+}
+
+impl<M: Material> VertexColored<M> {
+    pub fn new(base: M) -> VertexColored<M> {
+        VertexColored { base }
+    }
+
+    /// The hit's color at the ray's wavelengths when the base material
+    /// works at those.
+    fn tint(&self, r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        match r_in.wavelengths {
+            Some(wavelengths) if self.base.is_spectral() => wavelengths.upsample(rec.color),
+            _ => rec.color,
+        }
+    }
+}
+
+impl VertexColored<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<VertexColored<Box<dyn Material>>> {
+        Ok(VertexColored::new(material(field(json, "base")?)?))
+    }
+}
+
+impl<M: Material> Material for VertexColored<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let scatter = self.base.scatter(r_in, rec)?;
+        Some(ScatterRecord {
+            attenuation: self.tint(r_in, rec) * scatter.attenuation,
+            ..scatter
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.tint(r_in, rec) * self.base.eval(r_in, rec, direction)
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+
+    fn medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.medium()
+    }
+
+    fn alpha(&self, rec: &HitRecord) -> f64 {
+        self.base.alpha(rec)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "vertex_colored".into()),
+            ("base", self.base.to_json()?),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::{Hittable, Sphere};
+    use crate::subsurface::Subsurface;
+    use crate::texture::SolidColor;
+
+    #[test]
+    fn test_mix_and_cutout_weigh_their_materials() {
+        let (red, blue) = (Vec3::new(0.9, 0.1, 0.1), Vec3::new(0.1, 0.1, 0.9));
+        let mix = Mix::new(
+            Lambertian::new(red),
+            Lambertian::new(blue),
+            Arc::new(SolidColor::scalar(0.25)),
+        );
+        let cutout = AlphaCutout::new(mix, Arc::new(SolidColor::scalar(0.4)));
+        let sphere = Sphere::new(Vec3::zeros(), 1.0, cutout);
+
+        let r = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.hit(r, 0.001, f64::INFINITY).unwrap();
+        let direction = Vec3::new(0.0, 0.6, 0.8);
+        let (a, b) = (
+            Lambertian::new(red).eval(&r, &hit, direction),
+            Lambertian::new(blue).eval(&r, &hit, direction),
+        );
+        let expected = 0.75 * a + 0.25 * b;
+        assert!((hit.mat.eval(&r, &hit, direction) - expected).length() < 1.0e-12);
+        assert!((hit.mat.alpha(&hit) - 0.4).abs() < 1.0e-12);
+        assert!(hit.mat.medium().is_none());
+
+        let skin = || Subsurface::new(Vec3::new(0.8, 0.5, 0.4), Vec3::ones(), 1.4, 0.3);
+        let white = || Lambertian::new(Vec3::ones());
+        let gray = || Arc::new(SolidColor::scalar(0.5));
+        assert!(Mix::new(white(), skin(), gray()).medium().is_some());
+        assert!(Mix::new(skin(), white(), gray()).medium().is_some());
+        assert!(AlphaCutout::new(skin(), gray()).medium().is_some());
+        assert!(VertexColored::new(skin()).medium().is_some());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::vec3::Vec3;
+
+/// Right-handed orthonormal basis with `w` as the "up" axis, used to move
+/// sampled directions from a local frame into world space.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn build_from_w(n: Vec3) -> Onb {
+        let w = Vec3::unit_vector(n);
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vec3::unit_vector(Vec3::cross(w, a));
+        let u = Vec3::cross(v, w);
+        Onb { u, v, w }
+    }
+
+    /// Basis with `w` along `n` and `u` along the part of `t` perpendicular
+    /// to it, to line anisotropic materials up with a surface tangent.
+    pub fn build_from_wu(n: Vec3, t: Vec3) -> Onb {
+        let w = Vec3::unit_vector(n);
+        let u = t - Vec3::dot(t, w) * w;
+        if u.near_zero() {
+            return Onb::build_from_w(n);
+        }
+        let u = Vec3::unit_vector(u);
+        Onb {
+            u,
+            v: Vec3::cross(w, u),
+            w,
+        }
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        a * self.u + b * self.v + c * self.w
+    }
+
+    pub fn local_vec(&self, a: Vec3) -> Vec3 {
+        self.local(a.x, a.y, a.z)
+    }
+
+    pub fn to_local(self, a: Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(a, self.u),
+            Vec3::dot(a, self.v),
+            Vec3::dot(a, self.w),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bases_are_right_handed() {
+        let normals = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.3, -0.8, 0.5),
+        ];
+        for n in normals {
+            for uvw in [
+                Onb::build_from_w(n),
+                Onb::build_from_wu(n, Vec3::new(0.2, 1.0, -0.4)),
+            ] {
+                assert!((Vec3::cross(uvw.u, uvw.v) - uvw.w).length() < 1.0e-12);
+                assert!((uvw.w - Vec3::unit_vector(n)).length() < 1.0e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,1068 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::camera::Camera;
+use crate::light::*;
+use crate::material::*;
+use crate::mesh::Mesh;
+use crate::mesh_io::{parse_ply, MeshData};
+use crate::scene::Scene;
+use crate::sky::Sky;
+use crate::spectrum::spectrum_to_rgb;
+use crate::sphere::{HittableList, Sphere};
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+
+/// Deepest nesting of `Include` and `Import` followed.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+impl Scene {
+    /// Scene from a file in a subset of the pbrt-v4 format: the camera and
+    /// film, transforms, attribute blocks, named materials and coordinate
+    /// systems, spheres, triangle and PLY meshes, diffuse, conductor and
+    /// dielectric materials, and point, spot, distant and uniform infinite
+    /// lights. Every other directive, shape, material and light is skipped
+    /// with a warning.
+    ///
+    /// pbrt's world is left-handed, so it is mirrored in x to look the same
+    /// through this renderer's right-handed camera.
+    pub fn from_pbrt(path: impl AsRef<Path>, aspect_ratio: f64) -> io::Result<Scene> {
+        let path = path.as_ref();
+        let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let text = fs::read_to_string(path)?;
+        load(&text, &base, &path.display().to_string(), aspect_ratio)
+    }
+}
+
+fn invalid(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("bad pbrt file: {}", message),
+    )
+}
+
+/// Scene from the text of a pbrt file, with relative file names looked up
+/// in `base`. `name` is used in warnings.
+fn load(text: &str, base: &Path, name: &str, aspect_ratio: f64) -> io::Result<Scene> {
+    let mut loader = Loader {
+        tokens: tokenize(text, base, 0)?,
+        position: 0,
+        base: base.to_path_buf(),
+        name: name.to_string(),
+        warned: HashSet::new(),
+        state: GraphicsState {
+            transform: Transform::identity(),
+            material: PbrtMaterial::Diffuse(Vec3::new(0.5, 0.5, 0.5)),
+            reverse_orientation: false,
+        },
+        attributes: Vec::new(),
+        transforms: Vec::new(),
+        named_materials: HashMap::new(),
+        coordinate_systems: HashMap::new(),
+        camera: None,
+        film_aspect: 1280.0 / 720.0,
+        in_object: false,
+        world: HittableList::new(),
+        lights: Vec::new(),
+        sky: None,
+    };
+    loader.run()?;
+
+    let camera = loader.camera(aspect_ratio)?;
+    Ok(Scene {
+        objects: loader.world,
+        camera,
+        // A scene without an infinite light is dark apart from its lights.
+        sky: loader.sky.unwrap_or(Sky::Uniform(Vec3::zeros())),
+        lights: loader.lights,
+        fog: None,
+    })
+}
+
+/// Reflection in x taking pbrt's left-handed world to a right-handed one.
+fn mirror() -> Transform {
+    Transform::scale(Vec3::new(-1.0, 1.0, 1.0))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// A directive, or `true` and `false` written without quotes.
+    Word(String),
+    Quoted(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+/// Tokens of `text`, with the files it includes spliced in.
+fn tokenize(text: &str, base: &Path, depth: usize) -> io::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => {}
+            '#' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '[' => tokens.push(Token::Open),
+            ']' => tokens.push(Token::Close),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => string.push('\n'),
+                            Some((_, 't')) => string.push('\t'),
+                            Some((_, c)) => string.push(c),
+                            None => return Err(invalid("unterminated string")),
+                        },
+                        Some((_, c)) => string.push(c),
+                        None => return Err(invalid("unterminated string")),
+                    }
+                }
+                tokens.push(Token::Quoted(string));
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|&(_, c)| !c.is_whitespace() && !"[]\"#".contains(c))
+                {
+                    end = i + c.len_utf8();
+                }
+                let word = &text[start..end];
+                tokens.push(match word.parse() {
+                    Ok(number) if !word.starts_with(char::is_alphabetic) => Token::Number(number),
+                    _ => Token::Word(word.to_string()),
+                });
+            }
+        }
+    }
+
+    // Include and Import differ only in what pbrt may do in parallel.
+    let mut spliced = Vec::with_capacity(tokens.len());
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        if !matches!(&token, Token::Word(word) if word == "Include" || word == "Import") {
+            spliced.push(token);
+            continue;
+        }
+        let Some(Token::Quoted(file)) = tokens.next() else {
+            return Err(invalid("Include needs a file name"));
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(invalid(format!("includes nest too deeply at {}", file)));
+        }
+        let path = base.join(&file);
+        let text = fs::read_to_string(&path).map_err(|error| {
+            io::Error::new(error.kind(), format!("can't include {}: {}", file, error))
+        })?;
+        spliced.extend(tokenize(&text, path.parent().unwrap_or(base), depth + 1)?);
+    }
+    Ok(spliced)
+}
+
+/// A parameter like `"float radius" 2` or `"rgb reflectance" [0.8 0.2 0.1]`.
+#[derive(Clone, Debug, Default)]
+struct Param {
+    kind: String,
+    name: String,
+    numbers: Vec<f64>,
+    strings: Vec<String>,
+    bools: Vec<bool>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|param| param.name == name)
+    }
+
+    fn float(&self, name: &str, default: f64) -> f64 {
+        self.get(name)
+            .and_then(|param| param.numbers.first().copied())
+            .unwrap_or(default)
+    }
+
+    fn floats(&self, name: &str) -> &[f64] {
+        self.get(name).map_or(&[], |param| &param.numbers)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.get(name)
+            .and_then(|param| param.strings.first())
+            .map(String::as_str)
+    }
+
+    fn bool(&self, name: &str, default: bool) -> bool {
+        self.get(name)
+            .and_then(|param| param.bools.first().copied())
+            .unwrap_or(default)
+    }
+
+    fn point(&self, name: &str, default: Vec3) -> Vec3 {
+        match self.floats(name) {
+            [x, y, z] => Vec3::new(*x, *y, *z),
+            _ => default,
+        }
+    }
+
+    fn points(&self, name: &str) -> Vec<Vec3> {
+        self.floats(name)
+            .chunks_exact(3)
+            .map(|p| Vec3::new(p[0], p[1], p[2]))
+            .collect()
+    }
+
+    /// Roughness along the two tangent directions as this renderer's
+    /// perceptual roughness, whose square is the microfacet alpha. pbrt
+    /// takes alpha directly, or its square root's square by default.
+    fn roughness(&self) -> (f64, f64) {
+        let roughness = self.float("roughness", 0.0);
+        let remap = self.bool("remaproughness", true);
+        let convert = |value: f64| {
+            if remap {
+                value.sqrt().sqrt()
+            } else {
+                value.sqrt()
+            }
+        };
+        (
+            convert(self.float("uroughness", roughness)),
+            convert(self.float("vroughness", roughness)),
+        )
+    }
+}
+
+/// A material as written in the file, made into a renderer material for
+/// each shape using it.
+#[derive(Clone, Debug)]
+enum PbrtMaterial {
+    Diffuse(Vec3),
+    Conductor {
+        eta: Vec3,
+        k: Vec3,
+        roughness: (f64, f64),
+    },
+    Dielectric {
+        eta: Eta,
+        roughness: (f64, f64),
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Eta {
+    Constant(f64),
+    Dispersive(Dispersion),
+}
+
+impl PbrtMaterial {
+    fn build(&self) -> Box<dyn Material> {
+        match self {
+            PbrtMaterial::Diffuse(reflectance) => Box::new(Lambertian::new(*reflectance)),
+            PbrtMaterial::Conductor { eta, k, roughness } => {
+                Box::new(Conductor::anisotropic(*eta, *k, roughness.0, roughness.1))
+            }
+            PbrtMaterial::Dielectric { eta, roughness } => {
+                let roughness = 0.5 * (roughness.0 + roughness.1);
+                match *eta {
+                    Eta::Dispersive(dispersion) if roughness == 0.0 => {
+                        Box::new(Dielectric::dispersive(dispersion))
+                    }
+                    Eta::Constant(eta) if roughness == 0.0 => Box::new(Dielectric::new(eta)),
+                    // Rough glass has a single index, taken at the yellow helium d line.
+                    Eta::Dispersive(dispersion) => Box::new(RoughDielectric::new(
+                        dispersion.index_of_refraction(587.6),
+                        roughness,
+                    )),
+                    Eta::Constant(eta) => Box::new(RoughDielectric::new(eta, roughness)),
+                }
+            }
+        }
+    }
+}
+
+/// Conductor with the measured optical constants behind one of pbrt's
+/// `metal-<element>-eta` and `metal-<element>-k` spectra.
+fn named_metal(name: &str) -> Option<Conductor> {
+    let element = name.strip_prefix("metal-")?.split('-').next()?;
+    Some(match element {
+        "Au" => Conductor::gold(0.0),
+        "Cu" | "CuZn" => Conductor::copper(0.0),
+        "Al" => Conductor::aluminum(0.0),
+        "Ag" => Conductor::silver(0.0),
+        "Fe" => Conductor::iron(0.0),
+        _ => return None,
+    })
+}
+
+/// Normalized Planck spectrum of a black body at `kelvin`, as pbrt's
+/// `blackbody` parameters are.
+fn blackbody(kelvin: f64) -> impl Fn(f64) -> f64 {
+    let planck = move |lambda: f64| {
+        let meters = lambda * 1.0e-9;
+        1.0 / (meters.powi(5) * ((0.014387769 / (meters * kelvin)).exp() - 1.0))
+    };
+    // Wien's displacement law gives the peak.
+    let peak = planck(2.8977721e-3 / kelvin * 1.0e9);
+    move |lambda| planck(lambda) / peak
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    transform: Transform,
+    material: PbrtMaterial,
+    reverse_orientation: bool,
+}
+
+struct Loader {
+    tokens: Vec<Token>,
+    position: usize,
+    base: PathBuf,
+    name: String,
+    /// Warnings already printed, so each is printed once.
+    warned: HashSet<String>,
+    state: GraphicsState,
+    attributes: Vec<GraphicsState>,
+    transforms: Vec<Transform>,
+    named_materials: HashMap<String, PbrtMaterial>,
+    coordinate_systems: HashMap<String, Transform>,
+    /// Camera-from-world transform and parameters of the camera.
+    camera: Option<(Transform, Params)>,
+    film_aspect: f64,
+    /// Inside an object definition, whose shapes are skipped.
+    in_object: bool,
+    world: HittableList,
+    lights: Vec<Box<dyn Light>>,
+    sky: Option<Sky>,
+}
+
+impl Loader {
+    fn warn(&mut self, message: String) {
+        if self.warned.insert(message.clone()) {
+            eprintln!("{}: {}", self.name, message);
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn number(&mut self, directive: &str) -> io::Result<f64> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(number),
+            _ => Err(invalid(format!("{} expects a number", directive))),
+        }
+    }
+
+    fn numbers(&mut self, directive: &str, count: usize) -> io::Result<Vec<f64>> {
+        (0..count).map(|_| self.number(directive)).collect()
+    }
+
+    fn vector(&mut self, directive: &str) -> io::Result<Vec3> {
+        let v = self.numbers(directive, 3)?;
+        Ok(Vec3::new(v[0], v[1], v[2]))
+    }
+
+    /// Sixteen numbers, in brackets or not.
+    fn matrix(&mut self, directive: &str) -> io::Result<Transform> {
+        let bracketed = self.tokens.get(self.position) == Some(&Token::Open);
+        if bracketed {
+            self.position += 1;
+        }
+        let values = self.numbers(directive, 16)?;
+        if bracketed && self.next() != Some(Token::Close) {
+            return Err(invalid(format!("{} expects 16 numbers", directive)));
+        }
+        Ok(Transform::from_columns(&values))
+    }
+
+    fn string(&mut self, directive: &str) -> io::Result<String> {
+        match self.next() {
+            Some(Token::Quoted(string)) => Ok(string),
+            _ => Err(invalid(format!("{} expects a quoted name", directive))),
+        }
+    }
+
+    /// The parameters following a directive's fixed arguments.
+    fn params(&mut self, directive: &str) -> io::Result<Params> {
+        let mut params = Vec::new();
+        while let Some(Token::Quoted(declaration)) = self.tokens.get(self.position).cloned() {
+            self.position += 1;
+            let mut words = declaration.split_whitespace();
+            let (Some(kind), Some(name), None) = (words.next(), words.next(), words.next()) else {
+                return Err(invalid(format!(
+                    "{} has a bad parameter \"{}\"",
+                    directive, declaration
+                )));
+            };
+            let mut param = Param {
+                kind: kind.to_string(),
+                name: name.to_string(),
+                ..Param::default()
+            };
+            let bracketed = self.tokens.get(self.position) == Some(&Token::Open);
+            if bracketed {
+                self.position += 1;
+            }
+            loop {
+                match self.tokens.get(self.position) {
+                    Some(Token::Number(number)) => param.numbers.push(*number),
+                    Some(Token::Quoted(string)) if kind == "bool" => {
+                        param.bools.push(string == "true")
+                    }
+                    Some(Token::Word(word)) if word == "true" || word == "false" => {
+                        param.bools.push(word == "true")
+                    }
+                    Some(Token::Quoted(string)) if bracketed => param.strings.push(string.clone()),
+                    Some(Token::Quoted(string))
+                        if param.strings.is_empty() && param.numbers.is_empty() =>
+                    {
+                        param.strings.push(string.clone())
+                    }
+                    Some(Token::Close) if bracketed => {
+                        self.position += 1;
+                        break;
+                    }
+                    _ if bracketed => {
+                        return Err(invalid(format!(
+                            "{} has an unclosed value for \"{}\"",
+                            directive, declaration
+                        )))
+                    }
+                    _ => break,
+                }
+                self.position += 1;
+                if !bracketed {
+                    break;
+                }
+            }
+            params.push(param);
+        }
+        Ok(Params(params))
+    }
+
+    /// Color of a spectrum parameter, from RGB, a blackbody temperature or
+    /// sampled values, or none if it isn't there or can't be read.
+    fn spectrum(&mut self, params: &Params, name: &str) -> Option<Vec3> {
+        let param = params.get(name)?.clone();
+        match (param.kind.as_str(), &param.numbers[..]) {
+            ("rgb", [r, g, b]) => Some(Vec3::new(*r, *g, *b)),
+            ("float", [value]) => Some(Vec3::new(*value, *value, *value)),
+            ("blackbody", [kelvin]) => Some(spectrum_to_rgb(blackbody(*kelvin))),
+            ("spectrum", samples) if samples.len() >= 4 && samples.len() % 2 == 0 => {
+                let pairs: Vec<(f64, f64)> = samples
+                    .chunks_exact(2)
+                    .map(|pair| (pair[0], pair[1]))
+                    .collect();
+                Some(spectrum_to_rgb(|lambda| interpolate(&pairs, lambda)))
+            }
+            ("texture", _) => {
+                self.warn(format!(
+                    "textures aren't supported, using the default {}",
+                    name
+                ));
+                None
+            }
+            _ => {
+                self.warn(format!(
+                    "can't read the \"{} {}\" parameter, using the default",
+                    param.kind, name
+                ));
+                None
+            }
+        }
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(token) = self.next() {
+            let Token::Word(directive) = token else {
+                return Err(invalid(format!("expected a directive, found {:?}", token)));
+            };
+            self.directive(&directive)?;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str) -> io::Result<()> {
+        let d = directive;
+        match d {
+            "Identity" => self.state.transform = Transform::identity(),
+            "Translate" => {
+                let offset = self.vector(d)?;
+                self.apply(Transform::translate(offset))
+            }
+            "Scale" => {
+                let factors = self.vector(d)?;
+                self.apply(Transform::scale(factors))
+            }
+            "Rotate" => {
+                let degrees = self.number(d)?;
+                let axis = self.vector(d)?;
+                self.apply(Transform::rotate(degrees, axis))
+            }
+            "LookAt" => {
+                let (eye, at, up) = (self.vector(d)?, self.vector(d)?, self.vector(d)?);
+                self.apply(look_at(eye, at, up)?)
+            }
+            "Transform" => self.state.transform = self.matrix(d)?,
+            "ConcatTransform" => {
+                let transform = self.matrix(d)?;
+                self.apply(transform)
+            }
+            "CoordinateSystem" => {
+                let name = self.string(d)?;
+                self.coordinate_systems.insert(name, self.state.transform);
+            }
+            "CoordSysTransform" => {
+                let name = self.string(d)?;
+                match self.coordinate_systems.get(&name) {
+                    Some(&transform) => self.state.transform = transform,
+                    None => self.warn(format!("unknown coordinate system \"{}\"", name)),
+                }
+            }
+            "ReverseOrientation" => {
+                self.state.reverse_orientation = !self.state.reverse_orientation
+            }
+            "AttributeBegin" => self.attributes.push(self.state.clone()),
+            "AttributeEnd" => {
+                self.state = self
+                    .attributes
+                    .pop()
+                    .ok_or_else(|| invalid("AttributeEnd without AttributeBegin"))?
+            }
+            "TransformBegin" => self.transforms.push(self.state.transform),
+            "TransformEnd" => {
+                self.state.transform = self
+                    .transforms
+                    .pop()
+                    .ok_or_else(|| invalid("TransformEnd without TransformBegin"))?
+            }
+            "Camera" => {
+                let kind = self.string(d)?;
+                let params = self.params(d)?;
+                if kind != "perspective" {
+                    self.warn(format!(
+                        "\"{}\" cameras aren't supported, using a perspective camera",
+                        kind
+                    ));
+                }
+                let camera_from_world = self.state.transform;
+                if let Some(world_from_camera) = camera_from_world.inverse() {
+                    self.coordinate_systems
+                        .insert("camera".to_string(), world_from_camera);
+                }
+                self.camera = Some((camera_from_world, params));
+            }
+            "Film" => {
+                self.string(d)?;
+                let params = self.params(d)?;
+                self.film_aspect =
+                    params.float("xresolution", 1280.0) / params.float("yresolution", 720.0);
+            }
+            // The renderer chooses its own number of samples.
+            "Sampler" => {
+                self.string(d)?;
+                self.params(d)?;
+            }
+            "WorldBegin" => {
+                self.state.transform = Transform::identity();
+                self.coordinate_systems
+                    .insert("world".to_string(), Transform::identity());
+            }
+            "Material" => {
+                let kind = self.string(d)?;
+                let params = self.params(d)?;
+                self.state.material = self.material(&kind, &params);
+            }
+            "MakeNamedMaterial" => {
+                let name = self.string(d)?;
+                let params = self.params(d)?;
+                let kind = params.string("type").unwrap_or("diffuse").to_string();
+                let material = self.material(&kind, &params);
+                self.named_materials.insert(name, material);
+            }
+            "NamedMaterial" => {
+                let name = self.string(d)?;
+                match self.named_materials.get(&name) {
+                    Some(material) => self.state.material = material.clone(),
+                    None => self.warn(format!("unknown material \"{}\"", name)),
+                }
+            }
+            "Shape" => {
+                let kind = self.string(d)?;
+                let params = self.params(d)?;
+                if !self.in_object {
+                    self.shape(&kind, &params)?;
+                }
+            }
+            "LightSource" => {
+                let kind = self.string(d)?;
+                let params = self.params(d)?;
+                self.light(&kind, &params);
+            }
+            "ObjectBegin" => {
+                let name = self.string(d)?;
+                self.warn(format!(
+                    "object instancing isn't supported, skipping object \"{}\"",
+                    name
+                ));
+                self.attributes.push(self.state.clone());
+                self.in_object = true;
+            }
+            "ObjectEnd" => {
+                self.state = self
+                    .attributes
+                    .pop()
+                    .ok_or_else(|| invalid("ObjectEnd without ObjectBegin"))?;
+                self.in_object = false;
+            }
+            // Without motion blur, every transform applies at all times.
+            "ActiveTransform" => {
+                self.next();
+                self.warn(format!("ignoring unsupported {}", d));
+            }
+            "TransformTimes" | "AreaLightSource" | "Texture" | "MakeNamedMedium"
+            | "MediumInterface" | "ObjectInstance" | "Integrator" | "PixelFilter"
+            | "Accelerator" | "ColorSpace" | "Option" | "Attribute" => {
+                self.skip_arguments();
+                self.warn(format!("ignoring unsupported {}", d));
+            }
+            "WorldEnd" => {}
+            _ => {
+                self.skip_arguments();
+                self.warn(format!("skipping unknown directive {}", d));
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips the names, numbers and parameters up to the next directive.
+    fn skip_arguments(&mut self) {
+        while let Some(token) = self.tokens.get(self.position) {
+            match token {
+                Token::Word(word) if word != "true" && word != "false" => break,
+                _ => self.position += 1,
+            }
+        }
+    }
+
+    /// Applies `transform` before the current one.
+    fn apply(&mut self, transform: Transform) {
+        self.state.transform = self.state.transform.then(transform);
+    }
+
+    fn material(&mut self, kind: &str, params: &Params) -> PbrtMaterial {
+        match kind {
+            "diffuse" => PbrtMaterial::Diffuse(
+                self.spectrum(params, "reflectance")
+                    .unwrap_or(Vec3::new(0.5, 0.5, 0.5)),
+            ),
+            "conductor" => {
+                let roughness = params.roughness();
+                if let Some(reflectance) = self.spectrum(params, "reflectance") {
+                    // The absorption that gives this reflectance head on with eta 1.
+                    let k = |r: f64| {
+                        2.0 * r.clamp(0.0, 0.9999).sqrt() / (1.0 - r.clamp(0.0, 0.9999)).sqrt()
+                    };
+                    let k = Vec3::new(k(reflectance.x), k(reflectance.y), k(reflectance.z));
+                    return PbrtMaterial::Conductor {
+                        eta: Vec3::ones(),
+                        k,
+                        roughness,
+                    };
+                }
+                let copper = Conductor::copper(0.0);
+                let mut constant = |name: &str, of: fn(&Conductor) -> Vec3| match params
+                    .string(name)
+                {
+                    Some(spectrum) => named_metal(spectrum)
+                        .map(|metal| of(&metal))
+                        .unwrap_or_else(|| {
+                            self.warn(format!("unknown spectrum \"{}\", using copper", spectrum));
+                            of(&copper)
+                        }),
+                    None => self.spectrum(params, name).unwrap_or_else(|| of(&copper)),
+                };
+                let eta = constant("eta", |metal| metal.eta);
+                let k = constant("k", |metal| metal.k);
+                PbrtMaterial::Conductor { eta, k, roughness }
+            }
+            "dielectric" => {
+                let eta = match params.string("eta") {
+                    Some("glass-BK7") => Eta::Dispersive(Dispersion::BK7),
+                    Some(name) if name.starts_with("glass-SF") => {
+                        Eta::Dispersive(Dispersion::DENSE_FLINT)
+                    }
+                    Some(name) => {
+                        self.warn(format!(
+                            "unknown spectrum \"{}\", using an index of 1.5",
+                            name
+                        ));
+                        Eta::Constant(1.5)
+                    }
+                    None => Eta::Constant(params.float("eta", 1.5)),
+                };
+                PbrtMaterial::Dielectric {
+                    eta,
+                    roughness: params.roughness(),
+                }
+            }
+            _ => {
+                self.warn(format!(
+                    "\"{}\" materials aren't supported, using diffuse",
+                    kind
+                ));
+                PbrtMaterial::Diffuse(
+                    self.spectrum(params, "reflectance")
+                        .unwrap_or(Vec3::new(0.5, 0.5, 0.5)),
+                )
+            }
+        }
+    }
+
+    fn shape(&mut self, kind: &str, params: &Params) -> io::Result<()> {
+        let transform = mirror().then(self.state.transform);
+        let mut mesh = match kind {
+            "sphere" => {
+                if params.get("zmin").is_some()
+                    || params.get("zmax").is_some()
+                    || params.get("phimax").is_some()
+                {
+                    self.warn("partial spheres aren't supported, rendering them whole".to_string());
+                }
+                let radius = params.float("radius", 1.0);
+                let material = self.state.material.build();
+                if let Some(scale) = uniform_scale(transform) {
+                    let center = transform.point(Vec3::zeros());
+                    self.world
+                        .add(Box::new(Sphere::new(center, scale * radius, material)));
+                    return Ok(());
+                }
+                // Stretched spheres become meshes.
+                let sphere =
+                    Mesh::uv_sphere(Vec3::zeros(), radius, 64, 32, Lambertian::new(Vec3::ones()));
+                MeshData {
+                    positions: sphere.positions,
+                    normals: sphere.normals,
+                    uvs: sphere.uvs,
+                    colors: Vec::new(),
+                    triangles: sphere.triangles,
+                }
+            }
+            "trianglemesh" => {
+                let positions = params.points("P");
+                let indices = params.floats("indices");
+                if let Some(&index) = indices.iter().find(|&&i| i < 0.0 || i.fract() != 0.0) {
+                    return Err(invalid(format!(
+                        "trianglemesh has a bad vertex index {}",
+                        index
+                    )));
+                }
+                let mut corners: Vec<usize> = indices.iter().map(|&i| i as usize).collect();
+                if corners.is_empty() && positions.len() == 3 {
+                    corners = vec![0, 1, 2];
+                }
+                if let Some(&corner) = corners.iter().find(|&&i| i >= positions.len()) {
+                    return Err(invalid(format!(
+                        "trianglemesh refers to vertex {} of {}",
+                        corner,
+                        positions.len()
+                    )));
+                }
+                let (uv_name, uvs) = match params.floats("uv") {
+                    [] => ("st", params.floats("st")),
+                    uvs => ("uv", uvs),
+                };
+                for (name, values, expected) in [
+                    ("N", params.floats("N").len(), 3 * positions.len()),
+                    (uv_name, uvs.len(), 2 * positions.len()),
+                ] {
+                    if values != 0 && values != expected {
+                        return Err(invalid(format!(
+                            "trianglemesh has {} values of {} for {} vertices",
+                            values,
+                            name,
+                            positions.len()
+                        )));
+                    }
+                }
+                MeshData {
+                    normals: params.points("N"),
+                    uvs: uvs.chunks_exact(2).map(|uv| (uv[0], uv[1])).collect(),
+                    triangles: corners
+                        .chunks_exact(3)
+                        .map(|c| [c[0], c[1], c[2]])
+                        .collect(),
+                    positions,
+                    colors: Vec::new(),
+                }
+            }
+            "plymesh" => {
+                let file = params.string("filename").unwrap_or_default().to_string();
+                match fs::read(self.base.join(&file)).and_then(|bytes| parse_ply(&bytes)) {
+                    Ok(mesh) => mesh,
+                    Err(error) => {
+                        self.warn(format!("skipping {}: {}", file, error));
+                        return Ok(());
+                    }
+                }
+            }
+            _ => {
+                self.warn(format!("\"{}\" shapes aren't supported", kind));
+                return Ok(());
+            }
+        };
+
+        mesh.positions = mesh.positions.iter().map(|&p| transform.point(p)).collect();
+        mesh.normals = mesh.normals.iter().map(|&n| transform.normal(n)).collect();
+        // Without normals the winding decides the outside, which a mirroring
+        // transform or ReverseOrientation turns around.
+        if mesh.normals.is_empty()
+            && (transform.determinant() < 0.0) != self.state.reverse_orientation
+        {
+            for triangle in &mut mesh.triangles {
+                triangle.swap(1, 2);
+            }
+        }
+        if !mesh.triangles.is_empty() {
+            self.world
+                .add(Box::new(mesh.into_mesh(self.state.material.build())));
+        }
+        Ok(())
+    }
+
+    fn light(&mut self, kind: &str, params: &Params) {
+        let transform = mirror().then(self.state.transform);
+        let scale = params.float("scale", 1.0);
+        if params.get("power").is_some() || params.get("illuminance").is_some() {
+            self.warn(format!(
+                "ignoring the power of a {} light, using its scale",
+                kind
+            ));
+        }
+        let from = transform.point(params.point("from", Vec3::zeros()));
+        let to = transform.point(params.point("to", Vec3::new(0.0, 0.0, 1.0)));
+        match kind {
+            "point" => {
+                let color = self.spectrum(params, "I").unwrap_or(Vec3::ones());
+                self.lights
+                    .push(Box::new(PointLight::new(from, color, scale)));
+            }
+            "spot" => {
+                let color = self.spectrum(params, "I").unwrap_or(Vec3::ones());
+                let cone = params.float("coneangle", 30.0);
+                let delta = params.float("conedelta", 5.0);
+                self.lights.push(Box::new(SpotLight::new(
+                    from,
+                    to,
+                    color,
+                    scale,
+                    cone,
+                    cone - delta,
+                )));
+            }
+            "distant" => {
+                let color = self.spectrum(params, "L").unwrap_or(Vec3::ones());
+                self.lights
+                    .push(Box::new(DirectionalLight::new(to - from, color, scale)));
+            }
+            "infinite" => {
+                if params.get("filename").is_some() {
+                    self.warn("environment maps aren't supported, using a uniform sky".to_string());
+                }
+                let color = self.spectrum(params, "L").unwrap_or(Vec3::ones());
+                self.sky = Some(Sky::Uniform(scale * color));
+            }
+            _ => self.warn(format!("\"{}\" lights aren't supported", kind)),
+        }
+    }
+
+    /// The file's camera seen through the mirror, or one at the origin
+    /// looking down +z like pbrt's default.
+    fn camera(&mut self, aspect_ratio: f64) -> io::Result<Camera> {
+        let (camera_from_world, params) = self
+            .camera
+            .clone()
+            .unwrap_or((Transform::identity(), Params::default()));
+        let world_from_camera = mirror().then(
+            camera_from_world
+                .inverse()
+                .ok_or_else(|| invalid("the camera transform can't be inverted"))?,
+        );
+        let origin = world_from_camera.point(Vec3::zeros());
+        let forward = world_from_camera.vector(Vec3::new(0.0, 0.0, 1.0));
+        let up = world_from_camera.vector(Vec3::new(0.0, 1.0, 0.0));
+
+        // pbrt's field of view spans the shorter side of the film.
+        let fov = params.float("fov", 90.0);
+        let vfov = if self.film_aspect < 1.0 {
+            2.0 * ((0.5 * fov).to_radians().tan() / self.film_aspect)
+                .atan()
+                .to_degrees()
+        } else {
+            fov
+        };
+        if (self.film_aspect - aspect_ratio).abs() > 1.0e-3 {
+            self.warn(format!(
+                "the film's aspect ratio is {:.3}, rendering at {:.3} with the same vertical view",
+                self.film_aspect, aspect_ratio
+            ));
+        }
+        // Camera rays are as long as the focus distance, which only matters
+        // with a lens, and pbrt's default of 1e6 would put every hit below
+        // the renderer's shortest distance.
+        let aperture = 2.0 * params.float("lensradius", 0.0);
+        let focus_distance = if aperture > 0.0 {
+            params.float("focaldistance", 1.0e6)
+        } else {
+            1.0
+        };
+        Ok(Camera::new(
+            origin,
+            origin + forward,
+            up,
+            vfov,
+            aspect_ratio,
+            aperture,
+            focus_distance,
+        ))
+    }
+}
+
+/// pbrt's `LookAt`, the camera-from-world transform of a camera at `eye`
+/// looking at `at`.
+fn look_at(eye: Vec3, at: Vec3, up: Vec3) -> io::Result<Transform> {
+    let direction = Vec3::unit_vector(at - eye);
+    let right = Vec3::cross(Vec3::unit_vector(up), direction);
+    if right.near_zero() {
+        return Err(invalid(
+            "LookAt's up vector is parallel to its view direction",
+        ));
+    }
+    let right = Vec3::unit_vector(right);
+    let new_up = Vec3::cross(direction, right);
+    let mut world_from_camera = Transform::identity().0;
+    for (j, column) in [right, new_up, direction, eye].iter().enumerate() {
+        world_from_camera[0][j] = column.x;
+        world_from_camera[1][j] = column.y;
+        world_from_camera[2][j] = column.z;
+    }
+    Transform(world_from_camera)
+        .inverse()
+        .ok_or_else(|| invalid("LookAt can't be inverted"))
+}
+
+/// The scale factor of a transform that only rotates, mirrors, translates
+/// and scales the same in every direction.
+fn uniform_scale(transform: Transform) -> Option<f64> {
+    let x = transform.vector(Vec3::new(1.0, 0.0, 0.0));
+    let y = transform.vector(Vec3::new(0.0, 1.0, 0.0));
+    let z = transform.vector(Vec3::new(0.0, 0.0, 1.0));
+    let scale = x.length();
+    let tolerance = 1.0e-6 * scale;
+    let similar = (y.length() - scale).abs() < tolerance
+        && (z.length() - scale).abs() < tolerance
+        && Vec3::dot(x, y).abs() < tolerance * scale
+        && Vec3::dot(y, z).abs() < tolerance * scale
+        && Vec3::dot(z, x).abs() < tolerance * scale;
+    similar.then_some(scale)
+}
+
+/// Piecewise linear spectrum through `(wavelength, value)` samples, held
+/// flat past the ends.
+fn interpolate(samples: &[(f64, f64)], lambda: f64) -> f64 {
+    let first = samples[0];
+    let last = samples[samples.len() - 1];
+    if lambda <= first.0 {
+        return first.1;
+    }
+    if lambda >= last.0 {
+        return last.1;
+    }
+    samples
+        .windows(2)
+        .find(|pair| lambda <= pair[1].0)
+        .map_or(last.1, |pair| {
+            let t = (lambda - pair[0].0) / (pair[1].0 - pair[0].0);
+            pair[0].1 + t * (pair[1].1 - pair[0].1)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::sphere::Hittable;
+
+    #[test]
+    fn test_loads_scene_subset() {
+        let text = r#"
+            # A camera looking down +z at a sphere to its right and a triangle.
+            LookAt 0 0 -5  0 0 0  0 1 0
+            Camera "perspective" "float fov" [ 90 ]
+            Film "rgb" "integer xresolution" 400 "integer yresolution" 400 "string filename" "out.exr"
+            Sampler "zsobol" "integer pixelsamples" 16
+            Integrator "volpath" "integer maxdepth" [ 5 ]
+            TransformTimes 0 1
+            ActiveTransform EndTime
+            ActiveTransform All
+            Frobnicate "x" 1 [ 2 3 ] "bool y" true
+            WorldBegin
+            LightSource "point" "rgb I" [ 10 10 10 ] "point3 from" [ 0 4 -4 ]
+            LightSource "infinite" "blackbody L" 6500 "float scale" 0.5
+            MakeNamedMaterial "gold" "string type" "conductor" "spectrum eta" "metal-Au-eta"
+                "spectrum k" "metal-Au-k" "float roughness" 0.01
+            AttributeBegin
+                NamedMaterial "gold"
+                Translate 2 0 0
+                Shape "sphere" "float radius" 1
+            AttributeEnd
+            AttributeBegin
+                Material "dielectric" "float eta" 1.33
+                Translate 0 0 3
+                Shape "trianglemesh" "point3 P" [ -1 -1 0  1 -1 0  0 1 0 ] "integer indices" [ 0 1 2 ]
+            AttributeEnd
+            Shape "disk"
+        "#;
+        let scene = load(text, Path::new("."), "test", 1.0).unwrap();
+        assert_eq!(scene.lights.len(), 1);
+        assert!(matches!(scene.sky, Sky::Uniform(color) if color.x > 0.0));
+
+        // The sphere is on the right of the picture, as pbrt draws it.
+        let right = scene.camera.get_ray(0.75, 0.5);
+        let hit = scene.objects.hit(right, 0.001, f64::INFINITY).unwrap();
+        assert!(((hit.p - Vec3::new(-2.0, 0.0, 0.0)).length() - 1.0).abs() < 1.0e-9);
+        let left = scene.camera.get_ray(0.25, 0.5);
+        assert!(scene.objects.hit(left, 0.001, f64::INFINITY).is_none());
+
+        // The triangle is straight ahead, behind where the sphere would be.
+        let ahead = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = scene.objects.hit(ahead, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 8.0).abs() < 1.0e-9);
+
+        assert!(load("AttributeEnd", Path::new("."), "test", 1.0).is_err());
+        let bad_index = r#"Shape "trianglemesh" "point3 P" [ 0 0 0 ] "integer indices" [ 0 1 2 ]"#;
+        assert!(load(bad_index, Path::new("."), "test", 1.0).is_err());
+        for indices in ["0 -1 2", "0 1.5 2"] {
+            let text = format!(
+                r#"Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ] "integer indices" [ {} ]"#,
+                indices
+            );
+            let error = load(&text, Path::new("."), "test", 1.0).err().unwrap();
+            assert!(error.to_string().contains("bad vertex index"));
+        }
+        for (param, values) in [("normal N", "0 0 1  0 0 1"), ("float uv", "0 0  1 0  0")] {
+            let text = format!(
+                r#"Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ] "integer indices" [ 0 1 2 ] "{}" [ {} ]"#,
+                param, values
+            );
+            let error = load(&text, Path::new("."), "test", 1.0).err().unwrap();
+            assert!(error.to_string().contains("for 3 vertices"));
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::io;
+
+use crate::json::Json;
+use crate::random::*;
+use crate::scene_file::{array, as_vec3, field, invalid};
+use crate::vec3::Vec3;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise from "Ray Tracing: The Next Week", with random unit
+/// gradients on a lattice and Hermite-smoothed trilinear interpolation.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Perlin {
+        Perlin {
+            gradients: (0..POINT_COUNT)
+                .map(|_| Vec3::unit_vector(Vec3::random_range(-1.0, 1.0)))
+                .collect(),
+            perm_x: Perlin::generate_perm(),
+            perm_y: Perlin::generate_perm(),
+            perm_z: Perlin::generate_perm(),
+        }
+    }
+
+    /// The random tables the noise is made from, in the scene file format.
+    pub fn to_json(&self) -> Json {
+        let indices =
+            |perm: &[usize]| Json::Array(perm.iter().map(|&i| (i as f64).into()).collect());
+        Json::object([
+            (
+                "gradients",
+                Json::Array(self.gradients.iter().map(|&g| g.into()).collect()),
+            ),
+            ("perm_x", indices(&self.perm_x)),
+            ("perm_y", indices(&self.perm_y)),
+            ("perm_z", indices(&self.perm_z)),
+        ])
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Perlin> {
+        let gradients = array(json, "gradients")?
+            .iter()
+            .map(as_vec3)
+            .collect::<Option<Vec<_>>>()
+            .filter(|gradients| gradients.len() == POINT_COUNT)
+            .ok_or_else(|| invalid(format!("noise needs {} gradients", POINT_COUNT)))?;
+        let perm = |key: &str| {
+            field(json, key)?
+                .as_array()
+                .and_then(|perm| perm.iter().map(Json::as_usize).collect::<Option<Vec<_>>>())
+                .filter(|perm| perm.len() == POINT_COUNT && perm.iter().all(|&i| i < POINT_COUNT))
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "\"{}\" isn't {} indices of gradients",
+                        key, POINT_COUNT
+                    ))
+                })
+        };
+        Ok(Perlin {
+            gradients,
+            perm_x: perm("perm_x")?,
+            perm_y: perm("perm_y")?,
+            perm_z: perm("perm_z")?,
+        })
+    }
+
+    /// Noise in about [-1, 1] at `p`, varying over a distance of one.
+    pub fn noise(&self, p: Vec3) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        let mut corners = [[[Vec3::zeros(); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize];
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        let (uu, vv, ww) = (hermite(u), hermite(v), hermite(w));
+        let mut accum = 0.0;
+        for (di, plane) in corners.iter().enumerate() {
+            for (dj, row) in plane.iter().enumerate() {
+                for (dk, corner) in row.iter().enumerate() {
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+                    accum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * Vec3::dot(*corner, weight);
+                }
+            }
+        }
+        accum
+    }
+
+    fn generate_perm() -> Vec<usize> {
+        let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+        for i in (1..POINT_COUNT).rev() {
+            let target = (random_f64() * (i + 1) as f64) as usize;
+            perm.swap(i, target.min(i));
+        }
+        perm
+    }
+}
+
+fn hermite(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
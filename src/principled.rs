@@ -0,0 +1,540 @@
+use std::f64::consts::PI;
+use std::io;
+use std::sync::Arc;
+
+use crate::json::Json;
+use crate::material::*;
+use crate::microfacet::*;
+use crate::onb::Onb;
+use crate::random::*;
+use crate::ray::Ray;
+use crate::scene_file::{field, texture};
+use crate::sphere::HitRecord;
+use crate::texture::*;
+use crate::vec3::Vec3;
+
+/// How much the sheen takes on the hue of the base color.
+const SHEEN_TINT: f64 = 0.5;
+
+/// Principled "uber" material after Burley's "Physically Based Shading at
+/// Disney" (2012) and its 2015 extension to transmission. One material covers
+/// plastics, metals, glass, cloth-like sheen and lacquered surfaces, and every
+/// parameter can be driven by a texture.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub index_of_refraction: Arc<dyn Texture>,
+}
+
+impl Principled {
+    /// A dielectric with medium roughness and the default 4% specular
+    /// reflectance. Use the builder methods to change the other parameters.
+    pub fn new(base_color: Arc<dyn Texture>) -> Principled {
+        let constant = |value| -> Arc<dyn Texture> { Arc::new(SolidColor::scalar(value)) };
+        Principled {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            index_of_refraction: constant(1.5),
+        }
+    }
+
+    pub fn metallic(self, metallic: Arc<dyn Texture>) -> Principled {
+        Principled { metallic, ..self }
+    }
+
+    pub fn roughness(self, roughness: Arc<dyn Texture>) -> Principled {
+        Principled { roughness, ..self }
+    }
+
+    pub fn specular(self, specular: Arc<dyn Texture>) -> Principled {
+        Principled { specular, ..self }
+    }
+
+    pub fn specular_tint(self, specular_tint: Arc<dyn Texture>) -> Principled {
+        Principled {
+            specular_tint,
+            ..self
+        }
+    }
+
+    pub fn sheen(self, sheen: Arc<dyn Texture>) -> Principled {
+        Principled { sheen, ..self }
+    }
+
+    pub fn clearcoat(self, clearcoat: Arc<dyn Texture>) -> Principled {
+        Principled { clearcoat, ..self }
+    }
+
+    pub fn clearcoat_gloss(self, clearcoat_gloss: Arc<dyn Texture>) -> Principled {
+        Principled {
+            clearcoat_gloss,
+            ..self
+        }
+    }
+
+    pub fn transmission(self, transmission: Arc<dyn Texture>) -> Principled {
+        Principled {
+            transmission,
+            ..self
+        }
+    }
+
+    pub fn index_of_refraction(self, index_of_refraction: Arc<dyn Texture>) -> Principled {
+        Principled {
+            index_of_refraction,
+            ..self
+        }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Principled> {
+        let parameter = |key: &str| texture(field(json, key)?);
+        Ok(Principled {
+            base_color: parameter("base_color")?,
+            metallic: parameter("metallic")?,
+            roughness: parameter("roughness")?,
+            specular: parameter("specular")?,
+            specular_tint: parameter("specular_tint")?,
+            sheen: parameter("sheen")?,
+            clearcoat: parameter("clearcoat")?,
+            clearcoat_gloss: parameter("clearcoat_gloss")?,
+            transmission: parameter("transmission")?,
+            index_of_refraction: parameter("index_of_refraction")?,
+        })
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        let value = |texture: &Arc<dyn Texture>| texture.value(rec.u, rec.v, rec.p);
+        let scalar = |texture: &Arc<dyn Texture>| value(texture).x.clamp(0.0, 1.0);
+
+        let base_color = value(&self.base_color);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let specular = scalar(&self.specular);
+        let specular_tint = scalar(&self.specular_tint);
+        let sheen = scalar(&self.sheen);
+        let clearcoat = scalar(&self.clearcoat);
+        let clearcoat_gloss = scalar(&self.clearcoat_gloss);
+        let transmission = scalar(&self.transmission);
+        let index_of_refraction = value(&self.index_of_refraction).x.max(1.0);
+
+        let luminance = 0.3 * base_color.x + 0.6 * base_color.y + 0.1 * base_color.z;
+        let tint = if luminance > 0.0 {
+            base_color / luminance
+        } else {
+            Vec3::ones()
+        };
+
+        let alpha = (roughness * roughness).max(1.0e-3);
+        let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+        let glass_weight = (1.0 - metallic) * transmission;
+        let specular_weight = 1.0 - glass_weight;
+        let clearcoat_weight = 0.25 * clearcoat;
+        let total = diffuse_weight + specular_weight + clearcoat_weight + glass_weight;
+
+        Lobes {
+            base_color,
+            roughness,
+            specular_f0: mix(
+                0.08 * specular * mix(Vec3::ones(), tint, specular_tint),
+                base_color,
+                metallic,
+            ),
+            sheen_color: sheen * mix(Vec3::ones(), tint, SHEEN_TINT),
+            distribution: TrowbridgeReitz {
+                alpha_x: alpha,
+                alpha_y: alpha,
+            },
+            clearcoat_alpha: 0.1 + (0.001 - 0.1) * clearcoat_gloss,
+            eta: if rec.front_facing {
+                index_of_refraction
+            } else {
+                1.0 / index_of_refraction
+            },
+            diffuse_weight,
+            specular_weight,
+            clearcoat_weight,
+            glass_weight,
+            diffuse_probability: diffuse_weight / total,
+            specular_probability: specular_weight / total,
+            clearcoat_probability: clearcoat_weight / total,
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let lobes = self.lobes(rec);
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let wi = lobes.sample(wo)?;
+        let pdf = lobes.pdf(wo, wi);
+        if wi.z == 0.0 || pdf <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord {
+            attenuation: wi.z.abs() / pdf * lobes.f(wo, wi),
+            scattered: Ray::new(rec.p, uvw.local_vec(wi)),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let lobes = self.lobes(rec);
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-Vec3::unit_vector(r_in.direction));
+        let wi = uvw.to_local(Vec3::unit_vector(direction));
+        if wo.z <= 0.0 {
+            return Vec3::zeros();
+        }
+
+        wi.z.abs() * lobes.f(wo, wi)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "principled".into()),
+            ("base_color", self.base_color.to_json()?),
+            ("metallic", self.metallic.to_json()?),
+            ("roughness", self.roughness.to_json()?),
+            ("specular", self.specular.to_json()?),
+            ("specular_tint", self.specular_tint.to_json()?),
+            ("sheen", self.sheen.to_json()?),
+            ("clearcoat", self.clearcoat.to_json()?),
+            ("clearcoat_gloss", self.clearcoat_gloss.to_json()?),
+            ("transmission", self.transmission.to_json()?),
+            ("index_of_refraction", self.index_of_refraction.to_json()?),
+        ]))
+    }
+}
+
+/// The principled parameters evaluated at one hit, split into lobes.
+/// Directions are in the local shading frame, with `wo` above the surface.
+struct Lobes {
+    base_color: Vec3,
+    roughness: f64,
+    specular_f0: Vec3,
+    sheen_color: Vec3,
+    distribution: TrowbridgeReitz,
+    clearcoat_alpha: f64,
+    // Index of refraction on the far side relative to the side of `wo`.
+    eta: f64,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    clearcoat_weight: f64,
+    glass_weight: f64,
+    diffuse_probability: f64,
+    specular_probability: f64,
+    clearcoat_probability: f64,
+}
+
+impl Lobes {
+    fn sample(&self, wo: Vec3) -> Option<Vec3> {
+        let choice = random_f64();
+        // `f` and `pdf` tell the lobes apart by the side of the surface `wi`
+        // is on, so reflections that a microfacet sends below it, or
+        // refractions above it, are dropped rather than counted in the other.
+        let reflected = |wm| Some(Vec3::reflect(-wo, wm)).filter(|wi: &Vec3| wi.z > 0.0);
+        if choice < self.diffuse_probability {
+            Some(Vec3::random_cosine_direction())
+        } else if choice < self.diffuse_probability + self.specular_probability {
+            reflected(self.distribution.sample_wm(wo))
+        } else if choice
+            < self.diffuse_probability + self.specular_probability + self.clearcoat_probability
+        {
+            reflected(sample_gtr1(self.clearcoat_alpha))
+        } else {
+            let wm = self.distribution.sample_wm(wo);
+            if random_f64() < fresnel_dielectric(Vec3::dot(wo, wm), self.eta) {
+                reflected(wm)
+            } else {
+                refract(wo, wm, self.eta).filter(|wi| wi.z < 0.0)
+            }
+        }
+    }
+
+    /// Sum of all lobes' BSDFs, without the cosine term.
+    fn f(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wi.z < 0.0 {
+            return self.glass_weight * self.glass_transmission(wo, wi) * sqrt(self.base_color);
+        }
+
+        let wh = Vec3::unit_vector(wo + wi);
+        let cos_d = Vec3::dot(wi, wh);
+        let mut f = Vec3::zeros();
+
+        if self.diffuse_weight > 0.0 {
+            // Only what the specular Fresnel lets through on the way in and
+            // out reaches the diffuse lobe, or together they would reflect
+            // more light than arrives.
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+            let transmitted = |cos_theta| {
+                Vec3::ones() - mix(self.specular_f0, Vec3::ones(), schlick_weight(cos_theta))
+            };
+            let diffuse = retro / PI * transmitted(wi.z) * transmitted(wo.z) * self.base_color;
+            let sheen = schlick_weight(cos_d) * self.sheen_color;
+            f += self.diffuse_weight * (diffuse + sheen);
+        }
+
+        let fresnel = mix(self.specular_f0, Vec3::ones(), schlick_weight(cos_d));
+        let specular = self.distribution.d(wh) * self.distribution.g(wo, wi) / (4.0 * wo.z * wi.z);
+        f += self.specular_weight * specular * fresnel;
+
+        if self.clearcoat_weight > 0.0 {
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            let g = smith_g_ggx(wo.z, 0.25) * smith_g_ggx(wi.z, 0.25);
+            let clearcoat = gtr1(wh.z, self.clearcoat_alpha) * fresnel * g;
+            f += self.clearcoat_weight * clearcoat * Vec3::ones();
+        }
+
+        if self.glass_weight > 0.0 {
+            let fresnel = fresnel_dielectric(Vec3::dot(wo, wh), self.eta);
+            let reflection = fresnel * specular;
+            f += self.glass_weight * reflection * Vec3::ones();
+        }
+
+        f
+    }
+
+    /// Density of `sample` producing `wi`, summed over the lobes.
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let glass_probability =
+            1.0 - self.diffuse_probability - self.specular_probability - self.clearcoat_probability;
+
+        if wi.z < 0.0 {
+            return match self.transmission_half_vector(wo, wi) {
+                Some(wm) => {
+                    let fresnel = fresnel_dielectric(Vec3::dot(wo, wm), self.eta);
+                    let denominator = Vec3::dot(wi, wm) + Vec3::dot(wo, wm) / self.eta;
+                    let dwm_dwi = Vec3::dot(wi, wm).abs() / (denominator * denominator);
+                    glass_probability
+                        * (1.0 - fresnel)
+                        * self.distribution.visible_d(wo, wm)
+                        * dwm_dwi
+                }
+                None => 0.0,
+            };
+        }
+
+        let wh = Vec3::unit_vector(wo + wi);
+        let jacobian = 1.0 / (4.0 * Vec3::dot(wo, wh));
+        let specular = self.distribution.visible_d(wo, wh) * jacobian;
+        let clearcoat = gtr1(wh.z, self.clearcoat_alpha) * wh.z * jacobian;
+        let glass = fresnel_dielectric(Vec3::dot(wo, wh), self.eta) * specular;
+
+        self.diffuse_probability * wi.z / PI
+            + self.specular_probability * specular
+            + self.clearcoat_probability * clearcoat
+            + glass_probability * glass
+    }
+
+    fn glass_transmission(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let wm = match self.transmission_half_vector(wo, wi) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+
+        let fresnel = fresnel_dielectric(Vec3::dot(wo, wm), self.eta);
+        let denominator = Vec3::dot(wi, wm) + Vec3::dot(wo, wm) / self.eta;
+        (1.0 - fresnel)
+            * self.distribution.d(wm)
+            * self.distribution.g(wo, wi)
+            * (Vec3::dot(wi, wm) * Vec3::dot(wo, wm) / (denominator * denominator * wo.z * wi.z))
+                .abs()
+            / (self.eta * self.eta)
+    }
+
+    // Generalized half vector of a refraction, or `None` for configurations
+    // that no microfacet could produce.
+    fn transmission_half_vector(&self, wo: Vec3, wi: Vec3) -> Option<Vec3> {
+        let wm = wo + self.eta * wi;
+        if wm.near_zero() {
+            return None;
+        }
+        let wm = Vec3::unit_vector(wm);
+        let wm = if wm.z < 0.0 { -wm } else { wm };
+        if Vec3::dot(wm, wi) * wi.z < 0.0 || Vec3::dot(wm, wo) < 0.0 {
+            return None;
+        }
+        Some(wm)
+    }
+}
+
+fn mix(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+fn sqrt(v: Vec3) -> Vec3 {
+    Vec3::new(v.x.sqrt(), v.y.sqrt(), v.z.sqrt())
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+/// Separable Smith masking for the clearcoat, already divided by `2 cos_theta`.
+fn smith_g_ggx(cos_theta: f64, alpha: f64) -> f64 {
+    let a = alpha * alpha;
+    let b = cos_theta * cos_theta;
+    1.0 / (cos_theta + (a + b - a * b).sqrt())
+}
+
+/// Generalized Trowbridge-Reitz distribution with gamma = 1, which has the
+/// long tails Burley uses for the clearcoat.
+fn gtr1(cos_theta_h: f64, alpha: f64) -> f64 {
+    if cos_theta_h <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_theta_h * cos_theta_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+/// Samples a half vector proportionally to `gtr1 * cos_theta_h`.
+fn sample_gtr1(alpha: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_theta = ((1.0 - a2.powf(1.0 - random_f64())) / (1.0 - a2))
+        .max(0.0)
+        .sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * random_f64();
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::{Hittable, Sphere};
+
+    /// The lobes where a ray straight down hits the top of a sphere made of `material`.
+    fn lobes(material: Principled) -> Lobes {
+        let sphere = Sphere::new(Vec3::zeros(), 1.0, material);
+        let r = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.hit(r, 0.001, f64::INFINITY).unwrap();
+        sphere.material.lobes(&hit)
+    }
+
+    fn constant(value: f64) -> Arc<dyn Texture> {
+        Arc::new(SolidColor::scalar(value))
+    }
+
+    /// Light reflected and transmitted towards `wo` under uniform white
+    /// illumination, by quadrature over the sphere of directions.
+    fn albedo(lobes: &Lobes, wo: Vec3) -> Vec3 {
+        let n = 400;
+        let mut integral = Vec3::zeros();
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI;
+            for j in 0..2 * n {
+                let phi = (j as f64 + 0.5) / (2 * n) as f64 * 2.0 * PI;
+                let wi = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                integral += wi.z.abs() * theta.sin() * lobes.f(wo, wi);
+            }
+        }
+        (PI / n as f64) * (PI / n as f64) * integral
+    }
+
+    #[test]
+    fn test_scatter_pdf_matches_eval() {
+        let material = Principled::new(Arc::new(SolidColor::new(Vec3::new(0.8, 0.5, 0.3))))
+            .metallic(constant(0.3))
+            .roughness(constant(0.5))
+            .clearcoat(constant(0.5))
+            .transmission(constant(0.4));
+        let lobes = lobes(material);
+        let wo = Vec3::unit_vector(Vec3::new(0.6, 0.0, 0.8));
+
+        // Every sampled direction has the density it was sampled with, so
+        // weighting samples by it recovers the integral of the BSDF.
+        let n = 200_000;
+        let mut estimate = Vec3::zeros();
+        for _ in 0..n {
+            let Some(wi) = lobes.sample(wo) else { continue };
+            let pdf = lobes.pdf(wo, wi);
+            if wi.z != 0.0 && pdf > 0.0 {
+                estimate += wi.z.abs() / pdf * lobes.f(wo, wi);
+            }
+        }
+        let estimate = estimate / n as f64;
+        let expected = albedo(&lobes, wo);
+        assert!((estimate - expected).length() < 0.02 * expected.length());
+    }
+
+    #[test]
+    fn test_samples_land_where_pdf_puts_them() {
+        // As many samples go through the surface as the pdf puts below it,
+        // which grazing rough glass gets wrong if lobes leak across.
+        let material = Principled::new(constant(1.0))
+            .transmission(constant(1.0))
+            .roughness(constant(0.9));
+        let lobes = lobes(material);
+        let wo = Vec3::unit_vector(Vec3::new(0.95, 0.0, 0.3));
+
+        let n = 200_000;
+        let below = (0..n)
+            .filter(|_| lobes.sample(wo).is_some_and(|wi| wi.z < 0.0))
+            .count() as f64
+            / n as f64;
+
+        let m = 400;
+        let mut expected = 0.0;
+        for i in 0..m {
+            let theta = PI / 2.0 + (i as f64 + 0.5) / m as f64 * PI / 2.0;
+            for j in 0..2 * m {
+                let phi = (j as f64 + 0.5) / (2 * m) as f64 * 2.0 * PI;
+                let wi = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                expected += theta.sin() * lobes.pdf(wo, wi);
+            }
+        }
+        let expected = expected * (PI / 2.0 / m as f64) * (PI / m as f64);
+        assert!((below - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_white_furnace() {
+        // Metal, plastic and glass each lose some light but never add any.
+        for (metallic, transmission) in [(1.0, 0.0), (0.0, 0.0), (0.0, 1.0)] {
+            for roughness in [0.3, 0.6, 1.0] {
+                let material = Principled::new(constant(1.0))
+                    .metallic(constant(metallic))
+                    .transmission(constant(transmission))
+                    .roughness(constant(roughness));
+                let lobes = lobes(material);
+                for cos_theta in [1.0f64, 0.7, 0.3, 0.1] {
+                    let wo = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+                    let albedo = albedo(&lobes, wo);
+                    assert!(albedo.x <= 1.0 && albedo.y <= 1.0 && albedo.z <= 1.0);
+                    assert!(albedo.y > 0.25);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,759 @@
+use std::f64::consts::PI;
+use std::io;
+
+use crate::bvh::Aabb;
+use crate::json::Json;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::scene_file::{field, flag, invalid, material, number, numbers, vec3, vec3s};
+use crate::sphere::*;
+use crate::vec3::Vec3;
+
+/// Where a primitive built around its own z axis sits in the world: the
+/// origin of its frame and the direction its z axis points in.
+#[derive(Clone, Copy, Debug)]
+struct Placement {
+    center: Vec3,
+    frame: Onb,
+}
+
+impl Placement {
+    fn new(center: Vec3, axis: Vec3) -> Placement {
+        Placement {
+            center,
+            frame: Onb::build_from_w(axis),
+        }
+    }
+
+    /// The placement in the scene file format, with the frame's axes as
+    /// they are rather than rebuilt from the axis.
+    fn to_json(self) -> Json {
+        let Onb { u, v, w } = self.frame;
+        Json::object([
+            ("center", self.center.into()),
+            ("frame", Json::Array(vec![u.into(), v.into(), w.into()])),
+        ])
+    }
+
+    fn from_json(json: &Json) -> io::Result<Placement> {
+        match vec3s(json, "frame")?[..] {
+            [u, v, w] => Ok(Placement {
+                center: vec3(json, "center")?,
+                frame: Onb { u, v, w },
+            }),
+            _ => Err(invalid("\"frame\" isn't three vectors")),
+        }
+    }
+
+    /// `r` in the primitive's frame. The frame isn't scaled, so distances
+    /// along the ray stay the same.
+    fn to_local(self, r: Ray) -> Ray {
+        Ray::new(
+            self.frame.to_local(r.origin - self.center),
+            self.frame.to_local(r.direction),
+        )
+    }
+
+    /// World box around the box from `min` to `max` in the primitive's frame.
+    fn bounds(self, min: Vec3, max: Vec3) -> Aabb {
+        let corners: Vec<Vec3> = (0..8)
+            .map(|i| {
+                let pick = |bit: usize, min: f64, max: f64| if i & bit == 0 { min } else { max };
+                let corner = Vec3::new(
+                    pick(1, min.x, max.x),
+                    pick(2, min.y, max.y),
+                    pick(4, min.z, max.z),
+                );
+                self.center + self.frame.local_vec(corner)
+            })
+            .collect();
+        Aabb::from_points(&corners)
+    }
+
+    /// Hit record from the outward normal and tangents in the primitive's
+    /// frame.
+    fn record<'a>(
+        self,
+        r: Ray,
+        t: f64,
+        outward_normal: Vec3,
+        (u, v): (f64, f64),
+        (dpdu, dpdv): (Vec3, Vec3),
+        mat: &'a dyn Material,
+    ) -> HitRecord<'a> {
+        let outward_normal = Vec3::unit_vector(self.frame.local_vec(outward_normal));
+        let mut hit_record = HitRecord {
+            p: r.at(t),
+            normal: outward_normal,
+            mat,
+            t,
+            u,
+            v,
+            dpdu: self.frame.local_vec(dpdu),
+            dpdv: self.frame.local_vec(dpdv),
+            color: Vec3::ones(),
+            front_facing: false,
+        };
+        hit_record.set_face_normal(r, outward_normal);
+        hit_record
+    }
+}
+
+/// Angle around the z axis as a fraction of a turn, from +x towards +y.
+fn turn_fraction(y: f64, x: f64) -> f64 {
+    let phi = y.atan2(x);
+    (if phi < 0.0 { phi + 2.0 * PI } else { phi }) / (2.0 * PI)
+}
+
+/// Which part of a `Quadric` a ray crossed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum QuadricPart {
+    Side,
+    BottomCap,
+    TopCap,
+}
+
+/// Surface of revolution whose squared radius is a quadratic in height,
+/// x² + y² = a z² + b z + c in its own frame, between two heights. That
+/// covers cylinders, cones, paraboloids and hyperboloids, which are made
+/// with the constructors below, optionally closed with flat caps.
+///
+/// `u` goes around the axis and `v` along it from the bottom to the top; on
+/// the caps `v` goes from the center out to the rim.
+pub struct Quadric<M: Material> {
+    placement: Placement,
+    /// a, b and c of the squared radius.
+    coefficients: [f64; 3],
+    z_min: f64,
+    z_max: f64,
+    capped: bool,
+    pub material: M,
+}
+
+impl<M: Material> Quadric<M> {
+    /// Cylinder standing on the disk around `base_center`, reaching
+    /// `height` along `axis`.
+    pub fn cylinder(
+        base_center: Vec3,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        capped: bool,
+        material: M,
+    ) -> Quadric<M> {
+        Quadric {
+            placement: Placement::new(base_center, axis),
+            coefficients: [0.0, 0.0, radius * radius],
+            z_min: 0.0,
+            z_max: height,
+            capped,
+            material,
+        }
+    }
+
+    /// Cone standing on the disk around `base_center`, with its tip
+    /// `height` along `axis`. Only the base can be capped.
+    pub fn cone(
+        base_center: Vec3,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        capped: bool,
+        material: M,
+    ) -> Quadric<M> {
+        let k = (radius / height).powi(2);
+        Quadric {
+            placement: Placement::new(base_center, axis),
+            coefficients: [k, -2.0 * k * height, k * height * height],
+            z_min: 0.0,
+            z_max: height,
+            capped,
+            material,
+        }
+    }
+
+    /// Open bowl with its lowest point at `vertex`, opening along `axis` to
+    /// `radius` at `height`, like a dish antenna or a reflector.
+    pub fn paraboloid(
+        vertex: Vec3,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        material: M,
+    ) -> Quadric<M> {
+        Quadric {
+            placement: Placement::new(vertex, axis),
+            coefficients: [0.0, radius * radius / height, 0.0],
+            z_min: 0.0,
+            z_max: height,
+            capped: false,
+            material,
+        }
+    }
+
+    /// Open hyperboloid of one sheet, like a cooling tower, `height` tall
+    /// along `axis` with its narrowest ring around `center`. An
+    /// `end_radius` smaller than `waist_radius` makes a barrel instead.
+    pub fn hyperboloid(
+        center: Vec3,
+        axis: Vec3,
+        waist_radius: f64,
+        end_radius: f64,
+        height: f64,
+        material: M,
+    ) -> Quadric<M> {
+        let half_height = 0.5 * height;
+        Quadric {
+            placement: Placement::new(center, axis),
+            coefficients: [
+                (end_radius * end_radius - waist_radius * waist_radius)
+                    / (half_height * half_height),
+                0.0,
+                waist_radius * waist_radius,
+            ],
+            z_min: -half_height,
+            z_max: half_height,
+            capped: false,
+            material,
+        }
+    }
+
+    fn radius_squared(&self, z: f64) -> f64 {
+        let [a, b, c] = self.coefficients;
+        (a * z + b) * z + c
+    }
+
+    /// Distances along the local ray `r` where the line through it crosses
+    /// the surface, nearest first.
+    fn crossings(&self, r: Ray) -> Vec<(f64, QuadricPart)> {
+        let [a, b, c] = self.coefficients;
+        let (o, d) = (r.origin, r.direction);
+        let mut crossings: Vec<(f64, QuadricPart)> = solve_quadratic(
+            d.x * d.x + d.y * d.y - a * d.z * d.z,
+            2.0 * (o.x * d.x + o.y * d.y) - (2.0 * a * o.z + b) * d.z,
+            o.x * o.x + o.y * o.y - (a * o.z + b) * o.z - c,
+        )
+        .into_iter()
+        .filter(|&t| {
+            let z = o.z + t * d.z;
+            z >= self.z_min && z <= self.z_max
+        })
+        .map(|t| (t, QuadricPart::Side))
+        .collect();
+
+        if self.capped && d.z != 0.0 {
+            for (z, part) in [
+                (self.z_min, QuadricPart::BottomCap),
+                (self.z_max, QuadricPart::TopCap),
+            ] {
+                let t = (z - o.z) / d.z;
+                let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+                if x * x + y * y <= self.radius_squared(z) {
+                    crossings.push((t, part));
+                }
+            }
+        }
+        crossings.sort_by(|x, y| x.0.total_cmp(&y.0));
+        crossings
+    }
+
+    fn record(&self, r: Ray, local: Ray, t: f64, part: QuadricPart) -> HitRecord<'_> {
+        let p = local.at(t);
+        let radius = p.x.hypot(p.y);
+        let around = 2.0 * PI * Vec3::new(-p.y, p.x, 0.0);
+        let radial = if radius > 0.0 {
+            Vec3::new(p.x / radius, p.y / radius, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let u = turn_fraction(p.y, p.x);
+
+        match part {
+            QuadricPart::Side => {
+                // The gradient of x² + y² - (a z² + b z + c), and the way the
+                // surface leans out from the axis going up.
+                let [a, b, _] = self.coefficients;
+                let slope = 2.0 * a * p.z + b;
+                let outward_normal = Vec3::new(p.x, p.y, -0.5 * slope);
+                let lean = if radius > 0.0 {
+                    0.5 * slope / radius
+                } else {
+                    0.0
+                };
+                let length = self.z_max - self.z_min;
+                let dpdv = length * (lean * radial + Vec3::new(0.0, 0.0, 1.0));
+                let v = (p.z - self.z_min) / length;
+                self.placement
+                    .record(r, t, outward_normal, (u, v), (around, dpdv), &self.material)
+            }
+            QuadricPart::BottomCap | QuadricPart::TopCap => {
+                let (z, outward_normal) = if part == QuadricPart::TopCap {
+                    (self.z_max, Vec3::new(0.0, 0.0, 1.0))
+                } else {
+                    (self.z_min, Vec3::new(0.0, 0.0, -1.0))
+                };
+                let rim = self.radius_squared(z).sqrt();
+                let v = radius / rim;
+                self.placement.record(
+                    r,
+                    t,
+                    outward_normal,
+                    (u, v),
+                    (around, rim * radial),
+                    &self.material,
+                )
+            }
+        }
+    }
+}
+
+impl Quadric<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Quadric<Box<dyn Material>>> {
+        let coefficients = match numbers(json, "coefficients")?[..] {
+            [a, b, c] => [a, b, c],
+            _ => return Err(invalid("\"coefficients\" isn't three numbers")),
+        };
+        Ok(Quadric {
+            placement: Placement::from_json(field(json, "placement")?)?,
+            coefficients,
+            z_min: number(json, "z_min")?,
+            z_max: number(json, "z_max")?,
+            capped: flag(json, "capped")?,
+            material: material(field(json, "material")?)?,
+        })
+    }
+}
+
+impl<M: Material> Hittable for Quadric<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let local = self.placement.to_local(r);
+        let (t, part) = self
+            .crossings(local)
+            .into_iter()
+            .find(|&(t, _)| t >= t_min && t <= t_max)?;
+        Some(self.record(r, local, t, part))
+    }
+
+    /// The widest the surface gets is at an end, or where the radius
+    /// squared turns around in between.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [a, b, _] = self.coefficients;
+        let mut widest = self
+            .radius_squared(self.z_min)
+            .max(self.radius_squared(self.z_max));
+        if a != 0.0 {
+            let z = -b / (2.0 * a);
+            if z > self.z_min && z < self.z_max {
+                widest = widest.max(self.radius_squared(z));
+            }
+        }
+        let radius = widest.max(0.0).sqrt();
+        let min = Vec3::new(-radius, -radius, self.z_min);
+        let max = Vec3::new(radius, radius, self.z_max);
+        Some(self.placement.bounds(min, max))
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "quadric".into()),
+            ("placement", self.placement.to_json()),
+            (
+                "coefficients",
+                Json::Array(self.coefficients.iter().map(|&c| c.into()).collect()),
+            ),
+            ("z_min", self.z_min.into()),
+            ("z_max", self.z_max.into()),
+            ("capped", self.capped.into()),
+            ("material", self.material.to_json()?),
+        ]))
+    }
+}
+
+/// Ring around `axis` through `center`, `major_radius` from the axis to the
+/// middle of a tube `minor_radius` thick.
+///
+/// `u` goes around the axis and `v` around the tube, starting from its
+/// outer edge and going up over the top.
+pub struct Torus<M: Material> {
+    placement: Placement,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: M,
+}
+
+impl<M: Material> Torus<M> {
+    pub fn new(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        material: M,
+    ) -> Torus<M> {
+        Torus {
+            placement: Placement::new(center, axis),
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+
+    /// Distances along the local ray `r` where the line through it crosses
+    /// the torus, nearest first.
+    ///
+    /// The quartic is badly conditioned far from the torus, so it is solved
+    /// for a unit direction from the point on the line closest to the
+    /// center, and only if that is within the torus's bounding sphere.
+    fn crossings(&self, r: Ray) -> Vec<f64> {
+        let length = r.direction.length();
+        let d = r.direction / length;
+        let shift = -Vec3::dot(r.origin, d);
+        let o = r.origin + shift * d;
+        let bound = self.major_radius + self.minor_radius;
+        if o.length_squared() > bound * bound {
+            return Vec::new();
+        }
+
+        // (|p|² + R² - r²)² = 4 R² (x² + y²) along p = o + s d.
+        let major_squared = self.major_radius * self.major_radius;
+        let f = Vec3::dot(o, d);
+        let g = o.length_squared() + major_squared - self.minor_radius * self.minor_radius;
+        let coefficients = [
+            1.0,
+            4.0 * f,
+            2.0 * g + 4.0 * f * f - 4.0 * major_squared * (d.x * d.x + d.y * d.y),
+            4.0 * f * g - 8.0 * major_squared * (o.x * d.x + o.y * d.y),
+            g * g - 4.0 * major_squared * (o.x * o.x + o.y * o.y),
+        ];
+        solve_quartic(coefficients)
+            .into_iter()
+            .map(|s| (s + shift) / length)
+            .collect()
+    }
+
+    fn record(&self, r: Ray, local: Ray, t: f64) -> HitRecord<'_> {
+        let p = local.at(t);
+        let radius = p.x.hypot(p.y);
+        let radial = if radius > 0.0 {
+            Vec3::new(p.x / radius, p.y / radius, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let outward_normal = p - self.major_radius * radial;
+        let u = turn_fraction(p.y, p.x);
+        let v = turn_fraction(p.z, radius - self.major_radius);
+        let dpdu = 2.0 * PI * Vec3::new(-p.y, p.x, 0.0);
+        let dpdv = 2.0 * PI * (-p.z * radial + Vec3::new(0.0, 0.0, radius - self.major_radius));
+        self.placement
+            .record(r, t, outward_normal, (u, v), (dpdu, dpdv), &self.material)
+    }
+}
+
+impl Torus<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Torus<Box<dyn Material>>> {
+        Ok(Torus {
+            placement: Placement::from_json(field(json, "placement")?)?,
+            major_radius: number(json, "major_radius")?,
+            minor_radius: number(json, "minor_radius")?,
+            material: material(field(json, "material")?)?,
+        })
+    }
+}
+
+impl<M: Material> Hittable for Torus<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let local = self.placement.to_local(r);
+        let t = self
+            .crossings(local)
+            .into_iter()
+            .find(|&t| t >= t_min && t <= t_max)?;
+        Some(self.record(r, local, t))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vec3::new(outer, outer, self.minor_radius);
+        Some(self.placement.bounds(-extent, extent))
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "torus".into()),
+            ("placement", self.placement.to_json()),
+            ("major_radius", self.major_radius.into()),
+            ("minor_radius", self.minor_radius.into()),
+            ("material", self.material.to_json()?),
+        ]))
+    }
+}
+
+/// Real roots of a x² + b x + c in increasing order, falling back to the
+/// linear equation when `a` is zero.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { Vec::new() } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+
+    // Avoids subtracting nearly equal numbers for the smaller root.
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (x0, x1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    if x0 < x1 {
+        vec![x0, x1]
+    } else {
+        vec![x1, x0]
+    }
+}
+
+/// Real roots of x³ + a x² + b x + c, as in Numerical Recipes.
+fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let scale = -2.0 * q.sqrt();
+        [0.0, 2.0 * PI, -2.0 * PI]
+            .iter()
+            .map(|offset| scale * ((theta + offset) / 3.0).cos() - a / 3.0)
+            .collect()
+    } else {
+        let big = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let small = if big == 0.0 { 0.0 } else { q / big };
+        vec![big + small - a / 3.0]
+    }
+}
+
+/// Real roots of a quartic given its coefficients from the highest power
+/// down, in increasing order.
+///
+/// Ferrari's method: the quartic is shifted to have no cubic term and split
+/// into two quadratics with the help of a root of its resolvent cubic. The
+/// roots are then polished with a couple of Newton steps on the original
+/// polynomial.
+pub fn solve_quartic(coefficients: [f64; 5]) -> Vec<f64> {
+    let [c4, c3, c2, c1, c0] = coefficients;
+    if c4 == 0.0 {
+        return Vec::new();
+    }
+    let (a, b, c, d) = (c3 / c4, c2 / c4, c1 / c4, c0 / c4);
+
+    // y⁴ + p y² + q y + r with x = y - a / 4.
+    let a2 = a * a;
+    let p = b - 3.0 / 8.0 * a2;
+    let q = c - 0.5 * a * b + a2 * a / 8.0;
+    let r = d - 0.25 * a * c + a2 * b / 16.0 - 3.0 / 256.0 * a2 * a2;
+
+    let mut roots = Vec::new();
+    if q.abs() < 1.0e-12 {
+        // Biquadratic: a quadratic in y².
+        for y2 in solve_quadratic(1.0, p, r) {
+            if y2 >= 0.0 {
+                roots.push(-y2.sqrt());
+                roots.push(y2.sqrt());
+            }
+        }
+    } else {
+        // (y² + p/2 + m)² = 2m (y - q/4m)² for a positive root m.
+        let m = solve_cubic(p, 0.25 * p * p - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let s = (2.0 * m).sqrt();
+        let half = 0.5 * p + m;
+        roots.extend(solve_quadratic(1.0, -s, half + q / (2.0 * s)));
+        roots.extend(solve_quadratic(1.0, s, half - q / (2.0 * s)));
+    }
+
+    let mut roots: Vec<f64> = roots
+        .into_iter()
+        .map(|y| {
+            let mut x = y - 0.25 * a;
+            for _ in 0..2 {
+                let value = (((x + a) * x + b) * x + c) * x + d;
+                let slope = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+                if slope != 0.0 {
+                    x -= value / slope;
+                }
+            }
+            x
+        })
+        .collect();
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_quartic_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = solve_quartic([1.0, -10.0, 35.0, -50.0, 24.0]);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected).abs() < 1.0e-9);
+        }
+
+        // (x² - 2)(x² + 1)
+        let roots = solve_quartic([1.0, 0.0, -1.0, 0.0, -2.0]);
+        assert_eq!(roots.len(), 2);
+        assert!((roots[1] - 2.0f64.sqrt()).abs() < 1.0e-9);
+
+        assert!(solve_quartic([1.0, 0.0, 0.0, 0.0, 1.0]).is_empty());
+    }
+
+    #[test]
+    fn test_ray_through_torus_and_cylinder() {
+        let material = || Lambertian::new(Vec3::ones());
+        let r = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        // Around the y axis, crossing the tube at x = ±0.75 and ±1.25.
+        let torus = Torus::new(
+            Vec3::zeros(),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            0.25,
+            material(),
+        );
+        let ts: Vec<f64> = torus.crossings(torus.placement.to_local(r));
+        assert_eq!(ts.len(), 4);
+        for (t, expected) in ts.iter().zip([3.75, 4.25, 5.75, 6.25]) {
+            assert!((t - expected).abs() < 1.0e-9);
+        }
+        let hit = torus.hit(r, 4.0, f64::INFINITY).unwrap();
+        assert!(!hit.front_facing);
+        assert!((hit.normal.x + 1.0).abs() < 1.0e-9);
+
+        // Through the side of an upright cylinder, and down through its cap.
+        let cylinder = Quadric::cylinder(
+            Vec3::zeros(),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.5,
+            1.0,
+            true,
+            material(),
+        );
+        let hit = cylinder.hit(
+            Ray::new(Vec3::new(-5.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            0.001,
+            f64::INFINITY,
+        );
+        assert!((hit.unwrap().t - 4.5).abs() < 1.0e-9);
+        let hit = cylinder
+            .hit(
+                Ray::new(Vec3::new(0.1, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 2.0).abs() < 1.0e-9);
+        assert!(hit.front_facing && (hit.normal.y - 1.0).abs() < 1.0e-9);
+
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1.0e-9;
+        let bounds = torus.bounding_box().unwrap();
+        assert!(close(bounds.max, Vec3::new(1.25, 0.25, 1.25)) && close(bounds.min, -bounds.max));
+        let bounds = cylinder.bounding_box().unwrap();
+        assert!(
+            close(bounds.min, Vec3::new(-0.5, 0.0, -0.5))
+                && close(bounds.max, Vec3::new(0.5, 1.0, 0.5))
+        );
+    }
+
+    /// Hits `object` with `r` and checks the hit against the expected
+    /// distance, outward normal and texture coordinates, and that nearby hits
+    /// move along `dpdu` and `dpdv` as their coordinates change.
+    fn check_hit(object: &dyn Hittable, r: Ray, t: f64, normal: Vec3, (u, v): (f64, f64)) {
+        let hit = object.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - t).abs() < 1.0e-9);
+        assert!((hit.outward_normal() - Vec3::unit_vector(normal)).length() < 1.0e-9);
+        assert!((hit.u - u).abs() < 1.0e-9 && (hit.v - v).abs() < 1.0e-9);
+
+        let basis = Onb::build_from_w(r.direction);
+        for offset in [1.0e-6 * basis.u, 1.0e-6 * basis.v] {
+            let nearby = object
+                .hit(
+                    Ray::new(r.origin + offset, r.direction),
+                    0.001,
+                    f64::INFINITY,
+                )
+                .unwrap();
+            let predicted = hit.p + (nearby.u - hit.u) * hit.dpdu + (nearby.v - hit.v) * hit.dpdv;
+            assert!((predicted - nearby.p).length() < 1.0e-2 * offset.length());
+        }
+    }
+
+    #[test]
+    fn test_every_shape_has_normals_and_uvs() {
+        let material = || Lambertian::new(Vec3::ones());
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        // Along +x at height `z`, meeting each shape at u = 1/2, on its -x side.
+        let along_x = |z: f64| Ray::new(Vec3::new(-5.0, 0.0, z), Vec3::new(1.0, 0.0, 0.0));
+
+        let cylinder = Quadric::cylinder(Vec3::zeros(), up, 0.5, 2.0, true, material());
+        check_hit(
+            &cylinder,
+            along_x(0.5),
+            4.5,
+            Vec3::new(-1.0, 0.0, 0.0),
+            (0.5, 0.25),
+        );
+        // The cap's v runs from its center to the rim.
+        let down = Ray::new(Vec3::new(-0.25, 0.0, 3.0), -up);
+        check_hit(&cylinder, down, 1.0, up, (0.5, 0.5));
+
+        // Radius 1 - z / 2, so the side leans in by half a unit per unit up.
+        let cone = Quadric::cone(Vec3::zeros(), up, 1.0, 2.0, true, material());
+        check_hit(
+            &cone,
+            along_x(1.0),
+            4.5,
+            Vec3::new(-1.0, 0.0, 0.5),
+            (0.5, 0.5),
+        );
+
+        // Radius squared z.
+        let paraboloid = Quadric::paraboloid(Vec3::zeros(), up, 1.0, 1.0, material());
+        check_hit(
+            &paraboloid,
+            along_x(0.25),
+            4.5,
+            Vec3::new(-1.0, 0.0, -1.0),
+            (0.5, 0.25),
+        );
+
+        // Radius squared 3/4 z² + 1/4, narrowest at z = 0.
+        let hyperboloid = Quadric::hyperboloid(Vec3::zeros(), up, 0.5, 1.0, 2.0, material());
+        check_hit(
+            &hyperboloid,
+            along_x(0.0),
+            4.5,
+            Vec3::new(-1.0, 0.0, 0.0),
+            (0.5, 0.5),
+        );
+        let radius = (0.75f64 * 0.25 + 0.25).sqrt();
+        check_hit(
+            &hyperboloid,
+            along_x(0.5),
+            5.0 - radius,
+            Vec3::new(-radius, 0.0, -0.375),
+            (0.5, 0.75),
+        );
+
+        // Around z, hitting the outer edge of the tube, where v starts.
+        let torus = Torus::new(Vec3::zeros(), up, 1.0, 0.25, material());
+        check_hit(
+            &torus,
+            along_x(0.0),
+            3.75,
+            Vec3::new(-1.0, 0.0, 0.0),
+            (0.5, 0.0),
+        );
+    }
+}
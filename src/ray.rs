@@ -1,14 +1,22 @@
+use crate::spectrum::Wavelengths;
 use crate::vec3::Vec3;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// Set in spectral mode. Materials leave it empty on the rays they
+    /// scatter, and the integrator carries it over from the incoming ray.
+    pub wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelengths: None,
+        }
     }
 
     pub fn at(self, t: f64) -> Vec3 {
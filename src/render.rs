@@ -1,5 +1,5 @@
+use minifb::{Key, Window, WindowOptions};
 use std::sync::mpsc::*;
-use minifb::{WindowOptions, Window, Key};
 use triple_buffer::*;
 
 pub enum RenderStatus {
@@ -8,12 +8,12 @@ pub enum RenderStatus {
 }
 
 pub struct Render {
-    buffer_output : Output<Vec<u32>>,
-    receiver : Receiver<RenderStatus>,
+    buffer_output: Output<Vec<u32>>,
+    receiver: Receiver<RenderStatus>,
 }
 
 impl Render {
-    pub fn new(buffer_output : Output<Vec<u32>>, receiver : Receiver<RenderStatus>) -> Render {
+    pub fn new(buffer_output: Output<Vec<u32>>, receiver: Receiver<RenderStatus>) -> Render {
         Render {
             buffer_output,
             receiver,
@@ -52,8 +52,7 @@ impl Render {
                         if self.buffer_output.update() {
                             let output = self.buffer_output.output_buffer();
                             for (i, o) in output.iter().enumerate() {
-                                let index =
-                                    ((height - (counter as u32) - 1) * width + (i as u32)) as usize;
+                                let index = ((height - counter - 1) * width + (i as u32)) as usize;
                                 render_data[index] = *o;
                             }
                             window
@@ -69,4 +68,4 @@ impl Render {
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,23 +1,1530 @@
+use crate::atmosphere::HeightFog;
+use crate::bump::*;
+use crate::bvh::Aabb;
+use crate::coated::Coated;
+use crate::csg::Csg;
+use crate::cuboid::Cuboid;
+use crate::curve::*;
+use crate::diffuse::*;
+use crate::fabric::*;
+use crate::hair::Hair;
+use crate::heightfield::Heightfield;
+use crate::light::*;
+use crate::material::*;
+use crate::mesh::Mesh;
+use crate::metaball::*;
+use crate::mix::*;
+use crate::principled::Principled;
+use crate::quadric::*;
 use crate::random::*;
-use crate::Vec3;
-use crate::HittableList;
+use crate::sdf::*;
+use crate::sky::*;
+use crate::subsurface::Subsurface;
+use crate::texture::*;
+use crate::thin_film::ThinFilm;
 use crate::Camera;
-use crate::material::*;
+use crate::HittableList;
 use crate::Sphere;
+use crate::Vec3;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
 pub struct Scene {
-    pub objects : HittableList,
-    pub camera : Camera
+    pub objects: HittableList,
+    pub camera: Camera,
+    pub sky: Sky,
+    pub lights: Vec<Box<dyn Light>>,
+    pub fog: Option<HeightFog>,
 }
 
+/// Builds a scene for the given aspect ratio.
+pub type SceneBuilder = fn(f64) -> Scene;
+
 impl Scene {
+    /// The built-in scenes `from_name` knows, by name.
+    pub const BUILT_IN: [(&'static str, SceneBuilder); 23] = [
+        ("one_weekend", Self::one_weekend_scene),
+        ("hazy_weekend", Self::hazy_weekend_scene),
+        ("sunny_weekend", Self::sunny_weekend_scene),
+        ("lights", Self::lights_scene),
+        ("metals", Self::metals_scene),
+        ("frosted", Self::frosted_scene),
+        ("principled", Self::principled_scene),
+        ("coated", Self::coated_scene),
+        ("tinted_glass", Self::tinted_glass_scene),
+        ("prism", Self::prism_scene),
+        ("thin_film", Self::thin_film_scene),
+        ("subsurface", Self::subsurface_scene),
+        ("bumpy", Self::bumpy_scene),
+        ("mix", Self::mix_scene),
+        ("diffuse", Self::diffuse_scene),
+        ("fabric", Self::fabric_scene),
+        ("hair", Self::hair_scene),
+        ("csg", Self::csg_scene),
+        ("sdf", Self::sdf_scene),
+        ("quadrics", Self::quadrics_scene),
+        ("terrain", Self::terrain_scene),
+        ("metaballs", Self::metaballs_scene),
+        ("models", Self::models_scene),
+    ];
+
+    pub fn from_name(name: &str, aspect_ratio: f64) -> Scene {
+        if let Some((_, scene)) = Self::BUILT_IN
+            .iter()
+            .find(|(built_in, _)| *built_in == name)
+        {
+            return scene(aspect_ratio);
+        }
+        match name {
+            _ if name.ends_with(".gltf") || name.ends_with(".glb") => {
+                Self::from_gltf(name, aspect_ratio).unwrap_or_else(|error| {
+                    eprintln!("Failed to load {}: {}", name, error);
+                    std::process::exit(1)
+                })
+            }
+            _ if name.ends_with(".json") => Self::load(name).unwrap_or_else(|error| {
+                eprintln!("Failed to load {}: {}", name, error);
+                std::process::exit(1)
+            }),
+            _ if name.ends_with(".pbrt") => {
+                Self::from_pbrt(name, aspect_ratio).unwrap_or_else(|error| {
+                    eprintln!("Failed to load {}: {}", name, error);
+                    std::process::exit(1)
+                })
+            }
+            _ => Self::one_weekend_scene(aspect_ratio),
+        }
+    }
+
     pub fn one_weekend_scene(aspect_ratio: f64) -> Scene {
         Scene {
             objects: Self::random_scene(),
-            camera: Self::get_camera(aspect_ratio)
+            camera: Self::get_camera(aspect_ratio),
+            sky: Sky::Gradient,
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    pub fn hazy_weekend_scene(aspect_ratio: f64) -> Scene {
+        Scene {
+            sky: Sky::Daylight(DaylightSky::new(12.0, 120.0, 6.0)),
+            fog: Some(HeightFog::new(0.06, 0.8, Vec3::new(0.9, 0.9, 0.9))),
+            ..Self::one_weekend_scene(aspect_ratio)
+        }
+    }
+
+    pub fn sunny_weekend_scene(aspect_ratio: f64) -> Scene {
+        Scene {
+            sky: Sky::Daylight(DaylightSky::new(35.0, 120.0, 2.5)),
+            ..Self::one_weekend_scene(aspect_ratio)
         }
     }
 
+    pub fn lights_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(-2.2, 1.0, 0.0),
+            1.0,
+            Lambertian::new(Vec3::new(0.8, 0.3, 0.2)),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            Lambertian::new(Vec3::new(0.7, 0.7, 0.7)),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(2.2, 1.0, 0.0),
+            1.0,
+            Dielectric::new(1.5),
+        )));
+
+        let lights: Vec<Box<dyn Light>> = vec![
+            Box::new(PointLight::new(
+                Vec3::new(-3.0, 4.0, 3.0),
+                Vec3::new(1.0, 0.85, 0.6),
+                30.0,
+            )),
+            Box::new(SpotLight::new(
+                Vec3::new(4.0, 5.0, 2.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.6, 0.8, 1.0),
+                60.0,
+                30.0,
+                20.0,
+            )),
+            Box::new(DirectionalLight::new(
+                Vec3::new(0.0, -1.0, -1.0),
+                Vec3::ones(),
+                0.3,
+            )),
+        ];
+
+        let lookfrom = Vec3::new(0.0, 2.5, 9.0);
+        let lookat = Vec3::new(0.0, 0.8, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Uniform(Vec3::new(0.05, 0.05, 0.06)),
+            lights,
+            fog: None,
+        }
+    }
+
+    pub fn metals_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.3, 0.3, 0.3)),
+        )));
+
+        let x = |i: i32| -5.0 + 2.0 * i as f64;
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(0), 0.8, 0.0),
+            0.8,
+            Conductor::gold(0.05),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(1), 0.8, 0.0),
+            0.8,
+            Conductor::copper(0.2),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(2), 0.8, 0.0),
+            0.8,
+            Conductor::aluminum(0.35),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(3), 0.8, 0.0),
+            0.8,
+            Conductor::silver(0.0),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(4), 0.8, 0.0),
+            0.8,
+            Conductor::iron(0.5),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(5), 0.8, 0.0),
+            0.8,
+            Conductor::anisotropic(
+                Vec3::new(1.657, 0.880, 0.521),
+                Vec3::new(9.224, 6.270, 4.837),
+                0.1,
+                0.6,
+            ),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 3.0, 12.0);
+        let lookat = Vec3::new(0.0, 0.6, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                30.0,
+                aspect_ratio,
+                0.0,
+                12.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(40.0, 60.0, 3.0)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    pub fn frosted_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        // Colourful backdrop to look at through the glass.
+        for i in 0..12 {
+            let albedo = Vec3::new(
+                0.5 + 0.5 * (i as f64).cos(),
+                0.5 + 0.5 * (i as f64 * 1.7).sin(),
+                0.5 + 0.5 * (i as f64 * 0.6).cos(),
+            );
+            world.add(Box::new(Sphere::new(
+                Vec3::new(-5.5 + i as f64, 0.4, -3.0),
+                0.4,
+                Lambertian::new(albedo),
+            )));
+        }
+
+        for (i, roughness) in [0.0, 0.1, 0.25, 0.5].iter().enumerate() {
+            world.add(Box::new(Sphere::new(
+                Vec3::new(-3.3 + 2.2 * i as f64, 1.0, 0.0),
+                1.0,
+                RoughDielectric::new(1.5, *roughness),
+            )));
+        }
+
+        let lookfrom = Vec3::new(0.0, 2.0, 10.0);
+        let lookat = Vec3::new(0.0, 0.8, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                35.0,
+                aspect_ratio,
+                0.0,
+                10.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(50.0, 100.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    pub fn principled_scene(aspect_ratio: f64) -> Scene {
+        let color = |r, g, b| -> Arc<dyn Texture> { Arc::new(SolidColor::new(Vec3::new(r, g, b))) };
+        let scalar = |value| -> Arc<dyn Texture> { Arc::new(SolidColor::scalar(value)) };
+
+        let mut world = HittableList::new();
+        let checker = Arc::new(CheckerTexture::new(
+            1.0,
+            color(0.8, 0.8, 0.8),
+            color(0.2, 0.3, 0.4),
+        ));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Principled::new(checker.clone()).roughness(Arc::new(CheckerTexture::new(
+                1.0,
+                scalar(0.8),
+                scalar(0.1),
+            ))),
+        )));
+
+        // Back row: metallic on the left to dielectric on the right, roughness going up.
+        for i in 0..5 {
+            let t = i as f64 / 4.0;
+            world.add(Box::new(Sphere::new(
+                Vec3::new(-4.4 + 2.2 * i as f64, 1.0, -1.5),
+                1.0,
+                Principled::new(color(0.9, 0.5, 0.2))
+                    .metallic(scalar(1.0 - t))
+                    .roughness(scalar(0.1 + 0.6 * t)),
+            )));
+        }
+
+        // Front row: specular tint, sheen, clearcoat, glass and tinted glass.
+        let front = [
+            Principled::new(color(0.1, 0.4, 0.8))
+                .specular(scalar(1.0))
+                .specular_tint(scalar(1.0))
+                .roughness(scalar(0.3)),
+            Principled::new(color(0.6, 0.1, 0.3))
+                .sheen(scalar(1.0))
+                .roughness(scalar(0.9)),
+            Principled::new(color(0.7, 0.05, 0.05))
+                .roughness(scalar(0.6))
+                .clearcoat(scalar(1.0))
+                .clearcoat_gloss(scalar(0.95)),
+            Principled::new(color(1.0, 1.0, 1.0))
+                .transmission(scalar(1.0))
+                .roughness(scalar(0.05))
+                .index_of_refraction(scalar(1.45)),
+            Principled::new(color(0.5, 0.9, 0.6))
+                .transmission(scalar(1.0))
+                .roughness(scalar(0.3))
+                .index_of_refraction(scalar(1.6)),
+        ];
+        for (i, material) in front.into_iter().enumerate() {
+            world.add(Box::new(Sphere::new(
+                Vec3::new(-4.4 + 2.2 * i as f64, 0.7, 1.2),
+                0.7,
+                material,
+            )));
+        }
+
+        let lookfrom = Vec3::new(0.0, 4.0, 12.0);
+        let lookat = Vec3::new(0.0, 0.6, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                35.0,
+                aspect_ratio,
+                0.0,
+                12.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(45.0, 70.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    pub fn coated_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        let clear = Vec3::ones();
+        let x = |i: i32| -3.3 + 2.2 * i as f64;
+        // Red car paint and gold flake paint under a glossy clear coat.
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(0), 1.0, 0.0),
+            1.0,
+            Coated::new(
+                Lambertian::new(Vec3::new(0.6, 0.03, 0.03)),
+                1.5,
+                0.0,
+                clear,
+                0.0,
+            ),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(1), 1.0, 0.0),
+            1.0,
+            Coated::new(Conductor::gold(0.35), 1.5, 0.0, clear, 0.0),
+        )));
+        // Lacquered wood: a thick amber varnish over a light diffuse base.
+        let wood = Vec3::new(0.75, 0.55, 0.35);
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(2), 1.0, 0.0),
+            1.0,
+            Coated::new(
+                Lambertian::new(wood),
+                1.5,
+                0.1,
+                Vec3::new(0.9, 0.6, 0.3),
+                0.5,
+            ),
+        )));
+        // Satin finish: a rough coat over blue plastic.
+        world.add(Box::new(Sphere::new(
+            Vec3::new(x(3), 1.0, 0.0),
+            1.0,
+            Coated::new(
+                Lambertian::new(Vec3::new(0.1, 0.2, 0.6)),
+                1.5,
+                0.4,
+                clear,
+                0.0,
+            ),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 2.5, 10.0);
+        let lookat = Vec3::new(0.0, 0.8, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                35.0,
+                aspect_ratio,
+                0.0,
+                10.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(40.0, 60.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    pub fn tinted_glass_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.8, 0.8, 0.8)),
+        )));
+
+        // The same green glass gets darker and more saturated as it gets thicker.
+        let green = Vec3::new(0.4, 0.8, 0.5);
+        let mut x = -4.0;
+        for radius in [0.25, 0.5, 0.75, 1.0] {
+            x += radius;
+            world.add(Box::new(Sphere::new(
+                Vec3::new(x, radius, 0.0),
+                radius,
+                Dielectric::tinted(1.5, green, 0.5),
+            )));
+            x += radius + 0.3;
+        }
+
+        // Frosted amber glass, like a bottle.
+        world.add(Box::new(Sphere::new(
+            Vec3::new(3.0, 1.0, 0.0),
+            1.0,
+            RoughDielectric::tinted(1.5, 0.2, Vec3::new(0.9, 0.5, 0.1), 0.5),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 3.0, 10.0);
+        let lookat = Vec3::new(0.0, 0.7, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                10.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(55.0, 80.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Glass and diamond against a low sun, to be rendered with `-Spectral`:
+    /// the sun seen through each sphere is spread into a spectrum.
+    pub fn prism_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        let checker = CheckerTexture::new(
+            1.0,
+            Arc::new(SolidColor::new(Vec3::new(0.1, 0.1, 0.1))),
+            Arc::new(SolidColor::new(Vec3::new(0.9, 0.9, 0.9))),
+        );
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Principled::new(Arc::new(checker)),
+        )));
+
+        for (x, dispersion) in [
+            (-2.4, Dispersion::BK7),
+            (0.0, Dispersion::DENSE_FLINT),
+            (2.4, Dispersion::DIAMOND),
+        ] {
+            world.add(Box::new(Sphere::new(
+                Vec3::new(x, 1.0, 0.0),
+                1.0,
+                Dielectric::dispersive(dispersion),
+            )));
+        }
+
+        let lookfrom = Vec3::new(0.0, 1.5, 9.0);
+        let lookat = Vec3::new(0.0, 1.0, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(8.0, -90.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Soap bubbles, whose film drains and thins towards the top, and glass
+    /// with an anti-reflection coating next to uncoated glass.
+    pub fn thin_film_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.2, 0.2, 0.25)),
+        )));
+
+        let thickness = |value| -> Arc<dyn Texture> { Arc::new(SolidColor::scalar(value)) };
+        for (center, radius) in [
+            (Vec3::new(-2.2, 1.4, 0.0), 1.0),
+            (Vec3::new(-0.4, 2.2, -1.5), 0.7),
+        ] {
+            let soap = ThinFilm::new(
+                Arc::new(GradientTexture::new(thickness(900.0), thickness(150.0))),
+                1.33,
+            );
+            world.add(Box::new(Sphere::new(
+                center,
+                radius,
+                Dielectric::new(1.0).with_film(soap),
+            )));
+        }
+
+        // A quarter wave of magnesium fluoride at 550 nm, as on camera lenses.
+        let coating = ThinFilm::new(thickness(100.0), 1.38);
+        world.add(Box::new(Sphere::new(
+            Vec3::new(1.0, 0.8, 0.5),
+            0.8,
+            Dielectric::new(1.5).with_film(coating),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(2.9, 0.8, 0.5),
+            0.8,
+            Dielectric::new(1.5),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 2.0, 10.0);
+        let lookat = Vec3::new(0.0, 1.2, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                10.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(40.0, 60.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Marble, skin, wax and milk next to a diffuse sphere of the same color
+    /// as the marble, lit from behind so light bleeds through the thin parts.
+    pub fn subsurface_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.4, 0.4, 0.4)),
+        )));
+
+        let white = Vec3::new(0.85, 0.85, 0.8);
+        world.add(Box::new(Sphere::new(
+            Vec3::new(-4.0, 0.8, 0.0),
+            0.8,
+            Lambertian::new(white),
+        )));
+        let materials = [
+            Subsurface::new(white, Vec3::new(0.3, 0.3, 0.25), 1.5, 0.2),
+            Subsurface::new(
+                Vec3::new(0.85, 0.6, 0.5),
+                Vec3::new(0.35, 0.12, 0.06),
+                1.4,
+                0.4,
+            ),
+            Subsurface::new(
+                Vec3::new(0.9, 0.75, 0.4),
+                Vec3::new(0.5, 0.4, 0.2),
+                1.45,
+                0.3,
+            ),
+            Subsurface::new(
+                Vec3::new(0.95, 0.95, 0.9),
+                Vec3::new(0.05, 0.04, 0.03),
+                1.35,
+                0.1,
+            ),
+        ];
+        for (i, material) in materials.into_iter().enumerate() {
+            world.add(Box::new(Sphere::new(
+                Vec3::new(-2.0 + 2.0 * i as f64, 0.8, 0.0),
+                0.8,
+                material,
+            )));
+        }
+
+        let lookfrom = Vec3::new(0.0, 2.5, 10.0);
+        let lookat = Vec3::new(0.0, 0.6, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                45.0,
+                aspect_ratio,
+                0.0,
+                10.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(25.0, -70.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// A low-poly sphere mesh, the same mesh bump mapped into an orange, and
+    /// a sphere with a normal map of tilted tiles.
+    pub fn bumpy_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        let orange = Vec3::new(0.9, 0.4, 0.05);
+        world.add(Box::new(Mesh::uv_sphere(
+            Vec3::new(-2.2, 1.0, 0.0),
+            1.0,
+            16,
+            8,
+            Principled::new(Arc::new(SolidColor::new(orange))),
+        )));
+        let peel = Principled::new(Arc::new(SolidColor::new(orange)))
+            .roughness(Arc::new(SolidColor::scalar(0.35)));
+        world.add(Box::new(Mesh::uv_sphere(
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            16,
+            8,
+            BumpMapped::new(peel, Arc::new(NoiseTexture::new(20.0)), 0.04),
+        )));
+
+        // Tangent space normals tilted two different ways, packed into [0, 1].
+        let tilted = |x: f64, y: f64| -> Arc<dyn Texture> {
+            let n = Vec3::unit_vector(Vec3::new(x, y, 1.0));
+            Arc::new(SolidColor::new(0.5 * (n + Vec3::ones())))
+        };
+        world.add(Box::new(Sphere::new(
+            Vec3::new(2.2, 1.0, 0.0),
+            1.0,
+            NormalMapped::new(
+                Conductor::copper(0.2),
+                Arc::new(CheckerTexture::new(
+                    0.25,
+                    tilted(0.4, 0.4),
+                    tilted(-0.4, -0.4),
+                )),
+            ),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 2.5, 9.0);
+        let lookat = Vec3::new(0.0, 0.9, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(40.0, 60.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Diffuse paint blended with metal evenly, in a checker pattern and by
+    /// noise, behind a cut out quad that casts a matching shadow.
+    pub fn mix_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        let paint = || Lambertian::new(Vec3::new(0.1, 0.3, 0.7));
+        let weights: [Arc<dyn Texture>; 3] = [
+            Arc::new(SolidColor::scalar(0.5)),
+            Arc::new(CheckerTexture::new(
+                0.3,
+                Arc::new(SolidColor::scalar(0.0)),
+                Arc::new(SolidColor::scalar(1.0)),
+            )),
+            Arc::new(NoiseTexture::new(4.0)),
+        ];
+        for (i, weight) in weights.into_iter().enumerate() {
+            world.add(Box::new(Sphere::new(
+                Vec3::new(-2.2 + 2.2 * i as f64, 1.0, 0.0),
+                1.0,
+                Mix::new(paint(), Conductor::aluminum(0.1), weight),
+            )));
+        }
+
+        // A quad in front of the spheres with a grid of holes cut out of it.
+        let holes = CheckerTexture::new(
+            0.25,
+            Arc::new(SolidColor::scalar(1.0)),
+            Arc::new(SolidColor::scalar(0.0)),
+        );
+        world.add(Box::new(Mesh::new(
+            vec![
+                Vec3::new(-1.0, 0.01, 2.0),
+                Vec3::new(1.0, 0.01, 2.0),
+                Vec3::new(1.0, 1.51, 2.0),
+                Vec3::new(-1.0, 1.51, 2.0),
+            ],
+            Vec::new(),
+            vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            vec![[0, 1, 2], [0, 2, 3]],
+            AlphaCutout::new(Lambertian::new(Vec3::new(0.2, 0.6, 0.1)), Arc::new(holes)),
+        )));
+
+        let lookfrom = Vec3::new(1.0, 2.5, 9.0);
+        let lookat = Vec3::new(0.0, 0.9, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(35.0, 60.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Lambertian and Oren-Nayar clay and a moon-like retroreflective sphere,
+    /// lit from just behind the camera, and a lampshade lit from inside.
+    pub fn diffuse_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.4, 0.4, 0.4)),
+        )));
+
+        let clay = Vec3::new(0.7, 0.45, 0.3);
+        world.add(Box::new(Sphere::new(
+            Vec3::new(-3.3, 1.0, 0.0),
+            1.0,
+            Lambertian::new(clay),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(-1.1, 1.0, 0.0),
+            1.0,
+            OrenNayar::new(clay, 30.0),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(1.1, 1.0, 0.0),
+            1.0,
+            RetroreflectiveDiffuse::new(Vec3::new(0.9, 0.9, 0.9), 1.0, 0.05),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(3.3, 1.0, 0.0),
+            1.0,
+            DiffuseTransmission::new(Vec3::new(0.5, 0.45, 0.4), Vec3::new(0.4, 0.3, 0.15)),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 1.5, 10.0);
+        let lookat = Vec3::new(0.0, 1.0, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                10.0,
+            ),
+            sky: Sky::Uniform(Vec3::new(0.05, 0.05, 0.08)),
+            lights: vec![
+                Box::new(DirectionalLight::new(
+                    Vec3::new(0.15, -0.25, -1.0),
+                    Vec3::ones(),
+                    2.5,
+                )),
+                Box::new(PointLight::new(
+                    Vec3::new(3.3, 1.0, 0.0),
+                    Vec3::new(1.0, 0.8, 0.5),
+                    15.0,
+                )),
+            ],
+            fog: None,
+        }
+    }
+
+    /// A velvet sphere, a sphere wrapped in thread running pole to pole, and
+    /// a satin curtain hanging in folds behind them.
+    pub fn fabric_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        world.add(Box::new(Sphere::new(
+            Vec3::new(-1.3, 1.0, 0.5),
+            1.0,
+            Sheen::new(
+                Lambertian::new(Vec3::new(0.25, 0.02, 0.05)),
+                Vec3::new(1.0, 0.6, 0.7),
+                0.3,
+            ),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3::new(1.3, 1.0, 0.5),
+            1.0,
+            Fabric::new(
+                Arc::new(SolidColor::new(Vec3::new(0.05, 0.15, 0.35))),
+                Vec3::new(0.6, 0.6, 0.6),
+                90.0,
+                8.0,
+            ),
+        )));
+
+        let satin = Fabric::new(
+            Arc::new(SolidColor::new(Vec3::new(0.35, 0.2, 0.03))),
+            Vec3::new(0.8, 0.65, 0.3),
+            90.0,
+            10.0,
+        );
+        world.add(Box::new(Self::curtain(
+            Vec3::new(0.0, 0.0, -1.5),
+            8.0,
+            3.5,
+            satin,
+        )));
+
+        let lookfrom = Vec3::new(0.0, 2.0, 9.0);
+        let lookat = Vec3::new(0.0, 1.2, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(30.0, 40.0, 2.5)),
+            lights: vec![Box::new(PointLight::new(
+                Vec3::new(-3.0, 4.0, 4.0),
+                Vec3::ones(),
+                30.0,
+            ))],
+            fog: None,
+        }
+    }
+
+    /// Sheet `width` wide and `height` tall standing on `bottom_center`,
+    /// gathered into folds along its width. u runs across and v up.
+    fn curtain<M: Material>(bottom_center: Vec3, width: f64, height: f64, material: M) -> Mesh<M> {
+        const COLUMNS: usize = 96;
+        const ROWS: usize = 4;
+        let (depth, frequency) = (0.15, 5.0);
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        for row in 0..=ROWS {
+            let v = row as f64 / ROWS as f64;
+            for column in 0..=COLUMNS {
+                let u = column as f64 / COLUMNS as f64;
+                let x = (u - 0.5) * width;
+                let z = depth * (frequency * x).sin();
+                positions.push(bottom_center + Vec3::new(x, v * height, z));
+                normals.push(Vec3::unit_vector(Vec3::new(
+                    -depth * frequency * (frequency * x).cos(),
+                    0.0,
+                    1.0,
+                )));
+                uvs.push((u, v));
+            }
+        }
+
+        let mut triangles = Vec::new();
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let i0 = row * (COLUMNS + 1) + column;
+                let (i1, i2) = (i0 + 1, i0 + COLUMNS + 1);
+                triangles.push([i0, i1, i2 + 1]);
+                triangles.push([i0, i2 + 1, i2]);
+            }
+        }
+
+        Mesh::new(positions, normals, uvs, triangles, material)
+    }
+
+    /// Balls of blonde, black, red and dyed fur, and a copper spring made of
+    /// tube-shaded curves.
+    pub fn hair_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        let centers = [-3.0, -1.0, 1.0, 3.0].map(|x| Vec3::new(x, 0.9, 0.0));
+        world.add(Box::new(Self::fur_ball(
+            centers[0],
+            Hair::from_melanin(0.3, 0.0),
+        )));
+        world.add(Box::new(Self::fur_ball(
+            centers[1],
+            Hair::from_melanin(8.0, 0.0),
+        )));
+        world.add(Box::new(Self::fur_ball(
+            centers[2],
+            Hair::from_melanin(0.5, 3.0),
+        )));
+        world.add(Box::new(Self::fur_ball(
+            centers[3],
+            Hair::from_color(Vec3::new(0.15, 0.35, 0.8)).roughness(0.5, 0.5),
+        )));
+        for center in centers {
+            world.add(Box::new(Sphere::new(
+                center,
+                0.5,
+                Lambertian::new(Vec3::new(0.1, 0.08, 0.06)),
+            )));
+        }
+
+        // A coil of quarter circles, rising as it goes around.
+        let (radius, rise, turns) = (0.35, 0.12, 5);
+        let k = 0.5523 * radius;
+        let center = Vec3::new(0.0, 0.1, 2.2);
+        let mut coil = Vec::new();
+        for quarter in 0..4 * turns {
+            let angle = quarter as f64 * PI / 2.0;
+            let (start, end) = (angle, angle + PI / 2.0);
+            let point = |a: f64| Vec3::new(a.cos(), 0.0, a.sin());
+            let tangent = |a: f64| Vec3::new(-a.sin(), 0.0, a.cos());
+            let y = |a: f64| Vec3::new(0.0, rise * a / (2.0 * PI), 0.0);
+            coil.push(CurveSegment::new(
+                [
+                    center + radius * point(start) + y(start),
+                    center + radius * point(start) + k * tangent(start) + y(start + PI / 6.0),
+                    center + radius * point(end) - k * tangent(end) + y(end - PI / 6.0),
+                    center + radius * point(end) + y(end),
+                ],
+                [0.07, 0.07],
+            ));
+        }
+        world.add(Box::new(Curves::new(
+            coil,
+            CurveType::Cylinder,
+            Conductor::copper(0.25),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 2.5, 9.0);
+        let lookat = Vec3::new(0.0, 0.8, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(40.0, 50.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Strands growing out of a ball around `center` and drooping as they go.
+    fn fur_ball<M: Material>(center: Vec3, material: M) -> Curves<M> {
+        const STRANDS: usize = 4000;
+        let (radius, length) = (0.5, 0.45);
+        let droop = Vec3::new(0.0, -length, 0.0);
+        let strands = (0..STRANDS)
+            .map(|_| {
+                let direction = Vec3::unit_vector(Vec3::random_in_unit_sphere());
+                let root = center + radius * direction;
+                let tip = root + length * direction;
+                CurveSegment::new(
+                    [
+                        root,
+                        root + length / 3.0 * direction,
+                        root + 2.0 * length / 3.0 * direction + 0.15 * droop,
+                        tip + 0.4 * droop,
+                    ],
+                    [0.01, 0.002],
+                )
+            })
+            .collect();
+        Curves::new(strands, CurveType::Flat, material)
+    }
+
+    /// A glass lens cut from two spheres over a checkerboard, a rounded die
+    /// with its pips drilled out, and two glass spheres merged into one.
+    pub fn csg_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        let checker = CheckerTexture::new(
+            0.5,
+            Arc::new(SolidColor::new(Vec3::new(0.2, 0.3, 0.1))),
+            Arc::new(SolidColor::new(Vec3::new(0.9, 0.9, 0.9))),
+        );
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Principled::new(Arc::new(checker)).roughness(Arc::new(SolidColor::scalar(1.0))),
+        )));
+
+        // Biconvex lens, half a unit thick in the middle.
+        let lens_center = Vec3::new(-2.4, 1.0, 0.5);
+        let offset = Vec3::new(1.5, 0.0, 0.9);
+        world.add(Box::new(Csg::intersection(
+            Sphere::new(lens_center + offset, 2.0, Dielectric::new(1.5)),
+            Sphere::new(lens_center - offset, 2.0, Dielectric::new(1.5)),
+        )));
+
+        // A cube with its edges rounded off by a sphere, and a dimple for
+        // each pip on every face.
+        let (center, half) = (Vec3::new(0.0, 0.8, 0.0), 0.8);
+        let ivory = || Lambertian::new(Vec3::new(0.85, 0.8, 0.7));
+        let body = Csg::intersection(
+            Cuboid::new(
+                center - half * Vec3::ones(),
+                center + half * Vec3::ones(),
+                ivory(),
+            ),
+            Sphere::new(center, 1.35 * half, ivory()),
+        );
+        let mut pips = HittableList::new();
+        let faces = [
+            (1, Vec3::new(0.0, 1.0, 0.0)),
+            (6, Vec3::new(0.0, -1.0, 0.0)),
+            (2, Vec3::new(0.0, 0.0, 1.0)),
+            (5, Vec3::new(0.0, 0.0, -1.0)),
+            (3, Vec3::new(1.0, 0.0, 0.0)),
+            (4, Vec3::new(-1.0, 0.0, 0.0)),
+        ];
+        for (count, normal) in faces {
+            let a = Vec3::new(normal.y.abs(), normal.z.abs(), normal.x.abs());
+            let b = Vec3::cross(normal, a);
+            let d = 0.45 * half;
+            let spots: &[(f64, f64)] = match count {
+                1 => &[(0.0, 0.0)],
+                2 => &[(-d, -d), (d, d)],
+                3 => &[(-d, -d), (0.0, 0.0), (d, d)],
+                4 => &[(-d, -d), (-d, d), (d, -d), (d, d)],
+                5 => &[(-d, -d), (-d, d), (0.0, 0.0), (d, -d), (d, d)],
+                _ => &[(-d, -d), (-d, 0.0), (-d, d), (d, -d), (d, 0.0), (d, d)],
+            };
+            for (x, y) in spots {
+                pips.add(Box::new(Sphere::new(
+                    center + half * normal + *x * a + *y * b,
+                    0.12,
+                    Lambertian::new(Vec3::new(0.05, 0.05, 0.05)),
+                )));
+            }
+        }
+        world.add(Box::new(Csg::difference(body, pips)));
+
+        // Merged, the spheres have no wall inside where they overlap.
+        let glass = || Dielectric::tinted(1.5, Vec3::new(0.4, 0.7, 0.9), 1.0);
+        world.add(Box::new(Csg::union(
+            Sphere::new(Vec3::new(2.2, 0.8, 0.0), 0.8, glass()),
+            Sphere::new(Vec3::new(2.8, 1.3, -0.2), 0.7, glass()),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 3.0, 9.0);
+        let lookat = Vec3::new(0.0, 0.8, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(45.0, 30.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Shapes traced through distance fields: a Mandelbulb, blended blobs,
+    /// a rounded box with a scoop taken out of it and a twisted bar.
+    pub fn sdf_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        let bulb = Translate::new(
+            Arc::new(Mandelbulb::new(8.0, 10)),
+            Vec3::new(-3.3, 1.15, 0.0),
+        );
+        world.add(Box::new(SdfObject::new(
+            Arc::new(bulb),
+            Conductor::gold(0.3),
+        )));
+
+        let blob = SmoothUnion::new(
+            Arc::new(SmoothUnion::new(
+                Arc::new(SphereSdf::new(0.55)),
+                Arc::new(Capsule::new(
+                    Vec3::new(-0.3, 0.5, 0.0),
+                    Vec3::new(0.4, 0.9, 0.2),
+                    0.25,
+                )),
+                0.3,
+            )),
+            Arc::new(Translate::new(
+                Arc::new(TorusSdf::new(0.6, 0.12)),
+                Vec3::new(0.0, -0.3, 0.0),
+            )),
+            0.3,
+        );
+        world.add(Box::new(SdfObject::new(
+            Arc::new(Translate::new(Arc::new(blob), Vec3::new(-1.1, 0.85, 0.0))),
+            Principled::new(Arc::new(SolidColor::new(Vec3::new(0.8, 0.25, 0.35))))
+                .roughness(Arc::new(SolidColor::scalar(0.3))),
+        )));
+
+        let scooped = SmoothSubtraction::new(
+            Arc::new(RoundBox::new(Vec3::new(0.7, 0.7, 0.7), 0.12)),
+            Arc::new(Translate::new(
+                Arc::new(SphereSdf::new(0.7)),
+                Vec3::new(0.35, 0.6, 0.45),
+            )),
+            0.1,
+        );
+        world.add(Box::new(SdfObject::new(
+            Arc::new(Translate::new(Arc::new(scooped), Vec3::new(1.1, 0.7, 0.0))),
+            Lambertian::new(Vec3::new(0.2, 0.4, 0.7)),
+        )));
+
+        // A bar twisted a quarter turn per unit of height, with the distance
+        // scaled down since twisting stretches it.
+        let bar = RoundBox::new(Vec3::new(0.35, 0.9, 0.35), 0.05);
+        let twisted = CustomSdf::new(
+            Aabb::new(Vec3::new(-0.5, -0.9, -0.5), Vec3::new(0.5, 0.9, 0.5)),
+            move |p: Vec3| {
+                let angle = PI / 2.0 * p.y;
+                let (sin, cos) = angle.sin_cos();
+                let q = Vec3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z);
+                0.6 * bar.distance(q)
+            },
+        );
+        world.add(Box::new(SdfObject::new(
+            Arc::new(Translate::new(Arc::new(twisted), Vec3::new(3.2, 0.9, 0.0))),
+            Conductor::copper(0.2),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 2.5, 9.0);
+        let lookat = Vec3::new(0.0, 0.9, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(40.0, 60.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Mechanical props made from analytic surfaces rather than triangles:
+    /// a piston, a traffic cone, a ring, a dish on a stand, a cooling tower
+    /// and an open pipe.
+    pub fn quadrics_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        world.add(Box::new(Quadric::cylinder(
+            Vec3::new(-3.2, 0.0, -0.2),
+            up,
+            0.5,
+            1.2,
+            true,
+            Conductor::iron(0.15),
+        )));
+        world.add(Box::new(Quadric::cone(
+            Vec3::new(-1.7, 0.0, 0.6),
+            up,
+            0.45,
+            1.3,
+            true,
+            Lambertian::new(Vec3::new(0.9, 0.35, 0.05)),
+        )));
+        world.add(Box::new(Torus::new(
+            Vec3::new(-0.2, 0.82, 0.0),
+            Vec3::new(0.3, 0.2, 1.0),
+            0.6,
+            0.2,
+            Conductor::gold(0.2),
+        )));
+
+        // A dish tilted towards the camera on a short post.
+        world.add(Box::new(Quadric::cylinder(
+            Vec3::new(1.5, 0.0, 0.3),
+            up,
+            0.06,
+            0.55,
+            true,
+            Conductor::iron(0.4),
+        )));
+        world.add(Box::new(Quadric::paraboloid(
+            Vec3::new(1.5, 0.5, 0.3),
+            Vec3::new(0.0, 1.0, 1.0),
+            0.75,
+            0.35,
+            Conductor::aluminum(0.1),
+        )));
+
+        world.add(Box::new(Quadric::hyperboloid(
+            Vec3::new(3.2, 0.9, -0.6),
+            up,
+            0.35,
+            0.6,
+            1.8,
+            Lambertian::new(Vec3::new(0.75, 0.75, 0.7)),
+        )));
+        world.add(Box::new(Quadric::cylinder(
+            Vec3::new(-0.6, 0.25, 1.8),
+            Vec3::new(1.0, 0.0, 0.3),
+            0.25,
+            1.3,
+            false,
+            Conductor::copper(0.25),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 2.5, 9.0);
+        let lookat = Vec3::new(0.0, 0.7, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(35.0, 40.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// A valley with a lake, from `heightmap.pgm` in the working directory
+    /// if there is one and from fractal noise otherwise.
+    pub fn terrain_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+
+        let (min, size) = (Vec3::new(-12.0, -0.5, -20.0), Vec3::new(24.0, 4.0, 24.0));
+        let rock = || Lambertian::new(Vec3::new(0.45, 0.4, 0.3));
+        let terrain = match Heightfield::from_pgm("heightmap.pgm", min, size, rock()) {
+            Ok(terrain) => terrain,
+            Err(error) => {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("Ignoring heightmap.pgm: {}", error);
+                }
+                Heightfield::from_noise(256, 256, 3.0, 6, min, size, rock())
+            }
+        };
+        world.add(Box::new(terrain));
+
+        // The lake fills the valleys up to a fixed height.
+        world.add(Box::new(Cuboid::new(
+            Vec3::new(-12.0, -0.6, -20.0),
+            Vec3::new(12.0, 0.9, 4.0),
+            Dielectric::tinted(1.33, Vec3::new(0.3, 0.6, 0.65), 1.0),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 6.0, 10.0);
+        let lookat = Vec3::new(0.0, 1.0, -6.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                50.0,
+                aspect_ratio,
+                0.0,
+                16.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(25.0, 70.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// Blobby shapes from metaballs: water drops running together, a lava
+    /// lamp style blob pinching apart and a lump with a dent pressed in by a
+    /// ball of negative weight.
+    pub fn metaballs_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        let drops = vec![
+            Metaball::new(Vec3::new(-3.0, 0.3, 0.2), 1.0, 1.0),
+            Metaball::new(Vec3::new(-2.4, 0.25, 0.5), 0.8, 1.0),
+            Metaball::new(Vec3::new(-1.9, 0.2, 0.9), 0.6, 1.0),
+            Metaball::new(Vec3::new(-3.4, 0.2, 0.9), 0.6, 1.0),
+        ];
+        world.add(Box::new(Metaballs::new(
+            drops,
+            0.3,
+            Dielectric::tinted(1.33, Vec3::new(0.6, 0.85, 0.9), 1.0),
+        )));
+
+        let lava = vec![
+            Metaball::new(Vec3::new(0.0, 0.5, 0.0), 1.1, 1.0),
+            Metaball::new(Vec3::new(0.0, 1.3, 0.0), 0.8, 1.0),
+            Metaball::new(Vec3::new(0.1, 2.2, 0.1), 0.9, 1.0),
+        ];
+        world.add(Box::new(Metaballs::new(
+            lava,
+            0.25,
+            Principled::new(Arc::new(SolidColor::new(Vec3::new(0.9, 0.3, 0.05))))
+                .roughness(Arc::new(SolidColor::scalar(0.25))),
+        )));
+
+        let dented = vec![
+            Metaball::new(Vec3::new(2.4, 0.7, 0.0), 1.4, 1.0),
+            Metaball::new(Vec3::new(3.1, 0.8, 0.6), 1.0, 0.8),
+            Metaball::new(Vec3::new(2.3, 1.4, 0.9), 0.8, -1.0),
+        ];
+        world.add(Box::new(Metaballs::new(
+            dented,
+            0.2,
+            Conductor::copper(0.25),
+        )));
+
+        let lookfrom = Vec3::new(0.0, 2.5, 9.0);
+        let lookat = Vec3::new(0.0, 0.9, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(40.0, 60.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// `model.ply` with its vertex colors and `model.stl` in brushed
+    /// aluminum, from the working directory, each scaled to stand 2 high.
+    /// Without a PLY file a sphere colored by its vertices stands in.
+    pub fn models_scene(aspect_ratio: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        )));
+
+        let report = |name: &str, error: std::io::Error| {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("Ignoring {}: {}", name, error);
+            }
+        };
+
+        let painted = || VertexColored::new(Lambertian::new(Vec3::ones()));
+        let scan = Mesh::from_ply("model.ply", painted()).unwrap_or_else(|error| {
+            report("model.ply", error);
+            let sphere = Mesh::uv_sphere(Vec3::zeros(), 1.0, 32, 16, painted());
+            let colors = sphere
+                .positions
+                .iter()
+                .map(|p| 0.5 * (*p + Vec3::ones()))
+                .collect();
+            sphere.with_colors(colors)
+        });
+        world.add(Box::new(Self::fitted(scan, Vec3::new(-1.4, 0.0, 0.0), 2.0)));
+
+        match Mesh::from_stl("model.stl", Conductor::aluminum(0.2)) {
+            Ok(part) => world.add(Box::new(Self::fitted(part, Vec3::new(1.4, 0.0, 0.0), 2.0))),
+            Err(error) => report("model.stl", error),
+        }
+
+        let lookfrom = Vec3::new(0.0, 2.5, 9.0);
+        let lookat = Vec3::new(0.0, 0.9, 0.0);
+        Scene {
+            objects: world,
+            camera: Camera::new(
+                lookfrom,
+                lookat,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+                9.0,
+            ),
+            sky: Sky::Daylight(DaylightSky::new(40.0, 60.0, 2.5)),
+            lights: Vec::new(),
+            fog: None,
+        }
+    }
+
+    /// `mesh` scaled to be `size` across its longest side, and moved to
+    /// stand on `base_center`.
+    fn fitted<M: Material>(mesh: Mesh<M>, base_center: Vec3, size: f64) -> Mesh<M> {
+        let bounds = Aabb::from_points(&mesh.positions);
+        let extent = bounds.max - bounds.min;
+        let scale = size / extent.x.max(extent.y).max(extent.z).max(1.0e-12);
+        let bottom = Vec3::new(bounds.centroid().x, bounds.min.y, bounds.centroid().z);
+        let Mesh {
+            positions,
+            normals,
+            uvs,
+            colors,
+            triangles,
+            material,
+            ..
+        } = mesh;
+        let positions = positions
+            .iter()
+            .map(|&p| base_center + scale * (p - bottom))
+            .collect();
+        Mesh::new(positions, normals, uvs, triangles, material).with_colors(colors)
+    }
+
     fn get_camera(aspect_ratio: f64) -> Camera {
         let lookfrom = Vec3::new(13.0, 2.0, 3.0);
         let lookat = Vec3::new(0.0, 0.0, 0.0);
@@ -92,4 +1599,4 @@ impl Scene {
 
         world
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,413 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::atmosphere::HeightFog;
+use crate::bump::{BumpMapped, NormalMapped};
+use crate::camera::Camera;
+use crate::coated::Coated;
+use crate::csg::Csg;
+use crate::cuboid::Cuboid;
+use crate::curve::Curves;
+use crate::diffuse::*;
+use crate::fabric::{Fabric, Sheen};
+use crate::hair::Hair;
+use crate::heightfield::Heightfield;
+use crate::json::Json;
+use crate::light::*;
+use crate::material::*;
+use crate::mesh::Mesh;
+use crate::metaball::Metaballs;
+use crate::microfacet::TrowbridgeReitz;
+use crate::mix::*;
+use crate::principled::Principled;
+use crate::quadric::{Quadric, Torus};
+use crate::scene::Scene;
+use crate::sdf::*;
+use crate::sky::Sky;
+use crate::sphere::{Hittable, HittableList, Sphere};
+use crate::subsurface::Subsurface;
+use crate::texture::*;
+use crate::vec3::Vec3;
+
+// The scene file is JSON holding exactly what a `Scene` is made of, the
+// camera and lights as their stored vectors rather than the arguments they
+// were made from, so it reads back into the same scene bit for bit. Each
+// object, material, light and texture saves itself through its trait's
+// `to_json`, tagged with a "type", and is read back by its own `from_json`,
+// which the readers at the end of this file pick by that tag. Image files
+// are kept once in the top level "images", which image textures refer to by
+// index, however many textures share them.
+
+impl Scene {
+    /// The scene in the scene file format. Fails if it has an object,
+    /// material, light or texture that can't be saved.
+    pub fn to_json(&self) -> io::Result<Json> {
+        let unsupported = |what: String| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} can't be saved", what),
+            )
+        };
+        let objects = self
+            .objects
+            .objects()
+            .iter()
+            .enumerate()
+            .map(|(i, object)| {
+                object
+                    .to_json()
+                    .ok_or_else(|| unsupported(format!("object {}", i)))
+            })
+            .collect::<io::Result<Vec<Json>>>()?;
+        let lights = self
+            .lights
+            .iter()
+            .enumerate()
+            .map(|(i, light)| {
+                light
+                    .to_json()
+                    .ok_or_else(|| unsupported(format!("light {}", i)))
+            })
+            .collect::<io::Result<Vec<Json>>>()?;
+        let fog = self.fog.as_ref().map_or(Json::Null, |fog| {
+            Json::object([
+                ("density", fog.density.into()),
+                ("falloff", fog.falloff.into()),
+                ("albedo", fog.albedo.into()),
+            ])
+        });
+        let mut json = Json::object([
+            ("camera", self.camera.to_json()),
+            ("sky", self.sky.to_json()),
+            ("fog", fog),
+            ("lights", Json::Array(lights)),
+            ("objects", Json::Array(objects)),
+        ]);
+        let mut images = Vec::new();
+        hoist_images(&mut json, &mut images);
+        if let Json::Object(members) = &mut json {
+            members.push(("images".to_string(), Json::Array(images)));
+        }
+        Ok(json)
+    }
+
+    /// Writes the scene to `path` in the scene file format, which
+    /// `Scene::load` reads back.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json()?.pretty())
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Scene> {
+        let images = array(json, "images")?;
+        let inlined;
+        let json = if images.is_empty() {
+            json
+        } else {
+            let mut copy = json.clone();
+            inline_images(&mut copy, images)?;
+            inlined = copy;
+            &inlined
+        };
+        let fog = match field(json, "fog")? {
+            Json::Null => None,
+            fog => Some(HeightFog::new(
+                number(fog, "density")?,
+                number(fog, "falloff")?,
+                vec3(fog, "albedo")?,
+            )),
+        };
+        Ok(Scene {
+            objects: HittableList::from_json(json)?,
+            camera: Camera::from_json(field(json, "camera")?)?,
+            sky: Sky::from_json(field(json, "sky")?)?,
+            lights: array(json, "lights")?
+                .iter()
+                .map(light)
+                .collect::<io::Result<_>>()?,
+            fog,
+        })
+    }
+
+    /// Scene saved by `Scene::save`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Scene> {
+        Scene::from_json(&Json::parse(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Moves the image of each image texture into `images`, once however many
+/// textures share it, and leaves its index there in its place.
+fn hoist_images(json: &mut Json, images: &mut Vec<Json>) {
+    match json {
+        Json::Object(members) => {
+            let is_image = members
+                .iter()
+                .any(|(name, value)| name == "type" && value.as_str() == Some("image"));
+            for (name, value) in members {
+                if is_image && name == "image" {
+                    let index = images
+                        .iter()
+                        .position(|image| image == value)
+                        .unwrap_or_else(|| {
+                            images.push(value.clone());
+                            images.len() - 1
+                        });
+                    *value = (index as f64).into();
+                } else {
+                    hoist_images(value, images);
+                }
+            }
+        }
+        Json::Array(elements) => {
+            for element in elements {
+                hoist_images(element, images);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Puts back the images `hoist_images` took out.
+fn inline_images(json: &mut Json, images: &[Json]) -> io::Result<()> {
+    match json {
+        Json::Object(members) => {
+            let is_image = members
+                .iter()
+                .any(|(name, value)| name == "type" && value.as_str() == Some("image"));
+            for (name, value) in members {
+                if is_image && name == "image" {
+                    *value = value
+                        .as_usize()
+                        .and_then(|index| images.get(index))
+                        .ok_or_else(|| invalid("an image texture has a bad image index"))?
+                        .clone();
+                } else {
+                    inline_images(value, images)?;
+                }
+            }
+        }
+        Json::Array(elements) => {
+            for element in elements {
+                inline_images(element, images)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+impl From<Vec3> for Json {
+    fn from(v: Vec3) -> Json {
+        Json::Array(vec![v.x.into(), v.y.into(), v.z.into()])
+    }
+}
+
+pub fn invalid(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("bad scene file: {}", message),
+    )
+}
+
+pub fn field<'a>(json: &'a Json, key: &str) -> io::Result<&'a Json> {
+    json.get(key)
+        .ok_or_else(|| invalid(format!("missing \"{}\"", key)))
+}
+
+pub fn number(json: &Json, key: &str) -> io::Result<f64> {
+    field(json, key)?
+        .as_f64()
+        .ok_or_else(|| invalid(format!("\"{}\" isn't a number", key)))
+}
+
+pub fn vec3(json: &Json, key: &str) -> io::Result<Vec3> {
+    as_vec3(field(json, key)?).ok_or_else(|| invalid(format!("\"{}\" isn't three numbers", key)))
+}
+
+pub fn as_vec3(json: &Json) -> Option<Vec3> {
+    match json.as_f64s()?[..] {
+        [x, y, z] => Some(Vec3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+pub fn array<'a>(json: &'a Json, key: &str) -> io::Result<&'a [Json]> {
+    field(json, key)?
+        .as_array()
+        .ok_or_else(|| invalid(format!("\"{}\" isn't an array", key)))
+}
+
+pub fn numbers(json: &Json, key: &str) -> io::Result<Vec<f64>> {
+    field(json, key)?
+        .as_f64s()
+        .ok_or_else(|| invalid(format!("\"{}\" isn't an array of numbers", key)))
+}
+
+/// Every element of array `key` as a vector.
+pub fn vec3s(json: &Json, key: &str) -> io::Result<Vec<Vec3>> {
+    array(json, key)?
+        .iter()
+        .map(|v| as_vec3(v).ok_or_else(|| invalid(format!("\"{}\" has a bad vector", key))))
+        .collect()
+}
+
+pub fn index(json: &Json, key: &str) -> io::Result<usize> {
+    field(json, key)?
+        .as_usize()
+        .ok_or_else(|| invalid(format!("\"{}\" isn't an index", key)))
+}
+
+pub fn flag(json: &Json, key: &str) -> io::Result<bool> {
+    field(json, key)?
+        .as_bool()
+        .ok_or_else(|| invalid(format!("\"{}\" isn't true or false", key)))
+}
+
+pub fn string<'a>(json: &'a Json, key: &str) -> io::Result<&'a str> {
+    field(json, key)?
+        .as_str()
+        .ok_or_else(|| invalid(format!("\"{}\" isn't a string", key)))
+}
+
+/// Microfacet roughness saved as its two alphas.
+pub fn distribution(json: &Json) -> io::Result<TrowbridgeReitz> {
+    match numbers(json, "alpha")?[..] {
+        [alpha_x, alpha_y] => Ok(TrowbridgeReitz { alpha_x, alpha_y }),
+        _ => Err(invalid("\"alpha\" isn't two numbers")),
+    }
+}
+
+/// A member that holds an object, material, texture, light or distance
+/// field, read by the reader for its "type".
+pub fn hittable(json: &Json) -> io::Result<Box<dyn Hittable>> {
+    Ok(match string(json, "type")? {
+        "list" => Box::new(HittableList::from_json(json)?),
+        "sphere" => Box::new(Sphere::from_json(json)?),
+        "mesh" => Box::new(Mesh::from_json(json)?),
+        "csg" => Box::new(Csg::from_json(json)?),
+        "cuboid" => Box::new(Cuboid::from_json(json)?),
+        "sdf" => Box::new(SdfObject::from_json(json)?),
+        "quadric" => Box::new(Quadric::from_json(json)?),
+        "torus" => Box::new(Torus::from_json(json)?),
+        "heightfield" => Box::new(Heightfield::from_json(json)?),
+        "metaballs" => Box::new(Metaballs::from_json(json)?),
+        "curves" => Box::new(Curves::from_json(json)?),
+        other => return Err(invalid(format!("unknown object type \"{}\"", other))),
+    })
+}
+
+pub fn material(json: &Json) -> io::Result<Box<dyn Material>> {
+    Ok(match string(json, "type")? {
+        "lambertian" => Box::new(Lambertian::from_json(json)?),
+        "metal" => Box::new(Metal::from_json(json)?),
+        "conductor" => Box::new(Conductor::from_json(json)?),
+        "dielectric" => Box::new(Dielectric::from_json(json)?),
+        "rough_dielectric" => Box::new(RoughDielectric::from_json(json)?),
+        "principled" => Box::new(Principled::from_json(json)?),
+        "coated" => Box::new(Coated::from_json(json)?),
+        "subsurface" => Box::new(Subsurface::from_json(json)?),
+        "bump_mapped" => Box::new(BumpMapped::from_json(json)?),
+        "normal_mapped" => Box::new(NormalMapped::from_json(json)?),
+        "mix" => Box::new(Mix::from_json(json)?),
+        "alpha_cutout" => Box::new(AlphaCutout::from_json(json)?),
+        "vertex_colored" => Box::new(VertexColored::from_json(json)?),
+        "oren_nayar" => Box::new(OrenNayar::from_json(json)?),
+        "retroreflective_diffuse" => Box::new(RetroreflectiveDiffuse::from_json(json)?),
+        "diffuse_transmission" => Box::new(DiffuseTransmission::from_json(json)?),
+        "sheen" => Box::new(Sheen::from_json(json)?),
+        "fabric" => Box::new(Fabric::from_json(json)?),
+        "hair" => Box::new(Hair::from_json(json)?),
+        other => return Err(invalid(format!("unknown material type \"{}\"", other))),
+    })
+}
+
+pub fn texture(json: &Json) -> io::Result<Arc<dyn Texture>> {
+    Ok(match string(json, "type")? {
+        "solid" => Arc::new(SolidColor::from_json(json)?),
+        "checker" => Arc::new(CheckerTexture::from_json(json)?),
+        "gradient" => Arc::new(GradientTexture::from_json(json)?),
+        "noise" => Arc::new(NoiseTexture::from_json(json)?),
+        "image" => Arc::new(ImageTexture::from_json(json)?),
+        "scaled" => Arc::new(ScaledTexture::from_json(json)?),
+        "channel" => Arc::new(ChannelTexture::from_json(json)?),
+        "threshold" => Arc::new(ThresholdTexture::from_json(json)?),
+        other => return Err(invalid(format!("unknown texture type \"{}\"", other))),
+    })
+}
+
+pub fn light(json: &Json) -> io::Result<Box<dyn Light>> {
+    Ok(match string(json, "type")? {
+        "point" => Box::new(PointLight::from_json(json)?),
+        "spot" => Box::new(SpotLight::from_json(json)?),
+        "directional" => Box::new(DirectionalLight::from_json(json)?),
+        other => return Err(invalid(format!("unknown light type \"{}\"", other))),
+    })
+}
+
+pub fn sdf(json: &Json) -> io::Result<Arc<dyn Sdf>> {
+    Ok(match string(json, "type")? {
+        "sphere" => Arc::new(SphereSdf::from_json(json)?),
+        "round_box" => Arc::new(RoundBox::from_json(json)?),
+        "torus" => Arc::new(TorusSdf::from_json(json)?),
+        "capsule" => Arc::new(Capsule::from_json(json)?),
+        "mandelbulb" => Arc::new(Mandelbulb::from_json(json)?),
+        "translate" => Arc::new(Translate::from_json(json)?),
+        "smooth_union" => Arc::new(SmoothUnion::from_json(json)?),
+        "smooth_subtraction" => Arc::new(SmoothSubtraction::from_json(json)?),
+        other => {
+            return Err(invalid(format!(
+                "unknown distance field type \"{}\"",
+                other
+            )))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenes_round_trip_exactly() {
+        for (name, scene) in Scene::BUILT_IN {
+            let scene = scene(16.0 / 9.0);
+            let text = match scene.to_json() {
+                Ok(json) => json.pretty(),
+                // The twisted bar in the sdf scene is a closure, but every
+                // other shape there still saves and loads back.
+                Err(error) if name == "sdf" => {
+                    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+                    let objects = scene.objects.objects();
+                    let saved: Vec<String> = objects
+                        .iter()
+                        .filter_map(|object| Some(object.to_json()?.pretty()))
+                        .collect();
+                    assert_eq!(saved.len(), objects.len() - 1);
+                    for text in saved {
+                        let loaded = hittable(&Json::parse(&text).unwrap()).unwrap();
+                        assert_eq!(loaded.to_json().unwrap().pretty(), text);
+                    }
+                    continue;
+                }
+                Err(error) => panic!("{}: {}", name, error),
+            };
+            let loaded = Scene::from_json(&Json::parse(&text).unwrap()).unwrap();
+            assert_eq!(loaded.to_json().unwrap().pretty(), text, "{}", name);
+
+            // Rays through a grid over the image find the same surfaces.
+            for k in 0..25 {
+                let ray = scene
+                    .camera
+                    .get_ray((k % 5) as f64 / 4.0, (k / 5) as f64 / 4.0);
+                let surface = |scene: &Scene| {
+                    let hit = scene.objects.hit(ray, 0.001, f64::INFINITY)?;
+                    Some((hit.t, [hit.normal.x, hit.normal.y, hit.normal.z]))
+                };
+                assert_eq!(surface(&scene), surface(&loaded), "{}", name);
+            }
+        }
+
+        assert!(Scene::from_json(&Json::parse(r#"{"camera": {}}"#).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,566 @@
+use std::io;
+use std::sync::Arc;
+
+use crate::bvh::Aabb;
+use crate::json::Json;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::scene_file::{field, index, material, number, sdf, vec3};
+use crate::sphere::*;
+use crate::vec3::Vec3;
+
+/// Signed distance to a surface: negative inside, positive outside, and
+/// never more than the true distance so a ray can safely step that far.
+pub trait Sdf: Sync + Send {
+    fn distance(&self, p: Vec3) -> f64;
+
+    /// Box the surface lies entirely within.
+    fn bounding_box(&self) -> Aabb;
+
+    /// The shape in the scene file format, none if it can't be saved.
+    fn to_json(&self) -> Option<Json> {
+        None
+    }
+}
+
+/// Sphere around the origin.
+pub struct SphereSdf {
+    pub radius: f64,
+}
+
+impl SphereSdf {
+    pub fn new(radius: f64) -> SphereSdf {
+        SphereSdf { radius }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<SphereSdf> {
+        Ok(SphereSdf::new(number(json, "radius")?))
+    }
+}
+
+impl Sdf for SphereSdf {
+    fn distance(&self, p: Vec3) -> f64 {
+        p.length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(-self.radius * Vec3::ones(), self.radius * Vec3::ones())
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "sphere".into()),
+            ("radius", self.radius.into()),
+        ]))
+    }
+}
+
+/// Box around the origin with its edges rounded off by `radius`.
+pub struct RoundBox {
+    pub half_size: Vec3,
+    pub radius: f64,
+}
+
+impl RoundBox {
+    pub fn new(half_size: Vec3, radius: f64) -> RoundBox {
+        RoundBox { half_size, radius }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<RoundBox> {
+        Ok(RoundBox::new(
+            vec3(json, "half_size")?,
+            number(json, "radius")?,
+        ))
+    }
+}
+
+impl Sdf for RoundBox {
+    fn distance(&self, p: Vec3) -> f64 {
+        let q = Vec3::new(p.x.abs(), p.y.abs(), p.z.abs()) - self.half_size
+            + self.radius * Vec3::ones();
+        let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+        let inside = q.x.max(q.y).max(q.z).min(0.0);
+        outside + inside - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(-self.half_size, self.half_size)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "round_box".into()),
+            ("half_size", self.half_size.into()),
+            ("radius", self.radius.into()),
+        ]))
+    }
+}
+
+/// Ring around the y axis through the origin.
+pub struct TorusSdf {
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl TorusSdf {
+    pub fn new(major_radius: f64, minor_radius: f64) -> TorusSdf {
+        TorusSdf {
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<TorusSdf> {
+        Ok(TorusSdf::new(
+            number(json, "major_radius")?,
+            number(json, "minor_radius")?,
+        ))
+    }
+}
+
+impl Sdf for TorusSdf {
+    fn distance(&self, p: Vec3) -> f64 {
+        let ring = p.x.hypot(p.z) - self.major_radius;
+        ring.hypot(p.y) - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let outer = self.major_radius + self.minor_radius;
+        Aabb::new(
+            Vec3::new(-outer, -self.minor_radius, -outer),
+            Vec3::new(outer, self.minor_radius, outer),
+        )
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "torus".into()),
+            ("major_radius", self.major_radius.into()),
+            ("minor_radius", self.minor_radius.into()),
+        ]))
+    }
+}
+
+/// Segment from `a` to `b` thickened by `radius`.
+pub struct Capsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f64,
+}
+
+impl Capsule {
+    pub fn new(a: Vec3, b: Vec3, radius: f64) -> Capsule {
+        Capsule { a, b, radius }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Capsule> {
+        Ok(Capsule::new(
+            vec3(json, "a")?,
+            vec3(json, "b")?,
+            number(json, "radius")?,
+        ))
+    }
+}
+
+impl Sdf for Capsule {
+    fn distance(&self, p: Vec3) -> f64 {
+        let (pa, ba) = (p - self.a, self.b - self.a);
+        let h = (Vec3::dot(pa, ba) / ba.length_squared()).clamp(0.0, 1.0);
+        (pa - h * ba).length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(&[self.a, self.b]).padded(self.radius)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "capsule".into()),
+            ("a", self.a.into()),
+            ("b", self.b.into()),
+            ("radius", self.radius.into()),
+        ]))
+    }
+}
+
+/// The Mandelbulb fractal, a 3D take on the Mandelbrot set, within about a
+/// unit of the origin. More iterations give finer detail.
+pub struct Mandelbulb {
+    pub power: f64,
+    pub iterations: usize,
+}
+
+impl Mandelbulb {
+    pub fn new(power: f64, iterations: usize) -> Mandelbulb {
+        Mandelbulb { power, iterations }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Mandelbulb> {
+        Ok(Mandelbulb::new(
+            number(json, "power")?,
+            index(json, "iterations")?,
+        ))
+    }
+}
+
+impl Sdf for Mandelbulb {
+    /// Distance estimate from how fast the iteration escapes.
+    fn distance(&self, p: Vec3) -> f64 {
+        let mut z = p;
+        let mut dr = 1.0;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if r > 2.0 {
+                break;
+            }
+            let theta = (z.y / r).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.z.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            let zr = r.powf(self.power);
+            z =
+                zr * Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ) + p;
+            r = z.length();
+        }
+        if r == 0.0 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(-1.2 * Vec3::ones(), 1.2 * Vec3::ones())
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "mandelbulb".into()),
+            ("power", self.power.into()),
+            ("iterations", (self.iterations as f64).into()),
+        ]))
+    }
+}
+
+/// Any other distance function, within `bounds`. A closure can't be
+/// written to a scene file, so scenes holding one can't be saved.
+pub struct CustomSdf<F: Fn(Vec3) -> f64 + Sync + Send> {
+    pub bounds: Aabb,
+    pub distance: F,
+}
+
+impl<F: Fn(Vec3) -> f64 + Sync + Send> CustomSdf<F> {
+    pub fn new(bounds: Aabb, distance: F) -> CustomSdf<F> {
+        CustomSdf { bounds, distance }
+    }
+}
+
+impl<F: Fn(Vec3) -> f64 + Sync + Send> Sdf for CustomSdf<F> {
+    fn distance(&self, p: Vec3) -> f64 {
+        (self.distance)(p)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+/// Another shape moved by `offset`.
+pub struct Translate {
+    pub sdf: Arc<dyn Sdf>,
+    pub offset: Vec3,
+}
+
+impl Translate {
+    pub fn new(sdf: Arc<dyn Sdf>, offset: Vec3) -> Translate {
+        Translate { sdf, offset }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Translate> {
+        Ok(Translate::new(
+            sdf(field(json, "sdf")?)?,
+            vec3(json, "offset")?,
+        ))
+    }
+}
+
+impl Sdf for Translate {
+    fn distance(&self, p: Vec3) -> f64 {
+        self.sdf.distance(p - self.offset)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bounds = self.sdf.bounding_box();
+        Aabb::new(bounds.min + self.offset, bounds.max + self.offset)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "translate".into()),
+            ("sdf", self.sdf.to_json()?),
+            ("offset", self.offset.into()),
+        ]))
+    }
+}
+
+/// Two shapes merged with a fillet about `k` wide where they meet
+/// (the polynomial smooth minimum).
+pub struct SmoothUnion {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: f64,
+}
+
+impl SmoothUnion {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>, k: f64) -> SmoothUnion {
+        SmoothUnion { a, b, k }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<SmoothUnion> {
+        Ok(SmoothUnion::new(
+            sdf(field(json, "a")?)?,
+            sdf(field(json, "b")?)?,
+            number(json, "k")?,
+        ))
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: Vec3) -> f64 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0.0, 1.0);
+        b + h * (a - b) - self.k * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The fillet only fills in between the shapes, within `k` of both.
+        self.a
+            .bounding_box()
+            .union(&self.b.bounding_box())
+            .padded(0.25 * self.k)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "smooth_union".into()),
+            ("a", self.a.to_json()?),
+            ("b", self.b.to_json()?),
+            ("k", self.k.into()),
+        ]))
+    }
+}
+
+/// `b` carved out of `a`, with the edges of the cut rounded about `k` wide.
+pub struct SmoothSubtraction {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: f64,
+}
+
+impl SmoothSubtraction {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>, k: f64) -> SmoothSubtraction {
+        SmoothSubtraction { a, b, k }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<SmoothSubtraction> {
+        Ok(SmoothSubtraction::new(
+            sdf(field(json, "a")?)?,
+            sdf(field(json, "b")?)?,
+            number(json, "k")?,
+        ))
+    }
+}
+
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, p: Vec3) -> f64 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 - 0.5 * (a + b) / self.k).clamp(0.0, 1.0);
+        a + h * (-b - a) + self.k * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.a.bounding_box()
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "smooth_subtraction".into()),
+            ("a", self.a.to_json()?),
+            ("b", self.b.to_json()?),
+            ("k", self.k.into()),
+        ]))
+    }
+}
+
+/// Steps taken along a ray before giving up on it.
+const MAX_STEPS: usize = 512;
+
+/// How close to the surface counts as on it.
+const HIT_DISTANCE: f64 = 1.0e-4;
+
+/// Offset for the central differences that give the normal.
+const NORMAL_DELTA: f64 = 1.0e-5;
+
+/// Surface of a signed distance field, found by sphere tracing ("Sphere
+/// Tracing", Hart 1996): the ray steps forward by the distance to the
+/// nearest surface, which can't overshoot it, until it gets close enough.
+pub struct SdfObject<M: Material> {
+    pub sdf: Arc<dyn Sdf>,
+    pub material: M,
+    bounds: Aabb,
+}
+
+impl<M: Material> SdfObject<M> {
+    pub fn new(sdf: Arc<dyn Sdf>, material: M) -> SdfObject<M> {
+        // Padded so points on the surface aren't clipped away.
+        let bounds = sdf.bounding_box().padded(2.0 * HIT_DISTANCE);
+        SdfObject {
+            sdf,
+            material,
+            bounds,
+        }
+    }
+
+    fn gradient(&self, p: Vec3) -> Vec3 {
+        let axis = |d: Vec3| self.sdf.distance(p + d) - self.sdf.distance(p - d);
+        Vec3::new(
+            axis(Vec3::new(NORMAL_DELTA, 0.0, 0.0)),
+            axis(Vec3::new(0.0, NORMAL_DELTA, 0.0)),
+            axis(Vec3::new(0.0, 0.0, NORMAL_DELTA)),
+        )
+    }
+}
+
+impl SdfObject<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<SdfObject<Box<dyn Material>>> {
+        Ok(SdfObject::new(
+            sdf(field(json, "sdf")?)?,
+            material(field(json, "material")?)?,
+        ))
+    }
+}
+
+impl<M: Material> Hittable for SdfObject<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (start, end) = self.bounds.clip(&r, t_min, t_max)?;
+        let length = r.direction.length();
+
+        // A ray leaving the surface starts on it, so it first has to get
+        // clear of it before it can hit anything.
+        let mut t = start;
+        let mut leaving = self.sdf.distance(r.at(t)).abs() < HIT_DISTANCE;
+        let mut converged = false;
+        for _ in 0..MAX_STEPS {
+            if t > end {
+                return None;
+            }
+            let distance = self.sdf.distance(r.at(t)).abs();
+            if leaving {
+                leaving = distance < HIT_DISTANCE;
+            } else if distance < HIT_DISTANCE {
+                converged = true;
+                break;
+            }
+            t += distance.max(HIT_DISTANCE) / length;
+        }
+        // Running out of steps, like a ray grazing the surface without
+        // reaching it, is a miss.
+        if !converged {
+            return None;
+        }
+
+        let p = r.at(t);
+        let gradient = self.gradient(p);
+        let outward_normal = if gradient.near_zero() {
+            -Vec3::unit_vector(r.direction)
+        } else {
+            Vec3::unit_vector(gradient)
+        };
+        let tangents = Onb::build_from_w(outward_normal);
+        let mut hit_record = HitRecord {
+            p,
+            normal: outward_normal,
+            mat: &self.material,
+            t,
+            u: 0.0,
+            v: 0.0,
+            dpdu: tangents.u,
+            dpdv: tangents.v,
+            color: Vec3::ones(),
+            front_facing: false,
+        };
+        hit_record.set_face_normal(r, outward_normal);
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "sdf".into()),
+            ("sdf", self.sdf.to_json()?),
+            ("material", self.material.to_json()?),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_sphere_traced_round_box() {
+        let shape = Arc::new(RoundBox::new(Vec3::new(1.0, 1.0, 1.0), 0.25));
+        let object = SdfObject::new(shape, Lambertian::new(Vec3::ones()));
+
+        // Straight at a face, and at a rounded corner along the diagonal.
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = object.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 1.0e-3);
+        assert!(Vec3::dot(hit.normal, Vec3::new(0.0, 0.0, 1.0)) > 0.999);
+
+        let corner = Vec3::unit_vector(Vec3::ones());
+        let hit = object
+            .hit(Ray::new(5.0 * corner, -corner), 0.001, f64::INFINITY)
+            .unwrap();
+        let expected = 0.75 * 3.0f64.sqrt() + 0.25;
+        assert!((hit.t - (5.0 - expected)).abs() < 1.0e-3);
+
+        // From inside, the ray finds the far face facing back at it.
+        let hit = object
+            .hit(
+                Ray::new(Vec3::zeros(), Vec3::new(1.0, 0.0, 0.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 1.0).abs() < 1.0e-3);
+        assert!(!hit.front_facing);
+    }
+
+    /// Always just out of reach, so marching along it never ends.
+    struct Haze;
+
+    impl Sdf for Haze {
+        fn distance(&self, _p: Vec3) -> f64 {
+            2.0 * HIT_DISTANCE
+        }
+
+        fn bounding_box(&self) -> Aabb {
+            Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::ones())
+        }
+    }
+
+    #[test]
+    fn test_march_without_converging_misses() {
+        let object = SdfObject::new(Arc::new(Haze), Lambertian::new(Vec3::ones()));
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(object.hit(r, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,349 @@
+use std::f64::consts::PI;
+
+use std::io;
+
+use crate::camera::degrees_to_radians;
+use crate::json::Json;
+use crate::light::*;
+use crate::onb::Onb;
+use crate::random::*;
+use crate::scene_file::{invalid, number, vec3};
+use crate::vec3::Vec3;
+
+/// Scale from the Preetham model's kcd/m^2 to the renderer's radiance units,
+/// chosen so a white diffuse surface under a midday sun lands just below 1.
+const DAYLIGHT_EXPOSURE: f64 = 0.05;
+
+/// Luminance of the sun seen from outside the atmosphere, in kcd/m^2.
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+/// Mean angular radius of the sun disk as seen from the earth.
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+
+/// Radiance arriving from directions that don't hit any object.
+pub enum Sky {
+    /// The white to light blue vertical gradient from the book.
+    Gradient,
+    /// The same radiance from every direction.
+    Uniform(Vec3),
+    Daylight(DaylightSky),
+}
+
+impl Sky {
+    /// Radiance seen along `direction`. The sun disk is left out when
+    /// `include_sun` is false, because the path already sampled it explicitly.
+    pub fn radiance(&self, direction: Vec3, include_sun: bool) -> Vec3 {
+        match self {
+            Sky::Gradient => {
+                let unit_direction = Vec3::unit_vector(direction);
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * Vec3::ones() + t * Vec3::new(0.5, 0.7, 1.0)
+            }
+            Sky::Uniform(radiance) => *radiance,
+            Sky::Daylight(daylight) => {
+                let unit_direction = Vec3::unit_vector(direction);
+                let sky = daylight.sky_radiance(unit_direction);
+                if include_sun && daylight.sun.contains(unit_direction) {
+                    sky + daylight.sun.radiance
+                } else {
+                    sky
+                }
+            }
+        }
+    }
+
+    pub fn sun(&self) -> Option<&Sun> {
+        match self {
+            Sky::Gradient | Sky::Uniform(_) => None,
+            Sky::Daylight(daylight) => Some(&daylight.sun),
+        }
+    }
+
+    /// The sky in the scene file format. A daylight sky is saved as the
+    /// sun position and turbidity it is computed from.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Sky::Gradient => Json::object([("type", "gradient".into())]),
+            Sky::Uniform(radiance) => {
+                Json::object([("type", "uniform".into()), ("radiance", (*radiance).into())])
+            }
+            Sky::Daylight(daylight) => Json::object([
+                ("type", "daylight".into()),
+                ("elevation", daylight.elevation.into()),
+                ("azimuth", daylight.azimuth.into()),
+                ("turbidity", daylight.turbidity.into()),
+            ]),
+        }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<Sky> {
+        Ok(match json.get("type").and_then(Json::as_str) {
+            Some("gradient") => Sky::Gradient,
+            Some("uniform") => Sky::Uniform(vec3(json, "radiance")?),
+            Some("daylight") => Sky::Daylight(DaylightSky::new(
+                number(json, "elevation")?,
+                number(json, "azimuth")?,
+                number(json, "turbidity")?,
+            )),
+            _ => return Err(invalid("unknown sky")),
+        })
+    }
+}
+
+/// Perez sky luminance distribution, `F(theta, gamma)` in Preetham et al. 1999.
+#[derive(Clone, Copy, Debug)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    fn eval(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Analytic clear sky from "A Practical Analytic Model for Daylight"
+/// (Preetham, Shirley, Smits 1999) together with the sun disk it is lit by.
+pub struct DaylightSky {
+    /// The arguments of `new`, in degrees, kept for saving the sky.
+    elevation: f64,
+    azimuth: f64,
+    turbidity: f64,
+    sun: Sun,
+    perez_luminance: Perez,
+    perez_x: Perez,
+    perez_y: Perez,
+    // Zenith values divided by F(0, theta_sun), so each channel is just zenith * F(theta, gamma).
+    zenith_luminance: f64,
+    zenith_x: f64,
+    zenith_y: f64,
+}
+
+impl DaylightSky {
+    /// `elevation` and `azimuth` of the sun are in degrees. The azimuth is
+    /// measured from the +x axis towards +z. `turbidity` describes the haziness
+    /// of the atmosphere, from 2 (very clear) to around 10 (hazy).
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> DaylightSky {
+        let elevation_rad = degrees_to_radians(elevation);
+        let azimuth_rad = degrees_to_radians(azimuth);
+        let direction = Vec3::new(
+            elevation_rad.cos() * azimuth_rad.cos(),
+            elevation_rad.sin(),
+            elevation_rad.cos() * azimuth_rad.sin(),
+        );
+
+        // The sky model is only defined for the sun above the horizon.
+        let theta_sun = (PI / 2.0 - elevation_rad).clamp(0.0, PI / 2.0 - 0.001);
+        let t = turbidity;
+
+        let perez_luminance = Perez {
+            a: 0.1787 * t - 1.4630,
+            b: -0.3554 * t + 0.4275,
+            c: -0.0227 * t + 5.3251,
+            d: 0.1206 * t - 2.5771,
+            e: -0.0670 * t + 0.3703,
+        };
+        let perez_x = Perez {
+            a: -0.0193 * t - 0.2592,
+            b: -0.0665 * t + 0.0008,
+            c: -0.0004 * t + 0.2125,
+            d: -0.0641 * t - 0.8989,
+            e: -0.0033 * t + 0.0452,
+        };
+        let perez_y = Perez {
+            a: -0.0167 * t - 0.2608,
+            b: -0.0950 * t + 0.0092,
+            c: -0.0079 * t + 0.2102,
+            d: -0.0441 * t - 1.6537,
+            e: -0.0109 * t + 0.0529,
+        };
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let t2 = [t * t, t, 1.0];
+        let th = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+        let chromaticity = |m: [[f64; 4]; 3]| -> f64 {
+            (0..3)
+                .map(|i| t2[i] * (0..4).map(|j| m[i][j] * th[j]).sum::<f64>())
+                .sum()
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        DaylightSky {
+            elevation,
+            azimuth,
+            turbidity,
+            sun: Sun::new(direction, turbidity),
+            perez_luminance,
+            perez_x,
+            perez_y,
+            zenith_luminance: zenith_luminance / perez_luminance.eval(1.0, theta_sun),
+            zenith_x: zenith_x / perez_x.eval(1.0, theta_sun),
+            zenith_y: zenith_y / perez_y.eval(1.0, theta_sun),
+        }
+    }
+
+    fn sky_radiance(&self, direction: Vec3) -> Vec3 {
+        // Below the horizon, continue the horizon colour.
+        let cos_theta = direction.y.max(0.001);
+        let gamma = Vec3::dot(direction, self.sun.direction)
+            .clamp(-1.0, 1.0)
+            .acos();
+
+        let luminance = self.zenith_luminance * self.perez_luminance.eval(cos_theta, gamma);
+        let x = self.zenith_x * self.perez_x.eval(cos_theta, gamma);
+        let y = self.zenith_y * self.perez_y.eval(cos_theta, gamma);
+
+        DAYLIGHT_EXPOSURE * xyy_to_rgb(x, y, luminance)
+    }
+}
+
+/// The sun disk as a light source with a small but finite angular size.
+#[derive(Clone, Copy, Debug)]
+pub struct Sun {
+    pub direction: Vec3,
+    pub radiance: Vec3,
+    cos_angular_radius: f64,
+}
+
+impl Sun {
+    fn new(direction: Vec3, turbidity: f64) -> Sun {
+        Sun {
+            direction,
+            radiance: DAYLIGHT_EXPOSURE * SUN_LUMINANCE * sun_transmittance(direction, turbidity),
+            cos_angular_radius: SUN_ANGULAR_RADIUS.cos(),
+        }
+    }
+
+    pub fn contains(&self, unit_direction: Vec3) -> bool {
+        Vec3::dot(unit_direction, self.direction) >= self.cos_angular_radius
+    }
+}
+
+impl Light for Sun {
+    /// Picks a direction uniformly inside the sun's cone of directions.
+    fn sample(&self, _p: Vec3) -> Option<LightSample> {
+        let cos_theta = 1.0 - random_f64() * (1.0 - self.cos_angular_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * random_f64();
+        let uvw = Onb::build_from_w(self.direction);
+        let direction = uvw.local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+
+        let pdf = 1.0 / (2.0 * PI * (1.0 - self.cos_angular_radius));
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance / pdf,
+        })
+    }
+}
+
+/// Rayleigh and aerosol transmittance of sunlight through the atmosphere,
+/// evaluated at a representative wavelength for each of the red, green and
+/// blue channels (Preetham et al. 1999, appendix A.2).
+fn sun_transmittance(direction: Vec3, turbidity: f64) -> Vec3 {
+    if direction.y <= 0.0 {
+        return Vec3::zeros();
+    }
+
+    let theta = direction.y.acos();
+    let theta_degrees = theta.to_degrees();
+    let relative_optical_mass = 1.0 / (theta.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+    let beta = 0.04608365822 * turbidity - 0.04586025928;
+    let alpha = 1.3;
+
+    let transmittance = |lambda_um: f64| {
+        let rayleigh = (-0.008735 * lambda_um.powf(-4.08) * relative_optical_mass).exp();
+        let aerosol = (-beta * lambda_um.powf(-alpha) * relative_optical_mass).exp();
+        rayleigh * aerosol
+    };
+
+    Vec3::new(
+        transmittance(0.65),
+        transmittance(0.55),
+        transmittance(0.45),
+    )
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vec3 {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Vec3::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zenith_matches_preetham() {
+        // With the sun overhead, the zenith is the sun's direction and the
+        // sky there is the zenith luminance and chromaticity of the paper.
+        for (turbidity, luminance, x, y) in [
+            (2.0, 15.5007, 0.26674, 0.2772),
+            (3.0, 29.4773, 0.27068, 0.28236),
+        ] {
+            let daylight = DaylightSky::new(90.0, 0.0, turbidity);
+            let radiance = daylight.sky_radiance(Vec3::new(0.0, 1.0, 0.0));
+            let expected = DAYLIGHT_EXPOSURE * xyy_to_rgb(x, y, luminance);
+            assert!((radiance - expected).length() < 1.0e-4 * expected.length());
+        }
+
+        // The sky is brighter towards the sun than away from it.
+        let daylight = DaylightSky::new(30.0, 0.0, 3.0);
+        let towards = daylight.sky_radiance(Vec3::unit_vector(Vec3::new(1.0, 0.8, 0.0)));
+        let away = daylight.sky_radiance(Vec3::unit_vector(Vec3::new(-1.0, 0.8, 0.0)));
+        assert!(towards.y > away.y);
+    }
+
+    #[test]
+    fn test_sun_disk_solid_angle() {
+        let sun = DaylightSky::new(45.0, 30.0, 3.0).sun;
+        let solid_angle = PI * SUN_ANGULAR_RADIUS * SUN_ANGULAR_RADIUS;
+
+        // Every sample lies on the disk and carries its radiance times its
+        // solid angle.
+        for _ in 0..100 {
+            let sample = sun.sample(Vec3::zeros()).unwrap();
+            assert!(sun.contains(sample.direction));
+            assert!(
+                (sample.radiance.y / sun.radiance.y - solid_angle).abs() < 1.0e-3 * solid_angle
+            );
+        }
+
+        // And the disk covers that much of a cone twice its radius around it.
+        let cos_cone = (2.0 * SUN_ANGULAR_RADIUS).cos();
+        let uvw = Onb::build_from_w(sun.direction);
+        let n = 100_000;
+        let inside = (0..n)
+            .filter(|_| {
+                let cos_theta = 1.0 - random_f64() * (1.0 - cos_cone);
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let phi = 2.0 * PI * random_f64();
+                sun.contains(uvw.local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+            })
+            .count();
+        let estimate = inside as f64 / n as f64 * 2.0 * PI * (1.0 - cos_cone);
+        assert!((estimate - solid_angle).abs() < 0.02 * solid_angle);
+    }
+}
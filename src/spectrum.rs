@@ -0,0 +1,219 @@
+use crate::random::*;
+use crate::vec3::Vec3;
+
+// In spectral mode every path carries radiance at three wavelengths, one per
+// channel of a `Vec3`, instead of RGB. Colors in the scene are still given in
+// RGB and are upsampled to a spectrum where the path meets them.
+
+/// Visible range sampled by the spectral mode, in nanometers.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+/// Centers and half width of the smooth steps between the blue, green and red
+/// basis spectra used for upsampling.
+const BLUE_GREEN_EDGE: f64 = 490.0;
+const GREEN_RED_EDGE: f64 = 585.0;
+const EDGE_HALF_WIDTH: f64 = 15.0;
+
+/// Weights of the red, green and blue basis spectra that reproduce a linear
+/// sRGB color, the inverse of the film's response to each basis spectrum.
+const RGB_TO_BASIS: [[f64; 3]; 3] = [
+    [0.945055, 0.057947, -0.003002],
+    [-0.014769, 0.978237, 0.036532],
+    [0.026492, 0.037359, 0.936149],
+];
+
+const XYZ_TO_RGB: [[f64; 3]; 3] = [
+    [3.2406, -1.5372, -0.4986],
+    [-0.9689, 1.8758, 0.0415],
+    [0.0557, -0.2040, 1.0570],
+];
+
+/// Linear sRGB of a constant unit spectrum over the sampled range. The film
+/// divides by it so the constant spectrum comes out white rather than pinkish.
+const WHITE: [f64; 3] = [128.360743, 101.538076, 97.050920];
+
+/// The wavelengths a path is traced at. The first one is the hero: it alone
+/// decides directions at surfaces whose behavior depends on wavelength
+/// ("Hero Wavelength Spectral Sampling", Wilkie et al. 2014).
+#[derive(Clone, Copy, Debug)]
+pub struct Wavelengths {
+    /// In nanometers.
+    pub lambda: [f64; 3],
+    /// Set once a dispersive surface has dropped all but the hero wavelength.
+    pub secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    /// A uniformly random hero wavelength, with the others evenly spaced
+    /// after it and wrapped around the visible range.
+    pub fn sample() -> Wavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let offset = random_f64() * range;
+        let lambda = |i: f64| LAMBDA_MIN + (offset + i * range / 3.0) % range;
+        Wavelengths {
+            lambda: [lambda(0.0), lambda(1.0), lambda(2.0)],
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Keeps only the hero wavelength. Returns the wavelengths for the rest
+    /// of the path and the weight for the values carried so far, which moves
+    /// the dropped wavelengths' share of the estimate onto the hero.
+    pub fn terminate_secondary(self) -> (Wavelengths, Vec3) {
+        if self.secondary_terminated {
+            return (self, Vec3::new(1.0, 0.0, 0.0));
+        }
+        let wavelengths = Wavelengths {
+            secondary_terminated: true,
+            ..self
+        };
+        (wavelengths, Vec3::new(3.0, 0.0, 0.0))
+    }
+
+    /// Values at these wavelengths of a smooth spectrum that the film turns
+    /// back into the linear sRGB color `rgb`. A constant color stays constant.
+    pub fn upsample(self, rgb: Vec3) -> Vec3 {
+        let weights = mul(RGB_TO_BASIS, [rgb.x, rgb.y, rgb.z]);
+        self.map(|lambda| {
+            let basis = basis(lambda);
+            (weights[0] * basis[0] + weights[1] * basis[1] + weights[2] * basis[2]).max(0.0)
+        })
+    }
+
+    /// The spectrum `f` at these wavelengths.
+    pub fn map(self, f: impl Fn(f64) -> f64) -> Vec3 {
+        Vec3::new(f(self.lambda[0]), f(self.lambda[1]), f(self.lambda[2]))
+    }
+
+    /// Linear sRGB estimate of the spectral `radiance` traced at these wavelengths.
+    pub fn to_rgb(self, radiance: Vec3) -> Vec3 {
+        let values = [radiance.x, radiance.y, radiance.z];
+        let mut xyz = [0.0; 3];
+        for (lambda, value) in self.lambda.iter().zip(values) {
+            let matching = cie_matching(*lambda);
+            for c in 0..3 {
+                xyz[c] += matching[c] * value;
+            }
+        }
+
+        // Each wavelength has a uniform pdf over the range, and there are three of them.
+        let scale = (LAMBDA_MAX - LAMBDA_MIN) / 3.0;
+        let rgb = mul(XYZ_TO_RGB, xyz);
+        Vec3::new(
+            scale * rgb[0] / WHITE[0],
+            scale * rgb[1] / WHITE[1],
+            scale * rgb[2] / WHITE[2],
+        )
+    }
+}
+
+/// Linear sRGB color of a reflectance spectrum `f`, for surfaces whose
+/// reflectance is known per wavelength but which are rendered in RGB.
+pub fn spectrum_to_rgb(f: impl Fn(f64) -> f64) -> Vec3 {
+    const SAMPLES: usize = 32;
+    let mut xyz = [0.0; 3];
+    let mut white = [0.0; 3];
+    for i in 0..SAMPLES {
+        let lambda = LAMBDA_MIN + (i as f64 + 0.5) / SAMPLES as f64 * (LAMBDA_MAX - LAMBDA_MIN);
+        let matching = cie_matching(lambda);
+        let value = f(lambda);
+        for c in 0..3 {
+            xyz[c] += matching[c] * value;
+            white[c] += matching[c];
+        }
+    }
+
+    // Normalized by the same sum for a constant spectrum, so a constant reflectance keeps its value.
+    let rgb = mul(XYZ_TO_RGB, xyz);
+    let white = mul(XYZ_TO_RGB, white);
+    Vec3::new(rgb[0] / white[0], rgb[1] / white[1], rgb[2] / white[2])
+}
+
+/// Red, green and blue basis spectra. They sum to one at every wavelength.
+fn basis(lambda: f64) -> [f64; 3] {
+    let blue = 1.0
+        - smoothstep(
+            BLUE_GREEN_EDGE - EDGE_HALF_WIDTH,
+            BLUE_GREEN_EDGE + EDGE_HALF_WIDTH,
+            lambda,
+        );
+    let red = smoothstep(
+        GREEN_RED_EDGE - EDGE_HALF_WIDTH,
+        GREEN_RED_EDGE + EDGE_HALF_WIDTH,
+        lambda,
+    );
+    [red, 1.0 - red - blue, blue]
+}
+
+/// CIE 1931 color matching functions, from the multi-lobe fit in "Simple
+/// Analytic Approximations to the CIE XYZ Color Matching Functions" (Wyman et
+/// al. 2013).
+fn cie_matching(lambda: f64) -> [f64; 3] {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mul(m: [[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Film response to an upsampled color, averaged over a fine sweep of hero wavelengths.
+    fn round_trip(rgb: Vec3) -> Vec3 {
+        let n = 3000;
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut sum = Vec3::zeros();
+        for i in 0..n {
+            let offset = (i as f64 + 0.5) / n as f64 * range;
+            let lambda = |k: f64| LAMBDA_MIN + (offset + k * range / 3.0) % range;
+            let wavelengths = Wavelengths {
+                lambda: [lambda(0.0), lambda(1.0), lambda(2.0)],
+                secondary_terminated: false,
+            };
+            sum += wavelengths.to_rgb(wavelengths.upsample(rgb));
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn test_upsample_white_round_trip() {
+        let rgb = round_trip(Vec3::ones());
+        assert!((rgb - Vec3::ones()).length() < 1.0e-3);
+    }
+
+    #[test]
+    fn test_upsample_color_round_trip() {
+        for color in [Vec3::new(0.8, 0.3, 0.2), Vec3::new(0.1, 0.6, 0.9)] {
+            let rgb = round_trip(color);
+            assert!((rgb - color).length() < 1.0e-2);
+        }
+    }
+}
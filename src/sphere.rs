@@ -1,17 +1,43 @@
+use std::f64::consts::PI;
+use std::io;
+use std::sync::OnceLock;
+
+use crate::bvh::{Aabb, Bvh};
+use crate::json::Json;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::scene_file::{array, field, hittable, material, number, vec3};
 use crate::vec3::Vec3;
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub p: Vec3,
     pub normal: Vec3,
     pub mat: &'a dyn Material,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    /// Rates of change of `p` along the texture coordinates, which span the
+    /// surface's tangent plane.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    /// Color interpolated from the vertices of meshes that have them, white
+    /// everywhere else.
+    pub color: Vec3,
     pub front_facing: bool,
 }
 
 impl HitRecord<'_> {
-    fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
+    /// `normal` pointing out of the surface rather than towards the ray.
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_facing {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
+
+    pub fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
         self.front_facing = Vec3::dot(r.direction, outward_normal) < 0.0;
         self.normal = if self.front_facing {
             outward_normal
@@ -21,18 +47,106 @@ impl HitRecord<'_> {
     }
 }
 
+/// Stretch of a ray inside a closed object, from the hit where it enters to
+/// the one where it leaves.
+#[derive(Clone, Copy)]
+pub struct Span<'a> {
+    pub enter: HitRecord<'a>,
+    pub exit: HitRecord<'a>,
+}
+
+/// Step past a hit when following a ray from one surface to the next. Far
+/// from the origin, where it is lost to rounding, the step is to the next
+/// representable distance instead.
+const SPAN_EPSILON: f64 = 1.0e-7;
+
+/// Most hits followed along one ray when finding spans.
+const MAX_SPAN_HITS: usize = 1024;
+
 pub trait Hittable: Sync {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    /// Every stretch of the whole line through `r` that is inside the
+    /// object, in order, including any behind the ray's origin. Only makes
+    /// sense for closed objects.
+    ///
+    /// By default the ray is followed from hit to hit, pairing where it
+    /// enters with where it leaves, which needs surfaces that don't overlap.
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        let mut spans = Vec::new();
+        let mut enter: Option<HitRecord> = None;
+        let mut t_min = f64::NEG_INFINITY;
+        for _ in 0..MAX_SPAN_HITS {
+            let Some(hit) = self.hit(r, t_min, f64::INFINITY) else {
+                break;
+            };
+            t_min = (hit.t + SPAN_EPSILON).max(hit.t.next_up());
+            match enter {
+                None if hit.front_facing => enter = Some(hit),
+                Some(entered) if !hit.front_facing => {
+                    spans.push(Span {
+                        enter: entered,
+                        exit: hit,
+                    });
+                    enter = None;
+                }
+                _ => {}
+            }
+        }
+        spans
+    }
+
+    /// Box around the whole object, none if it has no bounds. Lists keep
+    /// the objects that have one in a BVH.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// The object in the scene file format, none if it can't be saved.
+    fn to_json(&self) -> Option<Json> {
+        None
+    }
 }
 
+/// An object picked at run time, like one read from a scene file.
+impl Hittable for Box<dyn Hittable> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(r, t_min, t_max)
+    }
+
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        self.as_ref().spans(r)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        self.as_ref().to_json()
+    }
+}
+
+/// Objects are only added through `add`, which keeps the index in step.
 pub struct HittableList {
-    pub objects: Vec<Box<dyn Hittable>>,
+    objects: Vec<Box<dyn Hittable>>,
+    /// Built on the first hit after objects are added.
+    index: OnceLock<ListIndex>,
+}
+
+/// BVH over the objects of a list that have bounds, and the rest, which
+/// every ray is tested against.
+struct ListIndex {
+    bvh: Bvh,
+    bounded: Vec<usize>,
+    unbounded: Vec<usize>,
 }
 
 impl HittableList {
     pub fn new() -> HittableList {
         HittableList {
             objects: Vec::new(),
+            index: OnceLock::new(),
         }
     }
 
@@ -42,26 +156,88 @@ impl HittableList {
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
         self.objects.push(object);
+        self.index = OnceLock::new();
+    }
+
+    pub fn objects(&self) -> &[Box<dyn Hittable>] {
+        &self.objects
     }
 
     // pub fn clear(&mut self) {
     //     self.objects.clear();
     // }
+
+    pub fn from_json(json: &Json) -> io::Result<HittableList> {
+        let mut list = HittableList::new();
+        for object in array(json, "objects")? {
+            list.add(hittable(object)?);
+        }
+        Ok(list)
+    }
+
+    fn index(&self) -> &ListIndex {
+        self.index.get_or_init(|| {
+            let (mut bounded, mut unbounded, mut boxes) = (Vec::new(), Vec::new(), Vec::new());
+            for (i, object) in self.objects.iter().enumerate() {
+                match object.bounding_box() {
+                    Some(bounds) => {
+                        bounded.push(i);
+                        boxes.push(bounds);
+                    }
+                    None => unbounded.push(i),
+                }
+            }
+            ListIndex {
+                bvh: Bvh::new(&boxes),
+                bounded,
+                unbounded,
+            }
+        })
+    }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let index = self.index();
         let mut hit = None;
         let mut closest_so_far = t_max;
-        for hittable in self.objects.iter() {
-            if let Some(candidate_hit) = hittable.hit(r, t_min, closest_so_far) {
+        for &i in &index.unbounded {
+            if let Some(candidate_hit) = self.objects[i].hit(r, t_min, closest_so_far) {
                 closest_so_far = candidate_hit.t;
                 hit = Some(candidate_hit);
             }
         }
+        index
+            .bvh
+            .traverse(&r, t_min, closest_so_far, |i, closest_so_far| {
+                let candidate_hit = self.objects[index.bounded[i]].hit(r, t_min, closest_so_far)?;
+                let t = candidate_hit.t;
+                hit = Some(candidate_hit);
+                Some(t)
+            });
 
         hit
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.objects
+            .iter()
+            .try_fold(Aabb::empty(), |bounds, object| {
+                Some(bounds.union(&object.bounding_box()?))
+            })
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        let objects = self
+            .objects
+            .iter()
+            .map(|object| object.to_json())
+            .collect::<Option<_>>()?;
+        Some(Json::object([
+            ("type", "list".into()),
+            ("objects", Json::Array(objects)),
+        ]))
+    }
 }
 
 pub struct Sphere<M: Material> {
@@ -78,10 +254,31 @@ impl<M: Material> Sphere<M> {
             material,
         }
     }
+
+    /// Maps a point on the unit sphere to texture coordinates: `u` goes
+    /// around the y axis starting from -x, `v` goes from the bottom (-y) to
+    /// the top (+y).
+    fn get_sphere_uv(p: Vec3) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
 }
 
-impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl Sphere<Box<dyn Material>> {
+    pub fn from_json(json: &Json) -> io::Result<Sphere<Box<dyn Material>>> {
+        Ok(Sphere::new(
+            vec3(json, "center")?,
+            number(json, "radius")?,
+            material(field(json, "material")?)?,
+        ))
+    }
+}
+
+impl<M: Material> Sphere<M> {
+    /// Distances along `r` where the line through it crosses the sphere, nearest first.
+    fn roots(&self, r: Ray) -> Option<(f64, f64)> {
         let oc = r.origin - self.center;
         let a = r.direction.length_squared();
         let half_b = Vec3::dot(oc, r.direction);
@@ -93,25 +290,81 @@ impl<M: Material> Hittable for Sphere<M> {
         }
 
         let sqrt_discriminant = discriminant.sqrt();
-        let mut root = (-half_b - sqrt_discriminant) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrt_discriminant) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
-        }
+        Some((
+            (-half_b - sqrt_discriminant) / a,
+            (-half_b + sqrt_discriminant) / a,
+        ))
+    }
 
+    fn record(&self, r: Ray, root: f64) -> HitRecord<'_> {
         let p = r.at(root);
         let outward_normal = (p - self.center) / self.radius;
+        let (u, v) = Self::get_sphere_uv(outward_normal);
+
+        // Around the y axis for u and along a meridian for v; at the poles any
+        // direction in the tangent plane will do.
+        let around = Vec3::new(outward_normal.z, 0.0, -outward_normal.x);
+        let around = if around.length_squared() > 1.0e-12 {
+            Vec3::unit_vector(around)
+        } else {
+            Vec3::new(0.0, 0.0, -1.0)
+        };
+        let dpdu = 2.0 * PI * self.radius * (outward_normal.x.hypot(outward_normal.z)) * around;
+        let dpdv = PI * self.radius * Vec3::cross(outward_normal, around);
+
         let mut hit_record = HitRecord {
             p,
             t: root,
+            u,
+            v,
+            dpdu,
+            dpdv,
             normal: outward_normal,
             mat: &self.material,
+            color: Vec3::ones(),
             front_facing: false,
         };
         hit_record.set_face_normal(r, outward_normal);
 
-        Some(hit_record)
+        hit_record
+    }
+}
+
+impl<M: Material> Hittable for Sphere<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (near, far) = self.roots(r)?;
+        let mut root = near;
+        if root < t_min || t_max < root {
+            root = far;
+            if root < t_min || t_max < root {
+                return None;
+            }
+        }
+
+        Some(self.record(r, root))
+    }
+
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        match self.roots(r) {
+            Some((near, far)) if near < far => vec![Span {
+                enter: self.record(r, near),
+                exit: self.record(r, far),
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = self.radius.abs() * Vec3::ones();
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "sphere".into()),
+            ("center", self.center.into()),
+            ("radius", self.radius.into()),
+            ("material", self.material.to_json()?),
+        ]))
     }
 }
@@ -0,0 +1,273 @@
+use std::io;
+
+use crate::json::Json;
+use crate::material::*;
+use crate::random::*;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::scene_file::{field, vec3};
+use crate::sphere::{HitRecord, Hittable};
+use crate::vec3::Vec3;
+
+/// Scattering events a path may have inside one object before it is given up.
+const MAX_WALK_STEPS: usize = 256;
+
+/// Light scattering deep inside a translucent object, like skin, wax, marble
+/// or milk.
+///
+/// The surface is a dielectric boundary and the inside is filled with a
+/// homogeneous medium. Paths that get inside are followed on a random walk
+/// through the medium by the integrator until they reach the surface again,
+/// where the boundary either lets them out, refracted, or reflects them
+/// back into the walk.
+pub struct Subsurface {
+    pub boundary: RoughDielectric,
+    pub medium: HomogeneousMedium,
+}
+
+impl Subsurface {
+    /// `albedo` is the color the material ends up with once light has
+    /// scattered many times inside it, and `mean_free_path` is how far light
+    /// of each channel travels between scattering events, which sets how
+    /// translucent the object looks at its size.
+    pub fn new(
+        albedo: Vec3,
+        mean_free_path: Vec3,
+        index_of_refraction: f64,
+        roughness: f64,
+    ) -> Subsurface {
+        Subsurface {
+            boundary: RoughDielectric::new(index_of_refraction, roughness),
+            medium: HomogeneousMedium::from_albedo(albedo, mean_free_path),
+        }
+    }
+
+    /// Subsurface material saved by `to_json`, with the medium's
+    /// coefficients as they were rather than inverted again from the albedo.
+    pub fn from_json(json: &Json) -> io::Result<Subsurface> {
+        Ok(Subsurface {
+            boundary: RoughDielectric::from_json(field(json, "boundary")?)?,
+            medium: HomogeneousMedium {
+                sigma_t: vec3(json, "sigma_t")?,
+                single_scattering_albedo: vec3(json, "single_scattering_albedo")?,
+            },
+        })
+    }
+}
+
+impl Material for Subsurface {
+    /// The boundary is crossed the same way from either side. From inside,
+    /// the Fresnel reflection sends the path back into the medium, where the
+    /// integrator carries on with the walk.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.boundary.scatter(r_in, rec)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.boundary.eval(r_in, rec, direction)
+    }
+
+    fn medium(&self) -> Option<&HomogeneousMedium> {
+        Some(&self.medium)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "subsurface".into()),
+            ("boundary", self.boundary.to_json()?),
+            ("sigma_t", self.medium.sigma_t.into()),
+            (
+                "single_scattering_albedo",
+                self.medium.single_scattering_albedo.into(),
+            ),
+        ]))
+    }
+}
+
+/// Medium with isotropic scattering and the same density everywhere.
+#[derive(Clone, Copy, Debug)]
+pub struct HomogeneousMedium {
+    /// Extinction coefficient per unit of distance.
+    pub sigma_t: Vec3,
+    /// Fraction of extinguished light that is scattered in a single event.
+    pub single_scattering_albedo: Vec3,
+}
+
+impl HomogeneousMedium {
+    /// Medium that reflects `albedo` overall from a thick slab, using the
+    /// inversion in "Practical and Controllable Subsurface Scattering for
+    /// Production Path Tracing" (Chiang et al. 2016).
+    pub fn from_albedo(albedo: Vec3, mean_free_path: Vec3) -> HomogeneousMedium {
+        let invert = |a: f64| {
+            let a = a.clamp(0.0, 0.999);
+            1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+        };
+        let sigma_t = |mfp: f64| 1.0 / mfp.max(1.0e-6);
+        HomogeneousMedium {
+            sigma_t: Vec3::new(
+                sigma_t(mean_free_path.x),
+                sigma_t(mean_free_path.y),
+                sigma_t(mean_free_path.z),
+            ),
+            single_scattering_albedo: Vec3::new(
+                invert(albedo.x),
+                invert(albedo.y),
+                invert(albedo.z),
+            ),
+        }
+    }
+
+    /// Samples how far a path travels through the medium before it scatters,
+    /// if that is less than `distance`. The channel the distance is sampled
+    /// for is picked at random and the weight returned for the segment
+    /// accounts for all three, so chromatic media don't get noisy colors.
+    /// Coefficients are taken through `spectrum` to match the path.
+    pub fn sample(&self, distance: f64, spectrum: impl Fn(Vec3) -> Vec3) -> (Option<f64>, Vec3) {
+        let sigma_t = spectrum(self.sigma_t);
+        let albedo = spectrum(self.single_scattering_albedo);
+        let channel = match (3.0 * random_f64()) as usize {
+            0 => sigma_t.x,
+            1 => sigma_t.y,
+            _ => sigma_t.z,
+        };
+        let t = -(1.0 - random_f64()).ln() / channel;
+        let mean = |v: Vec3| (v.x + v.y + v.z) / 3.0;
+
+        if t < distance {
+            let transmittance = exp(-t * sigma_t);
+            let pdf = mean(sigma_t * transmittance);
+            (Some(t), sigma_t * albedo * transmittance / pdf)
+        } else {
+            let transmittance = exp(-distance * sigma_t);
+            (None, transmittance / mean(transmittance))
+        }
+    }
+}
+
+fn exp(v: Vec3) -> Vec3 {
+    Vec3::new(v.x.exp(), v.y.exp(), v.z.exp())
+}
+
+/// Follows `r`, which ends at `hit`, through the medium inside the object it
+/// is in, if any. Returns the ray and hit at which the path gets back to the
+/// surface and the throughput of the walk, or `None` if the path got lost.
+pub fn subsurface_walk<'a>(
+    mut r: Ray,
+    mut hit: Option<HitRecord<'a>>,
+    scene: &'a Scene,
+    spectrum: impl Fn(Vec3) -> Vec3,
+) -> Option<(Ray, Option<HitRecord<'a>>, Vec3)> {
+    let mut throughput = Vec3::ones();
+    for _ in 0..MAX_WALK_STEPS {
+        let medium = match &hit {
+            Some(h) if !h.front_facing => match h.mat.medium() {
+                Some(medium) => medium,
+                None => return Some((r, hit, throughput)),
+            },
+            _ => return Some((r, hit, throughput)),
+        };
+
+        let length = r.direction.length();
+        let t = hit.as_ref().map_or(f64::INFINITY, |h| h.t);
+        let (distance, weight) = medium.sample(t * length, &spectrum);
+        throughput = throughput * weight;
+        match distance {
+            Some(distance) => {
+                let mut scattered = Ray::new(r.at(distance / length), Vec3::random_unit_vector());
+                scattered.wavelengths = r.wavelengths;
+                r = scattered;
+                // The path scattered inside the medium rather than off a
+                // surface, so nothing close by is skipped, or paths that
+                // scatter right under the surface would leak out through it.
+                hit = scene.objects.hit(r, 0.0, f64::INFINITY);
+            }
+            None => return Some((r, hit, throughput)),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::sky::Sky;
+    use crate::sphere::{HittableList, Sphere};
+
+    #[test]
+    fn test_walk_inside_slab_exits_through_boundary() {
+        // A sphere big enough to be a slab with its top at y = 0.
+        let center = Vec3::new(0.0, -1000.0, 0.0);
+        let mut objects = HittableList::new();
+        let skin = Subsurface::new(
+            Vec3::new(0.9, 0.8, 0.7),
+            Vec3::new(0.1, 0.05, 0.02),
+            1.4,
+            0.3,
+        );
+        objects.add(Box::new(Sphere::new(center, 1000.0, skin)));
+        let scene = Scene {
+            objects,
+            camera: Camera::new(
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::zeros(),
+                Vec3::new(0.0, 0.0, -1.0),
+                40.0,
+                1.0,
+                0.0,
+                1.0,
+            ),
+            sky: Sky::Gradient,
+            lights: Vec::new(),
+            fog: None,
+        };
+
+        let mut exits = 0;
+        for _ in 0..1000 {
+            // Just refracted in through the top, heading down.
+            let r = Ray::new(Vec3::new(0.0, -1.0e-3, 0.0), Vec3::new(0.3, -1.0, 0.0));
+            let hit = scene.objects.hit(r, 0.001, f64::INFINITY);
+            assert!(hit.as_ref().is_some_and(|hit| !hit.front_facing));
+            let Some((r, hit, throughput)) = subsurface_walk(r, hit, &scene, |v| v) else {
+                continue;
+            };
+
+            // The walk ends on the boundary, from inside, where the material
+            // decides whether the path leaves.
+            let hit = hit.unwrap();
+            assert!(!hit.front_facing);
+            assert!(((hit.p - center).length() - 1000.0).abs() < 1.0e-6);
+            assert!((r.at(hit.t) - hit.p).length() < 1.0e-6);
+            assert!(
+                throughput.x.is_finite() && throughput.y.is_finite() && throughput.z.is_finite()
+            );
+            if hit.p.y > -1.0 {
+                exits += 1;
+            }
+        }
+        // Most paths come back out of the top, near where they went in, before
+        // the walk gives up on them.
+        assert!(exits > 800);
+    }
+
+    #[test]
+    fn test_albedo_inversion() {
+        let medium =
+            HomogeneousMedium::from_albedo(Vec3::new(0.0, 0.5, 1.0), Vec3::new(2.0, 0.5, 0.0));
+        let single = medium.single_scattering_albedo;
+        assert!(single.x.abs() < 1.0e-4);
+        assert!((single.y - 0.911_709).abs() < 1.0e-6);
+        assert!(single.z > 0.999 && single.z < 1.0);
+        assert!((medium.sigma_t - Vec3::new(0.5, 2.0, 1.0e6)).length() < 1.0e-6);
+
+        // A brighter overall albedo always takes a higher single-scattering one.
+        let mut previous = -1.0;
+        for k in 0..=100 {
+            let albedo = k as f64 / 100.0;
+            let single = HomogeneousMedium::from_albedo(albedo * Vec3::ones(), Vec3::ones())
+                .single_scattering_albedo;
+            assert!(single.x > previous && single.x < 1.0);
+            previous = single.x;
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use std::io;
+use std::sync::Arc;
+
+use crate::image::Image;
+use crate::json::Json;
+use crate::perlin::Perlin;
+use crate::scene_file::{field, index, invalid, number, string, texture, vec3};
+use crate::vec3::Vec3;
+
+/// A value that varies over a surface. Scalar parameters read the first channel.
+pub trait Texture: Sync + Send {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3;
+
+    /// The texture in the scene file format, none if it can't be saved.
+    fn to_json(&self) -> Option<Json> {
+        None
+    }
+}
+
+pub struct SolidColor {
+    pub color_value: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(color_value: Vec3) -> SolidColor {
+        SolidColor { color_value }
+    }
+
+    pub fn scalar(value: f64) -> SolidColor {
+        SolidColor::new(Vec3::new(value, value, value))
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<SolidColor> {
+        Ok(SolidColor::new(vec3(json, "color")?))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
+        self.color_value
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "solid".into()),
+            ("color", self.color_value.into()),
+        ]))
+    }
+}
+
+/// Alternates between two textures in a 3D checkerboard of cubes `scale` wide.
+pub struct CheckerTexture {
+    pub inv_scale: f64,
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> CheckerTexture {
+        CheckerTexture {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    /// Checker saved by `to_json`, with the inverse of its scale as it was.
+    pub fn from_json(json: &Json) -> io::Result<CheckerTexture> {
+        Ok(CheckerTexture {
+            inv_scale: number(json, "inv_scale")?,
+            even: texture(field(json, "even")?)?,
+            odd: texture(field(json, "odd")?)?,
+        })
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        let x = (self.inv_scale * p.x).floor() as i64;
+        let y = (self.inv_scale * p.y).floor() as i64;
+        let z = (self.inv_scale * p.z).floor() as i64;
+
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "checker".into()),
+            ("inv_scale", self.inv_scale.into()),
+            ("even", self.even.to_json()?),
+            ("odd", self.odd.to_json()?),
+        ]))
+    }
+}
+
+/// Blends from `bottom` to `top` along the v texture coordinate, which runs
+/// from the bottom to the top of a sphere.
+pub struct GradientTexture {
+    pub bottom: Arc<dyn Texture>,
+    pub top: Arc<dyn Texture>,
+}
+
+impl GradientTexture {
+    pub fn new(bottom: Arc<dyn Texture>, top: Arc<dyn Texture>) -> GradientTexture {
+        GradientTexture { bottom, top }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<GradientTexture> {
+        Ok(GradientTexture::new(
+            texture(field(json, "bottom")?)?,
+            texture(field(json, "top")?)?,
+        ))
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        (1.0 - v) * self.bottom.value(u, v, p) + v * self.top.value(u, v, p)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "gradient".into()),
+            ("bottom", self.bottom.to_json()?),
+            ("top", self.top.to_json()?),
+        ]))
+    }
+}
+
+/// Perlin noise remapped to [0, 1] in every channel, with features about
+/// `1 / scale` apart.
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+
+    /// Noise saved by `to_json`, with the same random tables.
+    pub fn from_json(json: &Json) -> io::Result<NoiseTexture> {
+        Ok(NoiseTexture {
+            noise: Perlin::from_json(field(json, "noise")?)?,
+            scale: number(json, "scale")?,
+        })
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let value = 0.5 * (1.0 + self.noise.noise(self.scale * p));
+        Vec3::new(value, value, value)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "noise".into()),
+            ("noise", self.noise.to_json()),
+            ("scale", self.scale.into()),
+        ]))
+    }
+}
+
+/// Which channels of an image an `ImageTexture` reads, and how.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageChannels {
+    /// Colors gamma encoded like the rendered image, squared back to linear.
+    Color,
+    /// Values used as they are stored, like roughness or normal maps.
+    Data,
+    /// The alpha channel, in every channel.
+    Alpha,
+}
+
+/// An image stretched over [0, 1] in both texture coordinates and repeated
+/// beyond, bilinearly filtered. `v` runs from the bottom of the image to
+/// the top.
+pub struct ImageTexture {
+    pub image: Arc<Image>,
+    pub channels: ImageChannels,
+}
+
+impl ImageTexture {
+    pub fn color(image: Arc<Image>) -> ImageTexture {
+        ImageTexture {
+            image,
+            channels: ImageChannels::Color,
+        }
+    }
+
+    pub fn data(image: Arc<Image>) -> ImageTexture {
+        ImageTexture {
+            image,
+            channels: ImageChannels::Data,
+        }
+    }
+
+    pub fn alpha(image: Arc<Image>) -> ImageTexture {
+        ImageTexture {
+            image,
+            channels: ImageChannels::Alpha,
+        }
+    }
+
+    /// Image texture saved by `to_json`, with the image's file in it as a
+    /// data URI.
+    pub fn from_json(json: &Json) -> io::Result<ImageTexture> {
+        let channels = match string(json, "channels")? {
+            "color" => ImageChannels::Color,
+            "data" => ImageChannels::Data,
+            "alpha" => ImageChannels::Alpha,
+            other => return Err(invalid(format!("unknown image channels \"{}\"", other))),
+        };
+        Ok(ImageTexture {
+            image: Arc::new(Image::from_data_uri(string(json, "image")?)?),
+            channels,
+        })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let (width, height) = (self.image.width as i64, self.image.height as i64);
+        let [r, g, b, a] = self
+            .image
+            .pixel(x.rem_euclid(width) as usize, y.rem_euclid(height) as usize);
+        match self.channels {
+            ImageChannels::Color => Vec3::new(r * r, g * g, b * b),
+            ImageChannels::Data => Vec3::new(r, g, b),
+            ImageChannels::Alpha => Vec3::new(a, a, a),
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Vec3) -> Vec3 {
+        // Texel centers sit at half integers.
+        let x = u * self.image.width as f64 - 0.5;
+        let y = (1.0 - v) * self.image.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        (1.0 - fy) * ((1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0))
+            + fy * ((1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1))
+    }
+
+    /// Saves the image's file itself, so the scene file doesn't depend on
+    /// where the image came from. `Scene::to_json` keeps a single copy of
+    /// images that textures share.
+    fn to_json(&self) -> Option<Json> {
+        let channels = match self.channels {
+            ImageChannels::Color => "color",
+            ImageChannels::Data => "data",
+            ImageChannels::Alpha => "alpha",
+        };
+        Some(Json::object([
+            ("type", "image".into()),
+            ("channels", channels.into()),
+            ("image", self.image.to_data_uri().as_str().into()),
+        ]))
+    }
+}
+
+/// Another texture multiplied by a constant color.
+pub struct ScaledTexture {
+    pub texture: Arc<dyn Texture>,
+    pub scale: Vec3,
+}
+
+impl ScaledTexture {
+    pub fn new(texture: Arc<dyn Texture>, scale: Vec3) -> ScaledTexture {
+        ScaledTexture { texture, scale }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<ScaledTexture> {
+        Ok(ScaledTexture::new(
+            texture(field(json, "texture")?)?,
+            vec3(json, "scale")?,
+        ))
+    }
+}
+
+impl Texture for ScaledTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.scale * self.texture.value(u, v, p)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "scaled".into()),
+            ("texture", self.texture.to_json()?),
+            ("scale", self.scale.into()),
+        ]))
+    }
+}
+
+/// One channel of another texture (0 to 2 for red to blue) in every
+/// channel, for parameters packed together into one image.
+pub struct ChannelTexture {
+    pub texture: Arc<dyn Texture>,
+    pub channel: usize,
+}
+
+impl ChannelTexture {
+    pub fn new(texture: Arc<dyn Texture>, channel: usize) -> ChannelTexture {
+        ChannelTexture { texture, channel }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<ChannelTexture> {
+        Ok(ChannelTexture::new(
+            texture(field(json, "texture")?)?,
+            index(json, "channel")?,
+        ))
+    }
+}
+
+impl Texture for ChannelTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        let color = self.texture.value(u, v, p);
+        let value = match self.channel {
+            0 => color.x,
+            1 => color.y,
+            _ => color.z,
+        };
+        Vec3::new(value, value, value)
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "channel".into()),
+            ("texture", self.texture.to_json()?),
+            ("channel", (self.channel as f64).into()),
+        ]))
+    }
+}
+
+/// One where the first channel of another texture reaches `cutoff` and zero
+/// elsewhere, for hard edged alpha masks.
+pub struct ThresholdTexture {
+    pub texture: Arc<dyn Texture>,
+    pub cutoff: f64,
+}
+
+impl ThresholdTexture {
+    pub fn new(texture: Arc<dyn Texture>, cutoff: f64) -> ThresholdTexture {
+        ThresholdTexture { texture, cutoff }
+    }
+
+    pub fn from_json(json: &Json) -> io::Result<ThresholdTexture> {
+        Ok(ThresholdTexture::new(
+            texture(field(json, "texture")?)?,
+            number(json, "cutoff")?,
+        ))
+    }
+}
+
+impl Texture for ThresholdTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        if self.texture.value(u, v, p).x >= self.cutoff {
+            Vec3::ones()
+        } else {
+            Vec3::zeros()
+        }
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::object([
+            ("type", "threshold".into()),
+            ("texture", self.texture.to_json()?),
+            ("cutoff", self.cutoff.into()),
+        ]))
+    }
+}
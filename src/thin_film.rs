@@ -0,0 +1,125 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::spectrum::*;
+use crate::sphere::HitRecord;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+/// A transparent film a few hundred nanometers thick on top of a surface,
+/// like soap or an anti-reflection coating. Light reflected off the top and
+/// the bottom of the film interferes, which makes the reflectance depend on
+/// wavelength and gives iridescent colors.
+pub struct ThinFilm {
+    /// In nanometers, read from the first channel.
+    pub thickness: Arc<dyn Texture>,
+    pub index_of_refraction: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: Arc<dyn Texture>, index_of_refraction: f64) -> ThinFilm {
+        ThinFilm {
+            thickness,
+            index_of_refraction,
+        }
+    }
+
+    /// Reflectance of the film at `rec` between a medium of index `eta_outside`,
+    /// on the side light arrives from at `cos_theta`, and one of index
+    /// `eta_substrate`. Taken at the ray's wavelengths in spectral mode, RGB otherwise.
+    pub fn reflectance(
+        &self,
+        rec: &HitRecord,
+        cos_theta: f64,
+        eta_outside: f64,
+        eta_substrate: f64,
+        wavelengths: Option<Wavelengths>,
+    ) -> Vec3 {
+        let thickness = self.thickness.value(rec.u, rec.v, rec.p).x.max(0.0);
+        let reflectance = |lambda| {
+            film_reflectance(
+                cos_theta,
+                eta_outside,
+                self.index_of_refraction,
+                eta_substrate,
+                thickness,
+                lambda,
+            )
+        };
+        match wavelengths {
+            Some(wavelengths) => wavelengths.map(reflectance),
+            None => spectrum_to_rgb(reflectance),
+        }
+    }
+}
+
+/// Reflectance of a film of index `eta_film` and `thickness` nanometers between
+/// media of indices `eta_1` (where light arrives from, at `cos_theta_1`) and
+/// `eta_3`, at wavelength `lambda`. Sums the reflections off both sides of the
+/// film and all the ones bouncing inside it (Airy's formula), averaged over
+/// both polarizations.
+pub fn film_reflectance(
+    cos_theta_1: f64,
+    eta_1: f64,
+    eta_film: f64,
+    eta_3: f64,
+    thickness: f64,
+    lambda: f64,
+) -> f64 {
+    let cos_theta_1 = cos_theta_1.clamp(0.0, 1.0);
+    let sin2_theta_1 = 1.0 - cos_theta_1 * cos_theta_1;
+    let sin2_theta_2 = sin2_theta_1 * (eta_1 / eta_film).powi(2);
+    let sin2_theta_3 = sin2_theta_1 * (eta_1 / eta_3).powi(2);
+    if sin2_theta_2 >= 1.0 || sin2_theta_3 >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_2 = (1.0 - sin2_theta_2).sqrt();
+    let cos_theta_3 = (1.0 - sin2_theta_3).sqrt();
+
+    // Amplitude reflection coefficients at the top and bottom of the film.
+    let perpendicular = |eta_i: f64, cos_i: f64, eta_t: f64, cos_t: f64| {
+        (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t)
+    };
+    let parallel = |eta_i: f64, cos_i: f64, eta_t: f64, cos_t: f64| {
+        (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t)
+    };
+
+    // Phase difference picked up by one round trip through the film.
+    let cos_delta = (4.0 * PI * eta_film * thickness * cos_theta_2 / lambda).cos();
+    let airy = |r12: f64, r23: f64| {
+        let cross = 2.0 * r12 * r23 * cos_delta;
+        (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+    };
+
+    let r_perpendicular = airy(
+        perpendicular(eta_1, cos_theta_1, eta_film, cos_theta_2),
+        perpendicular(eta_film, cos_theta_2, eta_3, cos_theta_3),
+    );
+    let r_parallel = airy(
+        parallel(eta_1, cos_theta_1, eta_film, cos_theta_2),
+        parallel(eta_film, cos_theta_2, eta_3, cos_theta_3),
+    );
+    (r_perpendicular + r_parallel) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet::fresnel_dielectric;
+
+    #[test]
+    fn test_film_vanishes_at_zero_thickness() {
+        for cos_theta in [0.2, 0.6, 1.0] {
+            let film = film_reflectance(cos_theta, 1.0, 1.38, 1.5, 0.0, 550.0);
+            assert!((film - fresnel_dielectric(cos_theta, 1.5)).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn test_quarter_wave_coating() {
+        // A quarter wave film of index sqrt(1.5) cancels the reflection off glass.
+        let eta_film = 1.5_f64.sqrt();
+        let thickness = 550.0 / (4.0 * eta_film);
+        assert!(film_reflectance(1.0, 1.0, eta_film, 1.5, thickness, 550.0) < 1.0e-12);
+    }
+}
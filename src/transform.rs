@@ -0,0 +1,135 @@
+use crate::vec3::Vec3;
+
+/// Affine transform as a row-major 4 by 4 matrix.
+#[derive(Clone, Copy, Debug)]
+pub struct Transform(pub [[f64; 4]; 4]);
+
+impl Transform {
+    pub fn identity() -> Transform {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Transform(m)
+    }
+
+    /// Transform from 16 values listing the matrix column by column, the
+    /// order glTF and pbrt files use.
+    pub fn from_columns(values: &[f64]) -> Transform {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, entry) in row.iter_mut().enumerate() {
+                *entry = values[4 * j + i];
+            }
+        }
+        Transform(m)
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let mut m = Transform::identity().0;
+        m[0][3] = offset.x;
+        m[1][3] = offset.y;
+        m[2][3] = offset.z;
+        Transform(m)
+    }
+
+    pub fn scale(factors: Vec3) -> Transform {
+        let mut m = Transform::identity().0;
+        m[0][0] = factors.x;
+        m[1][1] = factors.y;
+        m[2][2] = factors.z;
+        Transform(m)
+    }
+
+    /// Rotation by `degrees` counterclockwise around `axis`, looking down it.
+    pub fn rotate(degrees: f64, axis: Vec3) -> Transform {
+        let a = Vec3::unit_vector(axis);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut m = Transform::identity().0;
+        m[0][0] = a.x * a.x + (1.0 - a.x * a.x) * cos;
+        m[0][1] = a.x * a.y * (1.0 - cos) - a.z * sin;
+        m[0][2] = a.x * a.z * (1.0 - cos) + a.y * sin;
+        m[1][0] = a.x * a.y * (1.0 - cos) + a.z * sin;
+        m[1][1] = a.y * a.y + (1.0 - a.y * a.y) * cos;
+        m[1][2] = a.y * a.z * (1.0 - cos) - a.x * sin;
+        m[2][0] = a.x * a.z * (1.0 - cos) - a.y * sin;
+        m[2][1] = a.y * a.z * (1.0 - cos) + a.x * sin;
+        m[2][2] = a.z * a.z + (1.0 - a.z * a.z) * cos;
+        Transform(m)
+    }
+
+    /// `self` applied after `local`.
+    pub fn then(self, local: Transform) -> Transform {
+        let (a, b) = (self.0, local.0);
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, entry) in row.iter_mut().enumerate() {
+                *entry = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        Transform(m)
+    }
+
+    /// Inverse by Gauss-Jordan elimination, none if the matrix is singular.
+    pub fn inverse(self) -> Option<Transform> {
+        let mut m = self.0;
+        let mut inverse = Transform::identity().0;
+        for column in 0..4 {
+            let pivot =
+                (column..4).max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))?;
+            if m[pivot][column].abs() < 1.0e-12 {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+            let scale = 1.0 / m[column][column];
+            for j in 0..4 {
+                m[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in (0..4).filter(|&row| row != column) {
+                let factor = m[row][column];
+                for j in 0..4 {
+                    m[row][j] -= factor * m[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Transform(inverse))
+    }
+
+    pub fn point(self, p: Vec3) -> Vec3 {
+        let m = self.0;
+        self.vector(p) + Vec3::new(m[0][3], m[1][3], m[2][3])
+    }
+
+    pub fn vector(self, v: Vec3) -> Vec3 {
+        let m = self.0;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /// Normal transformed by the inverse transpose of the linear part, which
+    /// keeps it perpendicular to the surface. That is the matrix of cofactors
+    /// over the determinant, and only the determinant's sign matters once the
+    /// normal is normalized.
+    pub fn normal(self, n: Vec3) -> Vec3 {
+        let m = self.0;
+        let column = |j: usize| Vec3::new(m[0][j], m[1][j], m[2][j]);
+        let (c0, c1, c2) = (column(0), column(1), column(2));
+        let cofactors =
+            n.x * Vec3::cross(c1, c2) + n.y * Vec3::cross(c2, c0) + n.z * Vec3::cross(c0, c1);
+        Vec3::unit_vector(self.determinant().signum() * cofactors)
+    }
+
+    /// Determinant of the linear part, negative if the transform mirrors.
+    pub fn determinant(self) -> f64 {
+        let m = self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+}
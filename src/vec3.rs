@@ -93,6 +93,15 @@ impl Vec3 {
         }
     }
 
+    /// Cosine weighted direction on the hemisphere around +z.
+    pub fn random_cosine_direction() -> Vec3 {
+        let r1 = random_f64();
+        let r2 = random_f64();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let r = r2.sqrt();
+        Vec3::new(phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt())
+    }
+
     pub fn random_in_unit_disk() -> Vec3 {
         loop {
             let p = Vec3::new(
@@ -225,6 +234,30 @@ impl ops::AddAssign for Vec3 {
     }
 }
 
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
+impl ops::IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, axis: usize) -> &mut f64 {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;